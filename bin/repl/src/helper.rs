@@ -0,0 +1,202 @@
+use crate::instructions::{completion, find_instruction, INSTRUCTIONS};
+use colored::*;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+
+pub const COMMANDS: &[&str] = &[
+    "stack", "res", "undo", "program", "help", ":yul", ":masm", "vars", "mem", "advice", "cycles",
+    "save", "load", "expect",
+];
+
+//Completes, hints and highlights Miden assembly at the prompt. It knows the instructions, the
+//standard library procs and whatever procs the program can use, which the REPL updates as they
+//change. In Yul mode it only completes commands.
+#[derive(Default)]
+pub struct ReplHelper {
+    procs: Vec<String>,
+    yul: bool,
+}
+
+impl ReplHelper {
+    pub fn update(&mut self, procs: Vec<String>, yul: bool) {
+        self.procs = procs;
+        self.yul = yul;
+    }
+
+    //Everything a word at the prompt can complete to
+    fn candidates(&self, first_word: bool) -> Vec<String> {
+        let mut candidates = vec![];
+        if first_word {
+            candidates.extend(COMMANDS.iter().map(|command| command.to_string()));
+        }
+        if !self.yul {
+            candidates.extend(INSTRUCTIONS.iter().map(completion));
+            candidates.extend(self.procs.iter().map(|proc| format!("exec.{}", proc)));
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    //Whether a token is an instruction, or runs a proc that the program can use
+    fn is_valid(&self, token: &str) -> bool {
+        match token.strip_prefix("exec.") {
+            Some(proc) if !proc.contains("::") => self.procs.iter().any(|name| name == proc),
+            _ => find_instruction(token).is_some(),
+        }
+    }
+}
+
+//The start of the word that the cursor is at the end of
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .rfind(char::is_whitespace)
+        .map_or(0, |start| start + 1)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+        let matches = self
+            .candidates(start == 0)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+//Either the rest of the only word that the input can complete to, or the stack effect of a
+//complete instruction, which is only shown
+pub struct ReplHint {
+    display: String,
+    completion: Option<String>,
+}
+
+impl Hint for ReplHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn completion(&self) -> Option<&str> {
+        self.completion.as_deref()
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = ReplHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ReplHint> {
+        if pos < line.len() || self.yul {
+            return None;
+        }
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return None;
+        }
+        if let Some(instruction) = find_instruction(word).filter(|_| self.is_valid(word)) {
+            return Some(ReplHint {
+                display: match instruction.effect {
+                    "" => format!("  # {}", instruction.description),
+                    effect => format!("  # {}", effect),
+                },
+                completion: None,
+            });
+        }
+        let candidates = self
+            .candidates(start == 0)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect::<Vec<_>>();
+        match candidates.as_slice() {
+            [candidate] => {
+                let rest = candidate[word.len()..].to_string();
+                Some(ReplHint {
+                    display: rest.clone(),
+                    completion: Some(rest),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Highlighter for ReplHelper {
+    //Marks the words that aren't instructions in red, unless the line is a command
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let first_word = line.split_whitespace().next().unwrap_or("");
+        if self.yul || COMMANDS.contains(&first_word) || line.contains('#') {
+            return Cow::Borrowed(line);
+        }
+        let mut highlighted = String::with_capacity(line.len());
+        let mut rest = line;
+        while !rest.is_empty() {
+            let space = rest.len() - rest.trim_start().len();
+            highlighted.push_str(&rest[..space]);
+            rest = &rest[space..];
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let token = &rest[..end];
+            if self.is_valid(token) {
+                highlighted.push_str(token);
+            } else {
+                highlighted.push_str(&token.red().to_string());
+            }
+            rest = &rest[end..];
+        }
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dimmed().to_string())
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+//Prints the stack effect and description of an instruction, or where a proc comes from, for
+//help <instruction>
+pub fn print_instruction_help(token: &str, procs: &[String]) -> Result<(), String> {
+    let token = token.trim();
+    let proc = token.strip_prefix("exec.").unwrap_or(token);
+    if procs.iter().any(|name| name == proc) {
+        println!(
+            "exec.{}: a proc from the functions file or src/miden_asm",
+            proc
+        );
+        return Ok(());
+    }
+    let instruction = find_instruction(token)
+        .ok_or_else(|| format!("{} isn't an instruction or a proc", token))?;
+    if instruction.effect.is_empty() {
+        println!("{}: {}", instruction.name, instruction.description);
+    } else {
+        println!(
+            "{}: {}\n  {}",
+            instruction.name, instruction.effect, instruction.description
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,619 @@
+//The Miden instructions and standard library procs that the REPL completes and documents. Stack
+//effects list the top of the stack first, and leave out the elements an instruction doesn't touch.
+//Instructions whose name ends in a letter like .a or .n take that value as an immediate.
+pub struct Instruction {
+    pub name: &'static str,
+    pub effect: &'static str,
+    pub description: &'static str,
+}
+
+const fn instruction(
+    name: &'static str,
+    effect: &'static str,
+    description: &'static str,
+) -> Instruction {
+    Instruction {
+        name,
+        effect,
+        description,
+    }
+}
+
+pub const INSTRUCTIONS: &[Instruction] = &[
+    //field operations
+    instruction("add", "[b, a] -> [a + b]", "adds the top two elements"),
+    instruction("add.b", "[a] -> [a + b]", "adds b to the top element"),
+    instruction(
+        "sub",
+        "[b, a] -> [a - b]",
+        "subtracts the top element from the one under it",
+    ),
+    instruction(
+        "sub.b",
+        "[a] -> [a - b]",
+        "subtracts b from the top element",
+    ),
+    instruction(
+        "mul",
+        "[b, a] -> [a * b]",
+        "multiplies the top two elements",
+    ),
+    instruction("mul.b", "[a] -> [a * b]", "multiplies the top element by b"),
+    instruction(
+        "div",
+        "[b, a] -> [a / b]",
+        "divides in the field, failing if b is 0",
+    ),
+    instruction(
+        "div.b",
+        "[a] -> [a / b]",
+        "divides the top element by b in the field",
+    ),
+    instruction("neg", "[a] -> [-a]", "negates the top element in the field"),
+    instruction(
+        "inv",
+        "[a] -> [1 / a]",
+        "inverts the top element, failing if it's 0",
+    ),
+    instruction(
+        "pow2",
+        "[a] -> [2^a]",
+        "raises 2 to the top element, which must be under 64",
+    ),
+    instruction("exp", "[b, a] -> [a^b]", "raises a to the power of b"),
+    instruction("not", "[a] -> [!a]", "negates a binary value"),
+    instruction("and", "[b, a] -> [a && b]", "ands two binary values"),
+    instruction("or", "[b, a] -> [a || b]", "ors two binary values"),
+    instruction("xor", "[b, a] -> [a ^ b]", "xors two binary values"),
+    instruction(
+        "eq",
+        "[b, a] -> [a == b]",
+        "pushes 1 if the top two elements are equal",
+    ),
+    instruction(
+        "eq.b",
+        "[a] -> [a == b]",
+        "pushes 1 if the top element is b",
+    ),
+    instruction(
+        "neq",
+        "[b, a] -> [a != b]",
+        "pushes 1 if the top two elements differ",
+    ),
+    instruction(
+        "neq.b",
+        "[a] -> [a != b]",
+        "pushes 1 if the top element isn't b",
+    ),
+    instruction("lt", "[b, a] -> [a < b]", "compares the top two elements"),
+    instruction("lte", "[b, a] -> [a <= b]", "compares the top two elements"),
+    instruction("gt", "[b, a] -> [a > b]", "compares the top two elements"),
+    instruction("gte", "[b, a] -> [a >= b]", "compares the top two elements"),
+    instruction(
+        "eqw",
+        "[B, A] -> [A == B, B, A]",
+        "compares the top two words, keeping them",
+    ),
+    instruction("assert", "[a] -> []", "fails unless the top element is 1"),
+    instruction("assertz", "[a] -> []", "fails unless the top element is 0"),
+    instruction(
+        "assert_eq",
+        "[b, a] -> []",
+        "fails unless the top two elements are equal",
+    ),
+    //u32 operations
+    instruction(
+        "u32test",
+        "[a] -> [a < 2^32, a]",
+        "checks whether the top element is a u32",
+    ),
+    instruction(
+        "u32assert",
+        "[a] -> [a]",
+        "fails unless the top element is a u32",
+    ),
+    instruction(
+        "u32cast",
+        "[a] -> [a mod 2^32]",
+        "keeps the low 32 bits of the top element",
+    ),
+    instruction(
+        "u32split",
+        "[a] -> [hi, lo]",
+        "splits the top element into two u32s",
+    ),
+    instruction(
+        "u32checked_add",
+        "[b, a] -> [a + b]",
+        "adds u32s, failing on overflow",
+    ),
+    instruction(
+        "u32wrapping_add",
+        "[b, a] -> [(a + b) mod 2^32]",
+        "adds u32s, wrapping",
+    ),
+    instruction(
+        "u32overflowing_add",
+        "[b, a] -> [overflow, (a + b) mod 2^32]",
+        "adds u32s with the carry",
+    ),
+    instruction(
+        "u32checked_sub",
+        "[b, a] -> [a - b]",
+        "subtracts u32s, failing on underflow",
+    ),
+    instruction(
+        "u32wrapping_sub",
+        "[b, a] -> [(a - b) mod 2^32]",
+        "subtracts u32s, wrapping",
+    ),
+    instruction(
+        "u32overflowing_sub",
+        "[b, a] -> [underflow, (a - b) mod 2^32]",
+        "subtracts u32s with the borrow",
+    ),
+    instruction(
+        "u32checked_mul",
+        "[b, a] -> [a * b]",
+        "multiplies u32s, failing on overflow",
+    ),
+    instruction(
+        "u32wrapping_mul",
+        "[b, a] -> [(a * b) mod 2^32]",
+        "multiplies u32s, wrapping",
+    ),
+    instruction(
+        "u32overflowing_mul",
+        "[b, a] -> [hi, lo]",
+        "multiplies u32s into a 64 bit result",
+    ),
+    instruction("u32checked_div", "[b, a] -> [a / b]", "divides u32s"),
+    instruction(
+        "u32unchecked_div",
+        "[b, a] -> [a / b]",
+        "divides values assumed to be u32s",
+    ),
+    instruction(
+        "u32checked_mod",
+        "[b, a] -> [a mod b]",
+        "takes the remainder of u32s",
+    ),
+    instruction(
+        "u32unchecked_mod",
+        "[b, a] -> [a mod b]",
+        "takes the remainder of values assumed to be u32s",
+    ),
+    instruction(
+        "u32checked_divmod",
+        "[b, a] -> [a mod b, a / b]",
+        "divides u32s with the remainder",
+    ),
+    instruction("u32checked_and", "[b, a] -> [a & b]", "ands u32s bitwise"),
+    instruction("u32checked_or", "[b, a] -> [a | b]", "ors u32s bitwise"),
+    instruction("u32checked_xor", "[b, a] -> [a ^ b]", "xors u32s bitwise"),
+    instruction("u32checked_not", "[a] -> [!a]", "flips the bits of a u32"),
+    instruction("u32checked_shl", "[b, a] -> [a << b]", "shifts a u32 left"),
+    instruction(
+        "u32unchecked_shl",
+        "[b, a] -> [a << b]",
+        "shifts a value assumed to be a u32 left",
+    ),
+    instruction("u32checked_shr", "[b, a] -> [a >> b]", "shifts a u32 right"),
+    instruction(
+        "u32unchecked_shr",
+        "[b, a] -> [a >> b]",
+        "shifts a value assumed to be a u32 right",
+    ),
+    instruction(
+        "u32checked_rotl",
+        "[b, a] -> [a rotl b]",
+        "rotates a u32 left",
+    ),
+    instruction(
+        "u32checked_rotr",
+        "[b, a] -> [a rotr b]",
+        "rotates a u32 right",
+    ),
+    instruction("u32checked_eq", "[b, a] -> [a == b]", "compares u32s"),
+    instruction("u32checked_neq", "[b, a] -> [a != b]", "compares u32s"),
+    instruction("u32checked_lt", "[b, a] -> [a < b]", "compares u32s"),
+    instruction(
+        "u32unchecked_lt",
+        "[b, a] -> [a < b]",
+        "compares values assumed to be u32s",
+    ),
+    instruction("u32checked_lte", "[b, a] -> [a <= b]", "compares u32s"),
+    instruction("u32checked_gt", "[b, a] -> [a > b]", "compares u32s"),
+    instruction(
+        "u32unchecked_gt",
+        "[b, a] -> [a > b]",
+        "compares values assumed to be u32s",
+    ),
+    instruction("u32checked_gte", "[b, a] -> [a >= b]", "compares u32s"),
+    instruction(
+        "u32checked_min",
+        "[b, a] -> [min(a, b)]",
+        "keeps the smaller u32",
+    ),
+    instruction(
+        "u32checked_max",
+        "[b, a] -> [max(a, b)]",
+        "keeps the larger u32",
+    ),
+    //stack manipulation
+    instruction("drop", "[a] -> []", "removes the top element"),
+    instruction("dropw", "[A] -> []", "removes the top word"),
+    instruction("padw", "[] -> [0, 0, 0, 0]", "pushes a word of zeros"),
+    instruction("dup", "[a] -> [a, a]", "copies the top element"),
+    instruction(
+        "dup.n",
+        "[..., a] -> [a, ..., a]",
+        "copies the element at depth n, up to 15",
+    ),
+    instruction("dupw", "[A] -> [A, A]", "copies the top word"),
+    instruction(
+        "dupw.n",
+        "[..., A] -> [A, ..., A]",
+        "copies the word at depth n, up to 3",
+    ),
+    instruction("swap", "[b, a] -> [a, b]", "swaps the top two elements"),
+    instruction(
+        "swap.n",
+        "[b, ..., a] -> [a, ..., b]",
+        "swaps the top element with the one at depth n",
+    ),
+    instruction("swapw", "[B, A] -> [A, B]", "swaps the top two words"),
+    instruction(
+        "swapw.n",
+        "[B, ..., A] -> [A, ..., B]",
+        "swaps the top word with the one at depth n",
+    ),
+    instruction(
+        "swapdw",
+        "[D, C, B, A] -> [B, A, D, C]",
+        "swaps the top two pairs of words",
+    ),
+    instruction(
+        "movup.n",
+        "[..., a] -> [a, ...]",
+        "moves the element at depth n to the top",
+    ),
+    instruction(
+        "movupw.n",
+        "[..., A] -> [A, ...]",
+        "moves the word at depth n to the top",
+    ),
+    instruction(
+        "movdn.n",
+        "[a, ...] -> [..., a]",
+        "moves the top element to depth n",
+    ),
+    instruction(
+        "movdnw.n",
+        "[A, ...] -> [..., A]",
+        "moves the top word to depth n",
+    ),
+    instruction(
+        "cswap",
+        "[c, b, a] -> [b, a] or [a, b]",
+        "swaps a and b if c is 1",
+    ),
+    instruction(
+        "cswapw",
+        "[c, B, A] -> [B, A] or [A, B]",
+        "swaps the words A and B if c is 1",
+    ),
+    instruction(
+        "cdrop",
+        "[c, b, a] -> [b] or [a]",
+        "keeps b if c is 1, and a otherwise",
+    ),
+    instruction(
+        "cdropw",
+        "[c, B, A] -> [B] or [A]",
+        "keeps B if c is 1, and A otherwise",
+    ),
+    //input and output
+    instruction(
+        "push.a",
+        "[] -> [a]",
+        "pushes a, or several values separated by dots",
+    ),
+    instruction("pushw.a", "[] -> [A]", "pushes a word"),
+    instruction(
+        "push.mem",
+        "[addr] -> [a]",
+        "pushes the first element of the word at addr",
+    ),
+    instruction(
+        "push.mem.addr",
+        "[] -> [a]",
+        "pushes the first element of the word at addr",
+    ),
+    instruction("pushw.mem", "[addr] -> [A]", "pushes the word at addr"),
+    instruction("pushw.mem.addr", "[] -> [A]", "pushes the word at addr"),
+    instruction(
+        "pop.mem",
+        "[addr, a] -> []",
+        "stores a in the first element of the word at addr",
+    ),
+    instruction(
+        "pop.mem.addr",
+        "[a] -> []",
+        "stores a in the first element of the word at addr",
+    ),
+    instruction("popw.mem", "[addr, A] -> []", "stores the word A at addr"),
+    instruction("popw.mem.addr", "[A] -> []", "stores the word A at addr"),
+    instruction(
+        "loadw.mem",
+        "[addr, B] -> [A]",
+        "replaces the top word with the word at addr",
+    ),
+    instruction(
+        "loadw.mem.addr",
+        "[B] -> [A]",
+        "replaces the top word with the word at addr",
+    ),
+    instruction(
+        "storew.mem",
+        "[addr, A] -> [A]",
+        "stores the top word at addr, keeping it",
+    ),
+    instruction(
+        "storew.mem.addr",
+        "[A] -> [A]",
+        "stores the top word at addr, keeping it",
+    ),
+    instruction("push.local.i", "[] -> [a]", "pushes local i of the proc"),
+    instruction(
+        "pushw.local.i",
+        "[] -> [A]",
+        "pushes the word of local i of the proc",
+    ),
+    instruction(
+        "pop.local.i",
+        "[a] -> []",
+        "stores a in local i of the proc",
+    ),
+    instruction(
+        "popw.local.i",
+        "[A] -> []",
+        "stores the word A in local i of the proc",
+    ),
+    instruction(
+        "push.env.sdepth",
+        "[] -> [depth]",
+        "pushes the depth of the stack",
+    ),
+    instruction(
+        "adv_push.n",
+        "[] -> [a_n, ..., a_1]",
+        "moves n values from the advice stack",
+    ),
+    instruction(
+        "adv_loadw",
+        "[B] -> [A]",
+        "replaces the top word with a word from the advice stack",
+    ),
+    //cryptographic operations
+    instruction(
+        "rphash",
+        "[B, A] -> [hash(A, B)]",
+        "hashes two words with Rescue Prime",
+    ),
+    instruction(
+        "rpperm",
+        "[C, B, A] -> [F, E, D]",
+        "applies the Rescue Prime permutation",
+    ),
+    instruction(
+        "mtree_get",
+        "[d, i, R] -> [V, R]",
+        "gets the node at depth d and index i of the tree with root R",
+    ),
+    //control flow
+    instruction(
+        "if.true",
+        "[c] -> []",
+        "runs the block if c is 1, and the else block otherwise",
+    ),
+    instruction(
+        "else",
+        "",
+        "starts the block that runs when the condition of if.true is 0",
+    ),
+    instruction("end", "", "ends a block"),
+    instruction(
+        "while.true",
+        "[c] -> []",
+        "runs the block while the top element is 1",
+    ),
+    instruction("repeat.n", "", "runs the block n times"),
+    instruction("exec.name", "", "runs a proc"),
+    instruction(
+        "call.name",
+        "",
+        "runs a proc in a new context, with its own memory",
+    ),
+    instruction("proc.name", "", "starts a proc, which ends with end"),
+    instruction(
+        "proc.name.n",
+        "",
+        "starts a proc with n locals, which ends with end",
+    ),
+    //the u256 standard library, which keeps a u256 in 8 u32 limbs with the most significant on top
+    instruction(
+        "exec.u256::add_unsafe",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "adds u256s, wrapping",
+    ),
+    instruction(
+        "exec.u256::sub_unsafe",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "subtracts u256s, wrapping",
+    ),
+    instruction(
+        "exec.u256::mul_unsafe",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "multiplies u256s, wrapping",
+    ),
+    instruction(
+        "exec.u256::and",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "ands u256s bitwise",
+    ),
+    instruction(
+        "exec.u256::or",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "ors u256s bitwise",
+    ),
+    instruction(
+        "exec.u256::xor",
+        "[b7..b0, a7..a0] -> [c7..c0]",
+        "xors u256s bitwise",
+    ),
+    instruction(
+        "exec.u256::iszero_unsafe",
+        "[a7..a0] -> [a == 0]",
+        "checks whether a u256 is zero",
+    ),
+    instruction(
+        "exec.u256::eq_unsafe",
+        "[b7..b0, a7..a0] -> [a == b]",
+        "compares u256s",
+    ),
+    //the u64 standard library, which keeps a u64 in 2 u32 limbs with the most significant on top
+    instruction(
+        "exec.u64::wrapping_add",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "adds u64s, wrapping",
+    ),
+    instruction(
+        "exec.u64::wrapping_sub",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "subtracts u64s, wrapping",
+    ),
+    instruction(
+        "exec.u64::wrapping_mul",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "multiplies u64s, wrapping",
+    ),
+    instruction(
+        "exec.u64::unchecked_div",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "divides u64s",
+    ),
+    instruction(
+        "exec.u64::checked_and",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "ands u64s bitwise",
+    ),
+    instruction(
+        "exec.u64::checked_or",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "ors u64s bitwise",
+    ),
+    instruction(
+        "exec.u64::checked_xor",
+        "[b1, b0, a1, a0] -> [c1, c0]",
+        "xors u64s bitwise",
+    ),
+    instruction(
+        "exec.u64::unchecked_lt",
+        "[b1, b0, a1, a0] -> [a < b]",
+        "compares u64s",
+    ),
+    instruction(
+        "exec.u64::unchecked_gt",
+        "[b1, b0, a1, a0] -> [a > b]",
+        "compares u64s",
+    ),
+    instruction(
+        "exec.u64::unchecked_eq",
+        "[b1, b0, a1, a0] -> [a == b]",
+        "compares u64s",
+    ),
+    instruction(
+        "exec.u64::unchecked_eqz",
+        "[a1, a0] -> [a == 0]",
+        "checks whether a u64 is zero",
+    ),
+];
+
+//Finds the instruction that an input token is an instance of. An instruction with immediates
+//matches any token with the same words and values in place of the immediates, and the most
+//specific match wins, so that push.mem.4 is push.mem.addr rather than push.a.
+pub fn find_instruction(token: &str) -> Option<&'static Instruction> {
+    let parts = token.split('.').collect::<Vec<_>>();
+    INSTRUCTIONS
+        .iter()
+        .filter(|instruction| {
+            let names = instruction.name.split('.').collect::<Vec<_>>();
+            //push takes up to four values
+            let variadic = instruction.name == "push.a" && (2..=5).contains(&parts.len());
+            (names.len() == parts.len() || variadic)
+                && parts.iter().enumerate().all(|(i, part)| {
+                    let name = names.get(i).unwrap_or(&"a");
+                    name == part || (i > 0 && matches_immediate(name, part))
+                })
+        })
+        .max_by_key(|instruction| {
+            instruction
+                .name
+                .split('.')
+                .filter(|name| !is_immediate(name))
+                .count()
+        })
+}
+
+//The immediates in instruction names are the placeholders after the first word
+fn is_immediate(name: &str) -> bool {
+    matches!(name, "a" | "b" | "n" | "i" | "addr" | "name")
+}
+
+fn matches_immediate(name: &str, part: &str) -> bool {
+    match name {
+        //procs from a library have to be listed to match
+        "name" => !part.is_empty() && !part.contains("::"),
+        _ if is_immediate(name) => part.parse::<u64>().is_ok() || part.starts_with("0x"),
+        _ => false,
+    }
+}
+
+//The name of an instruction as it's completed, which stops before the first immediate
+pub fn completion(instruction: &Instruction) -> String {
+    let mut words = vec![];
+    for (i, word) in instruction.name.split('.').enumerate() {
+        if i > 0 && is_immediate(word) {
+            words.push("");
+            break;
+        }
+        words.push(word);
+    }
+    words.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_most_specific_instruction() {
+        assert_eq!(find_instruction("push.5").unwrap().name, "push.a");
+        assert_eq!(
+            find_instruction("push.mem.5").unwrap().name,
+            "push.mem.addr"
+        );
+        assert_eq!(find_instruction("push.mem").unwrap().name, "push.mem");
+        assert_eq!(find_instruction("add").unwrap().name, "add");
+        assert_eq!(find_instruction("if.true").unwrap().name, "if.true");
+        assert!(find_instruction("if.false").is_none());
+        assert!(find_instruction("pish.1").is_none());
+        assert_eq!(find_instruction("push.1.2.3").unwrap().name, "push.a");
+        assert_eq!(
+            find_instruction("exec.u256::add_unsafe").unwrap().name,
+            "exec.u256::add_unsafe"
+        );
+        assert_eq!(completion(find_instruction("movup.3").unwrap()), "movup.");
+    }
+}
@@ -1,6 +1,8 @@
 extern crate quickcheck_macros;
 
 extern crate insta;
+mod helper;
+mod instructions;
 mod repl;
 use crate::repl::*;
 
@@ -13,10 +15,21 @@ struct Opts {
     functions_file: Option<String>,
     #[clap(short, long)]
     stack: Option<String>,
+    //runs the commands in a file instead of prompting for them, and fails on the first error
+    #[clap(long)]
+    script: Option<String>,
 }
 
 fn main() {
     let opts: Opts = Opts::parse();
 
-    start_repl(opts.functions_file, opts.stack);
+    match opts.script {
+        Some(script) => {
+            if let Err(error) = run_script(&script, opts.functions_file, opts.stack) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        None => start_repl(opts.functions_file, opts.stack),
+    }
 }
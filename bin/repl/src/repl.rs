@@ -1,9 +1,19 @@
+use crate::helper::{print_instruction_help, ReplHelper};
 use colored::*;
-use papyrus::executor::execute;
+use miden_core::{Felt, StarkField};
+use papyrus::executor::{
+    assemble, decode_stack, execute, ExecutionConfig, ExecutionResult, MemorySnapshot,
+};
+use papyrus::miden_generator::{CompileOptions, VariableLocation, YulSession};
+use papyrus::parser::parse_yul_syntax;
+use papyrus::type_inference::infer_types;
+use papyrus::types::Expr;
 use papyrus::utils::load_all_procs;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::collections::HashSet;
 use std::fs;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 
 //The Scribe Read–eval–print loop or repl for short is a Miden shell that allows for quick and easy debugging with Miden assembly!
@@ -21,6 +31,19 @@ use std::path::Path;
 //  push.3
 //  Is the same as
 //  push.1 push.2 push.3
+//  An input that starts a block with if.true, while.true or repeat.n continues on the next line until the block's end, so blocks
+//  can be typed over several lines. Procs defined with proc.name can be used by the inputs after them, and are put above begin.
+//  Ex.
+//  proc.double
+//      dup add
+//  end
+//  push.3 exec.double
+//  dup eq.6
+//  if.true
+//      push.1
+//  else
+//      push.2
+//  end
 
 //`stack`
 //  Use the `stack` command to check the state of the stack at anytime. When you start the repl, the stack will be empty.
@@ -67,88 +90,587 @@ use std::path::Path;
 //      add
 //  end
 
+//`:yul` and `:masm`
+//  Use `:yul` to switch to Yul mode, where each input is a Yul statement that is parsed, type checked and transpiled on top of
+//  the statements before it, so variables and functions carry over from one input to the next. Inputs with more `{` than `}`
+//  continue on the next line. Use `:masm` to switch back to Miden assembly, which keeps its own program.
+//  Ex.
+//  :yul
+//  let x := 5
+//  function double(a) -> b { b := mul(a, 2) }
+//  let y := double(x)
+
+//`vars`
+//  In Yul mode, use the `vars` command to see the value of every variable, whether it's on the stack or in memory.
+//  Ex.
+//  vars
+//  >> x: u256 = 5
+//  >> y: u256 = 10
+
+//`mem`
+//  Use `mem <addr>` or `mem <addr>..<addr>` to see memory after the program runs, one word per address. The end of a range
+//  isn't included, and a range can span at most 1024 addresses. Add `u32` to see only the first element of each word,
+//  where pop.mem stores a value, `u256` to read each pair of addresses as a u256 like the transpiler stores them, or `hex`
+//  to see the words in hex.
+//  Ex.
+//  push.7 pop.mem.3
+//  mem 3
+//  >> 3: 7 0 0 0
+//  mem 0..4 u32
+//  >> 0: 0
+//  >> 1: 0
+//  >> 2: 0
+//  >> 3: 7
+
+//`advice`
+//  Use `advice push <values>` to add values to the advice stack, and `advice map <key> <values>` to add an entry to the advice
+//  map, with the key in hex. The program reruns with the new advice. `advice` shows the advice and `advice clear` removes it.
+//  Ex.
+//  advice push 1 2 3
+//  adv_push.2
+//  stack
+//  >> 2 1 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+
+//`cycles`
+//  Use the `cycles` command to see how many cycles the program takes to run.
+
+//Each input runs on its own, starting from the stack, memory and advice that the program before it left. The whole program runs
+//again after `undo`, `load`, `advice` and mode switches, or when that state can't be carried over.
+
+//`save` and `load`
+//  Use `save <file>` to write the program, along with the procs it can use, to a .masm file, and `load <file>` to replay a
+//  program saved that way. Yul sessions are saved as the Miden assembly they were transpiled to.
+
+//`expect`
+//  Use `expect <values>` to check that the stack starts with the given values. It's meant for scripts, which are files of
+//  commands run with `repl --script <file>`. A script stops at the first error or failed expect and exits with an error.
+//  Ex.
+//  push.1 push.2
+//  expect 2 1
+
 //`help`
-// Use the `help` command at any time to see a list of available commands.
+// Use the `help` command at any time to see a list of available commands, or `help <instruction>` to see the stack effect of an
+// instruction, with the top of the stack first, and a short description of it.
+//  Ex.
+//  help u32checked_add
+//  >> u32checked_add: [b, a] -> [a + b]
+//  >>   adds u32s, failing on overflow
 
-pub fn start_repl(functions_file: Option<String>, stack_string: Option<String>) {
-    let mut program_lines: Vec<String> = Vec::new();
-    let mut functions_miden = "".to_string();
-    if let Some(functions_file) = functions_file {
-        let path = Path::new(&functions_file);
-        functions_miden = fs::read_to_string(path).expect("Something went wrong reading the file");
-    }
-    if let Some(stack_string) = stack_string {
-        program_lines.push(
-            stack_string
-                .split(',')
-                .map(|s| format!("push.{}", s))
-                .collect::<Vec<_>>()
-                .into_iter()
-                .rev()
-                .collect::<Vec<_>>()
-                .join(" "),
-        );
+//Tab completes commands, instructions, the procs of the u256 and u64 standard libraries and the procs from src/miden_asm and the
+//functions file. Once an instruction is complete, its stack effect is hinted after it, and words that aren't instructions are red.
+
+//The statements entered in Yul mode. Every input keeps the session from before it, so that it can
+//be undone.
+struct YulState {
+    statements: Vec<Expr>,
+    sessions: Vec<(usize, YulSession)>,
+}
+
+impl YulState {
+    fn new() -> Self {
+        YulState {
+            statements: vec![],
+            sessions: vec![(0, YulSession::new(CompileOptions::default()))],
+        }
     }
-    let mut rl = Editor::<()>::new();
-    loop {
-        let program = format!(
-            "begin\n{}\nend",
-            program_lines
-                .iter()
-                .map(|l| format!("    {}", l))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
-        let program_with_procs = format!(
-            "{}\n{}\n{}",
+
+    fn session(&self) -> &YulSession {
+        &self.sessions.last().unwrap().1
+    }
+
+    //Parses, type checks and transpiles a Yul input on top of the statements before it. The types
+    //of the new statements depend on the earlier ones, so they're inferred together.
+    fn add(&mut self, input: &str) -> Result<(), String> {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let parsed = parse_yul_syntax(input);
+            let statements = [self.statements.clone(), parsed.clone()].concat();
+            let typed = infer_types(&statements);
+            let mut session = self.session().clone();
+            let warnings = session.add_statements(&typed[self.statements.len()..]);
+            (parsed, session, warnings)
+        }));
+        panic::set_hook(default_hook);
+        let (parsed, session, warnings) = result.map_err(|payload| {
+            payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "the transpiler panicked".to_string())
+        })?;
+        for warning in warnings {
+            println!("{} {}", "warning:".yellow(), warning);
+        }
+        self.sessions.push((self.statements.len(), session));
+        self.statements.extend(parsed);
+        Ok(())
+    }
+
+    //Returns false if there's nothing to undo
+    fn undo(&mut self) -> bool {
+        if self.sessions.len() == 1 {
+            return false;
+        }
+        let (statement_count, _) = self.sessions.pop().unwrap();
+        self.statements.truncate(statement_count);
+        true
+    }
+}
+
+//The standard library modules that Miden assembly entered in the REPL can use
+const LIBRARIES: &str = "use.std::math::u256\nuse.std::math::u64";
+
+//The state of a REPL session, shared by the prompt and scripts
+struct Repl {
+    program_lines: Vec<String>,
+    //the procs entered at the prompt, each with how many lines the program had when it was
+    //defined, so that undo takes inputs back out in order
+    defined_procs: Vec<(usize, String)>,
+    functions_miden: String,
+    yul: Option<YulState>,
+    advice_stack: Vec<u64>,
+    advice_map: Vec<([u8; 32], Vec<u64>)>,
+    //the result of running the program as it is, or None if it's empty or failed
+    last_result: Option<ExecutionResult>,
+}
+
+impl Repl {
+    fn new(functions_file: Option<String>, stack_string: Option<String>) -> Self {
+        let mut program_lines: Vec<String> = Vec::new();
+        let mut functions_miden = "".to_string();
+        if let Some(functions_file) = functions_file {
+            let path = Path::new(&functions_file);
+            functions_miden =
+                fs::read_to_string(path).expect("Something went wrong reading the file");
+        }
+        if let Some(stack_string) = stack_string {
+            program_lines.push(
+                stack_string
+                    .split(',')
+                    .map(|s| format!("push.{}", s))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+        Repl {
+            program_lines,
+            defined_procs: vec![],
             functions_miden,
-            load_all_procs(),
-            program.clone()
+            yul: None,
+            advice_stack: vec![],
+            advice_map: vec![],
+            last_result: None,
+        }
+    }
+
+    //The program as it was entered, without the procs that are loaded for it
+    fn program(&self) -> String {
+        match &self.yul {
+            Some(yul) => yul.session().program(),
+            None => format!(
+                "{}begin\n{}\nend",
+                self.defined_procs(),
+                self.program_lines
+                    .iter()
+                    .flat_map(|input| input.lines())
+                    .map(|l| format!("    {}", l))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        }
+    }
+
+    fn program_with_procs(&self) -> String {
+        match &self.yul {
+            Some(_) => self.program(),
+            None => format!("{}\n{}", self.procs_header(), self.program()),
+        }
+    }
+
+    //What comes before begin in Miden assembly mode
+    fn procs_header(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            LIBRARIES,
+            self.functions_miden,
+            load_all_procs()
+        )
+    }
+
+    fn defined_procs(&self) -> String {
+        self.defined_procs
+            .iter()
+            .map(|(_, proc)| format!("{}\n", proc))
+            .collect()
+    }
+
+    //The names of the procs that Miden assembly can exec, other than the standard library ones
+    fn procs(&self) -> Vec<String> {
+        let procs = match &self.yul {
+            Some(_) => String::new(),
+            None => self.program_with_procs(),
+        };
+        procs
+            .split_whitespace()
+            .filter_map(|token| token.strip_prefix("proc."))
+            .filter_map(|name| name.split('.').next())
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.yul {
+            Some(yul) => yul.statements.is_empty(),
+            None => self.program_lines.is_empty(),
+        }
+    }
+
+    //The advice map and whatever is left of the advice stack after the first advice_used values
+    fn advice_config(&self, advice_used: usize) -> ExecutionConfig {
+        let advice_stack = self.advice_stack.get(advice_used..).unwrap_or_default();
+        self.advice_map.iter().fold(
+            ExecutionConfig::default().with_advice_stack(advice_stack.to_vec()),
+            |config, (key, values)| config.with_advice_map_entry(*key, values.clone()),
+        )
+    }
+
+    //Runs the whole program with the advice set so far
+    fn run(&mut self) -> Result<(), String> {
+        self.last_result = None;
+        if self.is_empty() {
+            return Ok(());
+        }
+        let result = execute(self.program_with_procs(), &self.advice_config(0))
+            .map_err(|e| format!("Error running program: {:?}", e))?;
+        self.last_result = Some(result);
+        Ok(())
+    }
+
+    //The program of only the last input, which runs on top of the state that the program before
+    //it left
+    fn input_program(&self, previous: Option<&YulSession>) -> Option<String> {
+        match (&self.yul, previous) {
+            (Some(yul), Some(previous)) => yul.session().program_since(previous),
+            (None, None) => Some(format!(
+                "{}\n{}begin\n    {}\nend",
+                self.procs_header(),
+                self.defined_procs(),
+                self.program_lines.last()?
+            )),
+            _ => None,
+        }
+    }
+
+    //Runs the program after an input, and takes the input back out if the program fails. Only the
+    //input runs, starting from the stack, memory and advice that the program before it left. The
+    //whole program runs again when that state can't be carried over, which is when the stack is
+    //deeper than the 16 elements a program can start with, or when it isn't known how much advice
+    //the program before the input read.
+    fn run_input(
+        &mut self,
+        previous_program: &str,
+        previous_session: Option<&YulSession>,
+    ) -> Result<(), String> {
+        let advice_used = advice_reads(previous_program);
+        let input_program = self.input_program(previous_session);
+        let last_result = match (&self.last_result, advice_used, input_program) {
+            (Some(last_result), Some(advice_used), Some(input_program))
+                if last_result.stack.len() <= 16 =>
+            {
+                Some((last_result, advice_used, input_program))
+            }
+            _ => None,
+        };
+        let (last_result, advice_used, input_program) = match last_result {
+            Some(continued) => continued,
+            None => {
+                let result = self.run();
+                if result.is_err() {
+                    self.undo();
+                    //the program ran before the input, so it runs again
+                    let _ = self.run();
+                }
+                return result;
+            }
+        };
+
+        //the stack inputs are pushed in order, so the top of the stack goes last
+        let config = last_result
+            .stack
+            .iter()
+            .rev()
+            .fold(self.advice_config(advice_used), |config, value| {
+                config.with_stack_input(*value)
+            });
+        let config = last_result
+            .memory
+            .addresses()
+            .into_iter()
+            .fold(config, |config, address| {
+                config.with_memory_word(address as u32, last_result.memory.read_word(address))
+            });
+        let result = match execute(input_program, &config)
+            .map_err(|e| format!("Error running program: {:?}", e))
+        {
+            Ok(result) => result,
+            Err(error) => {
+                //the state from before the input is still there, so nothing needs to run again
+                self.undo();
+                return Err(error);
+            }
+        };
+        //the cycles of the input alone leave out the stores of the preloaded memory, and the span
+        //that the input's begin block opens and closes, which the program before it already counted
+        let overhead = config.preload_cycles() + 2;
+        let cycles = last_result.cycles + result.cycles.saturating_sub(overhead);
+        self.last_result = Some(ExecutionResult { cycles, ..result });
+        Ok(())
+    }
+
+    //Removes the last input, returning false if there's nothing to undo
+    fn undo(&mut self) -> bool {
+        match &mut self.yul {
+            Some(yul) => yul.undo(),
+            None => match self.defined_procs.last() {
+                Some((lines, _)) if *lines == self.program_lines.len() => {
+                    self.defined_procs.pop().is_some()
+                }
+                _ => self.program_lines.pop().is_some(),
+            },
+        }
+    }
+
+    //Adds a proc entered at the prompt above begin. Defining a proc doesn't change what the program
+    //does, so it only has to assemble.
+    fn define_proc(&mut self, proc: String) -> Result<(), String> {
+        self.defined_procs.push((self.program_lines.len(), proc));
+        let program = format!(
+            "{}\n{}begin\n    push.0 drop\nend",
+            self.procs_header(),
+            self.defined_procs()
         );
-        let result = execute(program_with_procs, vec![]);
-
-        let mut result_string = "".to_string();
-        if !program_lines.is_empty() {
-            match result {
-                Ok(execution_value) => {
-                    let stack = execution_value.last_stack_state();
-                    result_string = format!(
-                        "\n{}",
-                        stack
-                            .iter()
-                            .map(|f| format!("{}", f))
-                            .collect::<Vec<_>>()
-                            .join(" "),
-                    );
+        if let Err(error) = assemble(program) {
+            self.defined_procs.pop();
+            return Err(format!("Error assembling proc: {:?}", error));
+        }
+        Ok(())
+    }
+
+    fn stack_string(&self) -> String {
+        match &self.last_result {
+            Some(result) => format!(
+                "\n{}",
+                result
+                    .stack
+                    .iter()
+                    .map(|f| format!("{}", f))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            None => "".to_string(),
+        }
+    }
+
+    fn last_result(&self) -> Result<&ExecutionResult, String> {
+        self.last_result
+            .as_ref()
+            .ok_or_else(|| "The program hasn't run yet".to_string())
+    }
+
+    //Handles a line of input, either a command or an instruction or statement to add to the
+    //program. Yul blocks that span lines are completed with next_line. Errors are returned, so
+    //that scripts can stop at them.
+    fn handle(
+        &mut self,
+        line: &str,
+        next_line: &mut dyn FnMut() -> Option<String>,
+    ) -> Result<(), String> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "program" => println!("\n{}", self.program()),
+            "help" if !args.trim().is_empty() => {
+                print_instruction_help(args, &self.procs())?;
+            }
+            "help" => {
+                println!("Available commands:");
+                println!();
+                println!("stack: display the stack");
+                println!("undo: remove the last instruction");
+                println!("program: display the program");
+                println!(":yul: switch to Yul statements");
+                println!(":masm: switch to Miden assembly");
+                println!("vars: display the Yul variables");
+                println!("mem <addr>[..<addr>] [u32|u256|hex]: display memory");
+                println!("advice [push <values> | map <key> <values> | clear]: set the advice");
+                println!("cycles: display the cycle count");
+                println!("save <file>: save the program, with its procs, as a .masm file");
+                println!("load <file>: replay a program saved with save");
+                println!("expect <values>: check the top of the stack, for scripts");
+                println!("help <instruction>: describe an instruction");
+            }
+            "undo" => {
+                if !self.undo() {
+                    return Err("Nothing to undo".to_string());
+                }
+                println!("Undoing the last input");
+                self.run()?;
+            }
+            "stack" | "res" => println!("{}", self.stack_string()),
+            ":yul" => {
+                self.yul.get_or_insert_with(YulState::new);
+                self.run()?;
+            }
+            ":masm" => {
+                self.yul = None;
+                self.run()?;
+            }
+            "vars" => match &self.yul {
+                Some(yul) => print_variables(yul.session(), self.last_result()?),
+                None => return Err("vars only works in Yul mode, switch with :yul".to_string()),
+            },
+            "mem" => {
+                for line in memory_lines(&self.last_result()?.memory, args)? {
+                    println!("{}", line);
                 }
-                Err(e) => {
-                    result_string = format!("Error running program: {:?}", e);
-                    println!("{}", result_string);
-                    program_lines.pop();
+            }
+            "advice" => {
+                update_advice(&mut self.advice_stack, &mut self.advice_map, args)?;
+                self.run()?;
+            }
+            "cycles" => println!("{} cycles", self.last_result()?.cycles),
+            "save" => {
+                fs::write(args.trim(), self.program_with_procs())
+                    .map_err(|error| format!("Couldn't save to {}: {}", args.trim(), error))?;
+                println!("Saved the program to {}", args.trim());
+            }
+            "load" => self.load(args.trim())?,
+            "expect" => self.expect(args)?,
+            _ => {
+                let previous_program = self.program_with_procs();
+                let previous_session = self.yul.as_ref().map(|yul| yul.session().clone());
+                match &mut self.yul {
+                    Some(yul) => {
+                        let input = read_until_closed(line.to_string(), yul_depth, next_line);
+                        yul.add(&input)
+                            .map_err(|error| format!("Error transpiling Yul: {}", error))?;
+                    }
+                    None => {
+                        let input = read_until_closed(line.to_string(), masm_depth, next_line);
+                        if input.trim_start().starts_with("proc.") {
+                            return self.define_proc(input);
+                        }
+                        self.program_lines.push(input);
+                    }
                 }
+                self.run_input(&previous_program, previous_session.as_ref())?;
             }
         }
+        Ok(())
+    }
+
+    //Replays a program saved with save. The procs before begin become the functions of the
+    //session, except for the ones that the REPL loads anyway, and each line between begin and end
+    //is added as an input, along with the lines up to the end of any block it starts. If the loaded
+    //program fails, the session goes back to what it was.
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let saved = fs::read_to_string(path)
+            .map_err(|error| format!("Couldn't load {}: {}", path, error))?;
+        let begin = saved
+            .lines()
+            .position(|line| line.trim() == "begin")
+            .ok_or_else(|| format!("{} doesn't have a begin block", path))?;
+        let lines = saved.lines().collect::<Vec<_>>();
+        let procs = lines[..begin].join("\n");
+        let end = lines
+            .iter()
+            .rposition(|line| line.trim() == "end")
+            .filter(|end| *end > begin)
+            .ok_or_else(|| format!("{} doesn't end its begin block", path))?;
+        let functions_miden = procs
+            .replace(&load_all_procs(), "")
+            .replace(LIBRARIES, "")
+            .trim()
+            .to_string();
+        let mut program_lines = vec![];
+        let mut body = lines[begin + 1..end]
+            .iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty());
+        while let Some(line) = body.next() {
+            let input = read_until_closed(line, masm_depth, &mut || body.next());
+            program_lines.push(input);
+        }
+        let previous = (
+            self.yul.take(),
+            std::mem::replace(&mut self.functions_miden, functions_miden),
+            std::mem::take(&mut self.defined_procs),
+            std::mem::replace(&mut self.program_lines, program_lines),
+        );
+        if let Err(error) = self.run() {
+            //the session from before the load runs again
+            (
+                self.yul,
+                self.functions_miden,
+                self.defined_procs,
+                self.program_lines,
+            ) = previous;
+            let _ = self.run();
+            return Err(error);
+        }
+        println!("Loaded {}", path);
+        Ok(())
+    }
+
+    //Checks that the stack starts with the given values
+    fn expect(&self, args: &str) -> Result<(), String> {
+        let expected = args
+            .split_whitespace()
+            .map(|value| value.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "usage: expect <values>".to_string())?;
+        let stack = self
+            .last_result
+            .as_ref()
+            .map(|result| result.stack.clone())
+            .unwrap_or_default();
+        if !stack.starts_with(&expected) {
+            return Err(format!(
+                "Expected the stack to start with {}, but it's{}",
+                args.trim(),
+                self.stack_string()
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn start_repl(functions_file: Option<String>, stack_string: Option<String>) {
+    let mut repl = Repl::new(functions_file, stack_string);
+    if let Err(error) = repl.run() {
+        println!("{}", error);
+    }
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper::default()));
+    loop {
         println!();
-        let readline = rl.readline(&">> ".blue());
+        if let Some(helper) = rl.helper_mut() {
+            helper.update(repl.procs(), repl.yul.is_some());
+        }
+        let prompt = match repl.yul {
+            Some(_) => "yul>> ",
+            None => ">> ",
+        };
+        let readline = rl.readline(&prompt.blue());
         match readline {
             Ok(line) => {
-                if line == "program" {
-                    println!("\n{}", program);
-                } else if line == "help" {
-                    println!("Available commands:");
-                    println!();
-                    println!("stack: display the stack");
-                    println!("undo: remove the last instruction");
-                    println!("program: display the program");
-                } else if line == "undo" {
-                    let last_line = program_lines.pop().unwrap();
-                    println!("Undoing {}", last_line);
-                } else if line == "stack" || line == "res" {
-                    println!("{}", result_string);
-                } else {
-                    rl.add_history_entry(line.clone());
-                    program_lines.push(line.clone());
-                    // println!("{}", line);
+                rl.add_history_entry(line.clone());
+                let mut next_line = || rl.readline(&"... ".blue()).ok();
+                if let Err(error) = repl.handle(&line, &mut next_line) {
+                    println!("{}", error);
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -167,3 +689,545 @@ pub fn start_repl(functions_file: Option<String>, stack_string: Option<String>)
     }
     rl.save_history("history.txt").unwrap();
 }
+
+//Runs each line of a script like it was typed into the REPL, stopping at the first error or
+//failed expect. Empty lines and lines starting with # are skipped.
+pub fn run_script(
+    script_file: &str,
+    functions_file: Option<String>,
+    stack_string: Option<String>,
+) -> Result<(), String> {
+    let script = fs::read_to_string(script_file)
+        .map_err(|error| format!("Couldn't read {}: {}", script_file, error))?;
+    let mut repl = Repl::new(functions_file, stack_string);
+    repl.run()?;
+    let mut lines = script.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut next_line = || lines.next().map(|(_, line)| line.to_string());
+        repl.handle(line, &mut next_line)
+            .map_err(|error| format!("{}:{}: {}", script_file, i + 1, error))?;
+    }
+    Ok(())
+}
+
+//Keeps reading lines while the input has blocks that haven't been closed, so that blocks can span
+//lines
+fn read_until_closed(
+    mut input: String,
+    depth: fn(&str) -> i64,
+    next_line: &mut dyn FnMut() -> Option<String>,
+) -> String {
+    while depth(&input) > 0 {
+        match next_line() {
+            Some(line) => {
+                input.push('\n');
+                input.push_str(&line);
+            }
+            None => break,
+        }
+    }
+    input
+}
+
+//How many more { there are than } in Yul
+fn yul_depth(input: &str) -> i64 {
+    input.matches('{').count() as i64 - input.matches('}').count() as i64
+}
+
+//How many more blocks Miden assembly starts than it ends
+fn masm_depth(input: &str) -> i64 {
+    //comments are between #s
+    input
+        .split('#')
+        .step_by(2)
+        .flat_map(|code| code.split_whitespace())
+        .map(|token| match token.split('.').next().unwrap_or(token) {
+            "if" | "while" | "repeat" | "proc" | "export" | "begin" => 1,
+            "end" => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+//Counts the values a program reads from the advice stack, or returns None if that can't be known
+//without running it, because it reads advice in a block that might not run or might run many
+//times, or in a proc, or with an instruction that adds to the advice itself
+fn advice_reads(program: &str) -> Option<usize> {
+    //comments are between #s
+    let tokens = program
+        .split('#')
+        .step_by(2)
+        .flat_map(|code| code.split_whitespace())
+        .collect::<Vec<_>>();
+    let begin = tokens.iter().position(|token| *token == "begin")?;
+
+    //the procs that read advice, directly or through other procs
+    let mut advice_procs: HashSet<&str> = HashSet::new();
+    let mut proc_name = None;
+    for token in &tokens[..begin] {
+        if let Some(name) = token.strip_prefix("proc.") {
+            proc_name = name.split('.').next();
+        } else if let Some(name) = proc_name {
+            let calls_advice_proc = token
+                .strip_prefix("exec.")
+                .map_or(false, |callee| advice_procs.contains(callee));
+            if token.starts_with("adv") || calls_advice_proc {
+                advice_procs.insert(name);
+            }
+        }
+    }
+
+    let mut reads = 0;
+    let mut depth = 0;
+    for token in &tokens[begin + 1..] {
+        let (instruction, argument) = token.split_once('.').unwrap_or((token, ""));
+        match instruction {
+            "if" | "while" | "repeat" => depth += 1,
+            "end" => depth -= 1,
+            "exec" | "call" if advice_procs.contains(argument) => return None,
+            "adv_push" | "adv_loadw" | "adv_pipe" if depth > 0 => return None,
+            "adv_push" => reads += argument.parse::<usize>().ok()?,
+            "adv_loadw" => reads += 4,
+            "adv_pipe" => reads += 8,
+            _ if instruction.starts_with("adv") => return None,
+            _ => {}
+        }
+    }
+    Some(reads)
+}
+
+//Prints each Yul variable with its value, reading it from the stack or memory depending on where
+//the transpiler keeps it
+fn print_variables(session: &YulSession, result: &ExecutionResult) {
+    let mut variables = session.variables();
+    variables.sort_by(|(a, _), (b, _)| a.identifier.cmp(&b.identifier));
+    for (typed_identifier, location) in variables {
+        let value = match location {
+            VariableLocation::Stack(offset) => {
+                let values = result
+                    .stack
+                    .get(offset..)
+                    .and_then(|stack| decode_stack(&[typed_identifier.yul_type], stack));
+                match values {
+                    Some(values) => values[0],
+                    None => {
+                        println!("{}: isn't on the stack", typed_identifier.identifier);
+                        continue;
+                    }
+                }
+            }
+            VariableLocation::Memory(address) => result
+                .memory
+                .read_value(address as u64, typed_identifier.yul_type),
+        };
+        println!(
+            "{}: {} = {}",
+            typed_identifier.identifier, typed_identifier.yul_type, value
+        );
+    }
+}
+
+//The most addresses that the mem command shows at once
+const MEMORY_VIEW_LIMIT: u64 = 1024;
+
+//Shows the memory at an address or a range of addresses, one line per address, for the mem
+//command
+fn memory_lines(memory: &MemorySnapshot, args: &str) -> Result<Vec<String>, String> {
+    let usage = "usage: mem <addr>[..<addr>] [u32|u256|hex]";
+    let mut words = args.split_whitespace();
+    let range = words.next().ok_or(usage)?;
+    let view = words.next().unwrap_or("word");
+    let parse = |address: &str| address.parse::<u64>().map_err(|_| usage.to_string());
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let start = parse(range)?;
+            //a u256 takes up two addresses
+            (
+                start,
+                start.saturating_add(if view == "u256" { 2 } else { 1 }),
+            )
+        }
+    };
+    if end.saturating_sub(start) > MEMORY_VIEW_LIMIT {
+        return Err(format!(
+            "mem shows at most {} addresses at a time",
+            MEMORY_VIEW_LIMIT
+        ));
+    }
+    let join = |word: [u64; 4], format: fn(&u64) -> String| {
+        word.iter().map(format).collect::<Vec<_>>().join(" ")
+    };
+    match view {
+        "word" | "u32" | "hex" => Ok((start..end)
+            .map(|address| {
+                let word = memory.read_word(address);
+                match view {
+                    "u32" => format!("{}: {}", address, word[0]),
+                    "hex" => format!(
+                        "{}: {}",
+                        address,
+                        join(word, |element| format!("{:#x}", element))
+                    ),
+                    _ => format!("{}: {}", address, join(word, u64::to_string)),
+                }
+            })
+            .collect()),
+        "u256" => Ok((start..end)
+            .step_by(2)
+            .map(|address| format!("{}: {}", address, memory.read_u256(address)))
+            .collect()),
+        _ => Err(usage.to_string()),
+    }
+}
+
+//Changes the advice that the program runs with, for the advice command
+fn update_advice(
+    advice_stack: &mut Vec<u64>,
+    advice_map: &mut Vec<([u8; 32], Vec<u64>)>,
+    args: &str,
+) -> Result<(), String> {
+    let usage = "usage: advice [push <values> | map <key> <values> | clear]";
+    let mut words = args.split_whitespace();
+    let parse_values = |values: Vec<&str>| {
+        values
+            .iter()
+            .map(|value| match value.parse::<u64>() {
+                Ok(value) if value < Felt::MODULUS => Ok(value),
+                Ok(value) => Err(format!("{} is too big to be a field element", value)),
+                Err(_) => Err(usage.to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match words.next() {
+        None => {
+            println!(
+                "stack: {}",
+                advice_stack
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            for (key, values) in advice_map.iter() {
+                println!(
+                    "0x{}: {}",
+                    hex::encode(key),
+                    values
+                        .iter()
+                        .map(u64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+        }
+        Some("push") => advice_stack.extend(parse_values(words.collect())?),
+        Some("map") => {
+            //keys shorter than 32 bytes are padded with zeros on the left
+            let key = hex::decode(words.next().ok_or(usage)?.trim_start_matches("0x"))
+                .map_err(|_| "the key isn't valid hex".to_string())?;
+            if key.len() > 32 {
+                return Err("the key is longer than 32 bytes".to_string());
+            }
+            let mut padded_key = [0u8; 32];
+            padded_key[32 - key.len()..].copy_from_slice(&key);
+            advice_map.push((padded_key, parse_values(words.collect())?));
+        }
+        Some("clear") => {
+            advice_stack.clear();
+            advice_map.clear();
+        }
+        Some(_) => return Err(usage.to_string()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Checks that the inputs entered so far, each run on top of the state that the ones before it
+    //left, end up where running the whole program does
+    fn assert_same_as_full_run(repl: &mut Repl) {
+        let words = |result: &ExecutionResult| {
+            result
+                .memory
+                .addresses()
+                .into_iter()
+                .map(|address| (address, result.memory.read_word(address)))
+                .collect::<Vec<_>>()
+        };
+        let incremental = repl.last_result.take().unwrap();
+        repl.run().unwrap();
+        let full = repl.last_result.as_ref().unwrap();
+        assert_eq!(incremental.stack, full.stack);
+        assert_eq!(words(&incremental), words(full));
+    }
+
+    #[test]
+    fn incremental_masm_runs_match_full_runs() {
+        let mut repl = Repl::new(None, None);
+        for input in ["push.1", "push.2 pop.mem.5", "push.mem.5 add", "dup mul"] {
+            repl.handle(input, &mut || None).unwrap();
+        }
+        assert_same_as_full_run(&mut repl);
+    }
+
+    #[test]
+    fn incremental_advice_reads_match_full_runs() {
+        let mut repl = Repl::new(None, None);
+        repl.handle("advice push 7 8 9", &mut || None).unwrap();
+        for input in ["adv_push.1", "adv_push.1 add", "adv_push.1 mul"] {
+            repl.handle(input, &mut || None).unwrap();
+        }
+        repl.expect("135").unwrap();
+        assert_same_as_full_run(&mut repl);
+    }
+
+    #[test]
+    fn incremental_yul_runs_match_full_runs() {
+        let mut repl = Repl::new(None, None);
+        repl.handle(":yul", &mut || None).unwrap();
+        for input in [
+            "let x := 1",
+            "let y := add(x, 2)",
+            "mstore(0, y)",
+            "let z := mload(0)",
+        ] {
+            repl.handle(input, &mut || None).unwrap();
+        }
+        assert_same_as_full_run(&mut repl);
+    }
+
+    #[test]
+    fn count_advice_reads() {
+        assert_eq!(
+            advice_reads("begin\n    adv_push.2 adv_loadw\nend"),
+            Some(6)
+        );
+        assert_eq!(
+            advice_reads("begin\n    # adv_push.3 # adv_push.1\nend"),
+            Some(1)
+        );
+        assert_eq!(
+            advice_reads("proc.g\n    push.1\nend\nbegin\n    exec.g adv_push.1\nend"),
+            Some(1)
+        );
+        assert_eq!(
+            advice_reads("begin\n    push.1 if.true adv_push.1 end\nend"),
+            None
+        );
+        assert_eq!(
+            advice_reads(
+                "proc.f\n    adv_push.1\nend\nproc.g\n    exec.f\nend\nbegin\n    exec.g\nend"
+            ),
+            None
+        );
+        assert_eq!(advice_reads("begin\n    adv.keyval\nend"), None);
+        assert_eq!(advice_reads("push.1"), None);
+    }
+
+    //Enters an input that can span lines, the way the prompt and scripts read the lines after the
+    //first one
+    fn enter(repl: &mut Repl, input: &str) -> Result<(), String> {
+        let mut lines = input.lines();
+        let first = lines.next().unwrap();
+        repl.handle(first, &mut || lines.next().map(str::to_string))
+    }
+
+    #[test]
+    fn count_open_masm_blocks() {
+        assert_eq!(masm_depth("push.1 if.true"), 1);
+        assert_eq!(masm_depth("if.true push.1 else push.2 end"), 0);
+        assert_eq!(masm_depth("while.true repeat.3"), 2);
+        assert_eq!(masm_depth("proc.f.2"), 1);
+        assert_eq!(masm_depth("export.g"), 1);
+        assert_eq!(masm_depth("begin"), 1);
+        assert_eq!(masm_depth("end end"), -2);
+        assert_eq!(masm_depth("push.1 # if.true # add"), 0);
+    }
+
+    #[test]
+    fn define_procs() {
+        let mut repl = Repl::new(None, None);
+        enter(&mut repl, "proc.double\n    dup add\nend").unwrap();
+        assert!(enter(&mut repl, "proc.broken\n    not_an_instruction\nend").is_err());
+        assert_eq!(repl.defined_procs.len(), 1);
+        assert!(repl.procs().contains(&"double".to_string()));
+        enter(&mut repl, "push.4").unwrap();
+        enter(&mut repl, "exec.double").unwrap();
+        repl.expect("8").unwrap();
+    }
+
+    #[test]
+    fn undo_inputs_in_the_order_they_were_entered() {
+        let mut repl = Repl::new(None, None);
+        enter(&mut repl, "push.1").unwrap();
+        enter(&mut repl, "proc.a\n    push.2\nend").unwrap();
+        enter(&mut repl, "push.3").unwrap();
+        enter(&mut repl, "proc.b\n    push.4\nend").unwrap();
+        enter(&mut repl, "proc.c\n    push.5\nend").unwrap();
+
+        let defined = |repl: &Repl| {
+            repl.defined_procs
+                .iter()
+                .map(|(_, proc)| proc.lines().next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(repl.undo());
+        assert_eq!(defined(&repl), vec!["proc.a", "proc.b"]);
+        assert!(repl.undo());
+        assert_eq!(defined(&repl), vec!["proc.a"]);
+        assert_eq!(repl.program_lines, vec!["push.1", "push.3"]);
+        assert!(repl.undo());
+        assert_eq!(repl.program_lines, vec!["push.1"]);
+        assert_eq!(defined(&repl), vec!["proc.a"]);
+        assert!(repl.undo());
+        assert!(defined(&repl).is_empty());
+        assert!(repl.undo());
+        assert!(repl.program_lines.is_empty());
+        assert!(!repl.undo());
+    }
+
+    #[test]
+    fn load_saved_sessions_with_blocks() {
+        let mut repl = Repl::new(None, None);
+        enter(&mut repl, "proc.triple\n    dup dup add add\nend").unwrap();
+        enter(&mut repl, "push.1").unwrap();
+        enter(&mut repl, "if.true\npush.5\nelse\npush.6\nend").unwrap();
+        enter(&mut repl, "exec.triple").unwrap();
+        repl.expect("15").unwrap();
+        let path = write_temp_file("save", "session.masm", "");
+        enter(&mut repl, &format!("save {}", path)).unwrap();
+
+        let mut loaded = Repl::new(None, None);
+        enter(&mut loaded, &format!("load {}", path)).unwrap();
+        assert_eq!(
+            loaded.program_lines,
+            vec![
+                "push.1",
+                "if.true\npush.5\nelse\npush.6\nend",
+                "exec.triple"
+            ]
+        );
+        assert!(loaded.functions_miden.contains("proc.triple"));
+        loaded.expect("15").unwrap();
+        //the blocks come back as single inputs, so undo takes out the whole if
+        assert!(loaded.undo());
+        assert!(loaded.undo());
+        assert_eq!(loaded.program_lines, vec!["push.1"]);
+    }
+
+    #[test]
+    fn update_the_advice() {
+        let mut advice_stack = vec![];
+        let mut advice_map = vec![];
+        update_advice(&mut advice_stack, &mut advice_map, "push 1 2 3").unwrap();
+        update_advice(&mut advice_stack, &mut advice_map, "map 0x0102 7").unwrap();
+        assert_eq!(advice_stack, vec![1, 2, 3]);
+        let mut key = [0; 32];
+        key[30..].copy_from_slice(&[1, 2]);
+        assert_eq!(advice_map, vec![(key, vec![7])]);
+
+        let too_big = (Felt::MODULUS).to_string();
+        assert!(update_advice(
+            &mut advice_stack,
+            &mut advice_map,
+            &format!("push 4 {}", too_big)
+        )
+        .is_err());
+        assert!(update_advice(
+            &mut advice_stack,
+            &mut advice_map,
+            &format!("map 01 {}", too_big)
+        )
+        .is_err());
+        assert!(update_advice(&mut advice_stack, &mut advice_map, "push x").is_err());
+        assert!(update_advice(
+            &mut advice_stack,
+            &mut advice_map,
+            &format!("map {} 1", "00".repeat(33))
+        )
+        .is_err());
+        assert_eq!(advice_stack, vec![1, 2, 3]);
+        assert_eq!(advice_map.len(), 1);
+
+        update_advice(&mut advice_stack, &mut advice_map, "clear").unwrap();
+        assert!(advice_stack.is_empty() && advice_map.is_empty());
+    }
+
+    #[test]
+    fn show_memory() {
+        let program =
+            "begin\n  push.7 pop.mem.3\n  push.1.2.3.4 popw.mem.4\n  push.5.6.7.8 popw.mem.5\nend";
+        let memory = execute(program.to_string(), &ExecutionConfig::default())
+            .unwrap()
+            .memory;
+        assert_eq!(memory_lines(&memory, "3").unwrap(), vec!["3: 7 0 0 0"]);
+        assert_eq!(
+            memory_lines(&memory, "2..4 u32").unwrap(),
+            vec!["2: 0", "3: 7"]
+        );
+        assert_eq!(
+            memory_lines(&memory, "3 hex").unwrap(),
+            vec!["3: 0x7 0x0 0x0 0x0"]
+        );
+        assert_eq!(
+            memory_lines(&memory, "4 u256").unwrap(),
+            vec![format!("4: {}", memory.read_u256(4))]
+        );
+        assert!(memory_lines(&memory, "0..4000000000").is_err());
+        assert!(memory_lines(&memory, "3 u8").is_err());
+        assert!(memory_lines(&memory, "").is_err());
+        assert_eq!(memory_lines(&memory, "0..1024").unwrap().len(), 1024);
+    }
+
+    //Writes a file to a directory of its own for the test, returning its path
+    fn write_temp_file(test: &str, name: &str, contents: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("repl-test-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn failed_loads_keep_the_session() {
+        let mut repl = Repl::new(None, None);
+        repl.handle("push.1", &mut || None).unwrap();
+        repl.handle("push.2", &mut || None).unwrap();
+        let path = write_temp_file("load", "failing.masm", "begin\n    push.0\n    assert\nend");
+        assert!(repl
+            .handle(&format!("load {}", path), &mut || None)
+            .is_err());
+        assert_eq!(repl.program_lines, vec!["push.1", "push.2"]);
+        repl.expect("2 1").unwrap();
+    }
+
+    #[test]
+    fn run_scripts_with_expectations() {
+        let script = "\
+# comments and empty lines are skipped
+
+push.1
+push.2
+add
+expect 3
+proc.double
+    dup add
+end
+exec.double
+expect 6
+";
+        let path = write_temp_file("script", "passing.txt", script);
+        run_script(&path, None, None).unwrap();
+
+        let path = write_temp_file("script", "failing.txt", "push.1\nexpect 2\n");
+        let error = run_script(&path, None, None).unwrap_err();
+        assert!(error.starts_with(&format!("{}:2: Expected the stack to start with 2", path)));
+    }
+}
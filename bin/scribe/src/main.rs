@@ -1,62 +1,695 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use colored::Colorize;
+use itertools::Itertools;
 use papyrus::ast_optimization::optimize_ast;
-use papyrus::miden_generator;
+use papyrus::debugger::Debugger;
+use papyrus::executor::{
+    self, decode_stack, ExecutionConfig, ExecutionContext, ExecutionOutcome, ProgramProof,
+    TxContext,
+};
+use papyrus::miden_generator::{self, CompileOptions, SourceMap};
 use papyrus::parser;
+use papyrus::range_analysis::infer_types_with_narrowing;
 use papyrus::type_inference::infer_types;
-
-use papyrus::types::YulFile;
+use papyrus::types::{expressions_to_tree, YulFile, YulType};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
 use std::fs;
-extern crate quickcheck_macros;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
 
-extern crate insta;
+#[derive(Parser)]
+#[clap(version, about = "Transpiles Yul to Miden assembly")]
+struct Opts {
+    #[clap(subcommand)]
+    command: Command,
+}
 
-pub fn write_yul_to_masm(yul_file: YulFile) {
-    let parsed = parser::parse_yul_syntax(&yul_file.file_contents);
-    let ast = optimize_ast(parsed);
-    let ast = infer_types(&ast);
+#[derive(Subcommand)]
+enum Command {
+    #[clap(about = "Compiles Yul files, or every .yul file in directories, to Miden assembly")]
+    Build(BuildOpts),
+    #[clap(about = "Compiles and runs a Yul file, and prints the stack, memory, storage and logs")]
+    Run(RunOpts),
+    #[clap(about = "Steps through a Yul file as it runs, showing the Yul line and the stack")]
+    Debug(DebugOpts),
+    #[clap(
+        about = "Runs a Yul file with Miden's STARK prover and writes a proof of the execution"
+    )]
+    Prove(ProveOpts),
+    #[clap(about = "Checks a proof written by prove")]
+    Verify(VerifyOpts),
+}
 
-    let miden_code = miden_generator::transpile_program(ast, Default::default());
+#[derive(Args)]
+struct BuildOpts {
+    #[clap(required = true, help = "Yul files or directories of Yul files")]
+    inputs: Vec<PathBuf>,
+    #[clap(
+        short,
+        long,
+        default_value = ".",
+        help = "Directory to write the output to"
+    )]
+    out: PathBuf,
+    #[clap(flatten)]
+    compile: CompileOpts,
+    #[clap(
+        long,
+        arg_enum,
+        value_delimiter = ',',
+        default_value = "masm",
+        help = "Stages to write out, each to <out>/<name>.<stage>"
+    )]
+    emit: Vec<Stage>,
+}
 
-    fs::write(
-        format!(
-            "../masm/{}.masm",
-            &yul_file.file_path.file_stem().unwrap().to_str().unwrap()
-        ),
-        miden_code,
-    )
-    .expect("Unable to write Miden to file.");
+#[derive(Args)]
+struct RunOpts {
+    #[clap(help = "The Yul file to run")]
+    file: PathBuf,
+    #[clap(long, default_value = "", help = "Calldata as hex, such as 0x1234")]
+    calldata: String,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Values to push to the stack before running, such as 1,2,3, so 3 ends up on top"
+    )]
+    stack: Vec<u64>,
+    #[clap(flatten)]
+    compile: CompileOpts,
+}
+
+#[derive(Args)]
+struct DebugOpts {
+    #[clap(help = "The Yul file to debug")]
+    file: PathBuf,
+    #[clap(
+        long = "break",
+        value_delimiter = ',',
+        help = "Lines to stop at, such as 3,7"
+    )]
+    breakpoints: Vec<u32>,
+    #[clap(long, default_value = "", help = "Calldata as hex, such as 0x1234")]
+    calldata: String,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Values to push to the stack before running, such as 1,2,3, so 3 ends up on top"
+    )]
+    stack: Vec<u64>,
+    #[clap(flatten)]
+    compile: CompileOpts,
+}
+
+#[derive(Args)]
+struct ProveOpts {
+    #[clap(help = "The Yul file to prove")]
+    file: PathBuf,
+    #[clap(
+        short,
+        long,
+        help = "Where to write the proof, <name>.proof by default"
+    )]
+    out: Option<PathBuf>,
+    #[clap(long, default_value = "", help = "Calldata as hex, such as 0x1234")]
+    calldata: String,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Values to push to the stack before running, such as 1,2,3, so 3 ends up on top"
+    )]
+    stack: Vec<u64>,
+    #[clap(flatten)]
+    compile: CompileOpts,
+}
+
+#[derive(Args)]
+struct VerifyOpts {
+    #[clap(help = "The proof to check")]
+    proof: PathBuf,
+    #[clap(
+        long,
+        help = "A Yul file that the proof has to be for, compiled with the same flags as when \
+                it was proven"
+    )]
+    program: Option<PathBuf>,
+    #[clap(flatten)]
+    compile: CompileOpts,
+}
+
+//The flags that mirror CompileOptions
+#[derive(Args)]
+struct CompileOpts {
+    #[clap(long, help = "Leave comments out of the Miden assembly")]
+    no_comments: bool,
+    #[clap(long, help = "Don't indent the Miden assembly")]
+    no_indent: bool,
+    #[clap(
+        short = 'O',
+        long,
+        default_value = "2",
+        possible_values = ["0", "1", "2"],
+        help = "0 compiles every value as typed, 1 also narrows u256 values to u32 where they \
+                fit, 2 also inlines small functions"
+    )]
+    opt_level: u8,
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Ast,
+    TypedAst,
+    Masm,
+    Cost,
+}
+
+impl Stage {
+    fn extension(self) -> &'static str {
+        match self {
+            Stage::Ast => "ast",
+            Stage::TypedAst => "typed-ast",
+            Stage::Masm => "masm",
+            Stage::Cost => "cost",
+        }
+    }
+}
+
+impl CompileOpts {
+    fn compile_options(&self) -> CompileOptions {
+        let default = CompileOptions::default();
+        CompileOptions {
+            comments: !self.no_comments,
+            auto_indent: !self.no_indent,
+            inline_threshold: match self.opt_level {
+                2 => default.inline_threshold,
+                _ => 0,
+            },
+            ..default
+        }
+    }
 }
 
 fn main() {
-    let yul_contracts = read_yul_contracts();
+    let opts = Opts::parse();
+    let result = match opts.command {
+        Command::Build(build_opts) => build(&build_opts),
+        Command::Run(run_opts) => run(&run_opts),
+        Command::Debug(debug_opts) => debug(&debug_opts),
+        Command::Prove(prove_opts) => prove(&prove_opts),
+        Command::Verify(verify_opts) => verify(&verify_opts),
+    };
+    if let Err(error) = result {
+        eprintln!("{} {:#}", "error:".red().bold(), error);
+        process::exit(1);
+    }
+}
 
-    //For each contract in Vec of YulFile
-    for yul_code in yul_contracts {
-        write_yul_to_masm(yul_code)
+//Compiles every input, reporting each file that fails rather than stopping at the first one
+fn build(opts: &BuildOpts) -> Result<()> {
+    let yul_files = read_yul_files(&opts.inputs)?;
+    fs::create_dir_all(&opts.out)
+        .with_context(|| format!("couldn't create {}", opts.out.display()))?;
+    let mut failures = 0;
+    for yul_file in &yul_files {
+        if let Err(error) = build_file(yul_file, opts) {
+            eprintln!("{} {:#}", "error:".red().bold(), error);
+            failures += 1;
+        }
     }
+    if failures > 0 {
+        bail!(
+            "{} of {} files failed to compile",
+            failures,
+            yul_files.len()
+        );
+    }
+    Ok(())
 }
 
-//Read in all of the Yul contracts from the contracts directory and return a Vec of Yul Files
-fn read_yul_contracts() -> Vec<YulFile> {
-    let mut yul_files: Vec<YulFile> = Vec::new();
-    let mut paths: Vec<_> = fs::read_dir("../contracts/")
-        .unwrap()
-        .map(|r| r.unwrap())
-        .collect();
+fn build_file(yul_file: &YulFile, opts: &BuildOpts) -> Result<()> {
+    let path = yul_file.file_path.display();
+    let stages = compile(yul_file, &opts.compile, false).with_context(|| format!("{}", path))?;
+    let name = yul_file
+        .file_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("{} doesn't have a file name", path))?;
+    for stage in &opts.emit {
+        let out_path = opts.out.join(format!("{}.{}", name, stage.extension()));
+        let contents = match stage {
+            Stage::Ast => &stages.ast,
+            Stage::TypedAst => &stages.typed_ast,
+            Stage::Masm => &stages.masm,
+            Stage::Cost => &stages.cost,
+        };
+        fs::write(&out_path, contents)
+            .with_context(|| format!("couldn't write {}", out_path.display()))?;
+    }
+    println!("{} {}", "compiled".green(), path);
+    Ok(())
+}
 
-    //Sort the files by file name
-    paths.sort_by_key(|dir| dir.path());
+//Compiles a Yul file with source markers, runs it and prints how it ended. A failure inside
+//Miden is reported along with the Yul statement that was running.
+fn run(opts: &RunOpts) -> Result<()> {
+    let yul_file = &read_yul_files(&[opts.file.clone()])?[0];
+    let stages = compile(yul_file, &opts.compile, true)
+        .with_context(|| format!("{}", opts.file.display()))?;
+    let layout = opts.compile.compile_options().memory_layout;
+    let config = execution_config(&opts.calldata, &opts.stack)?.with_layout(layout.clone());
+    let result = match executor::execute(stages.masm, &config) {
+        Ok(result) => result,
+        Err(failure) => {
+            let location = match failure.source_line {
+                Some(line) => format!(
+                    " at line {}:\n{:>5} | {}",
+                    line,
+                    line,
+                    yul_file
+                        .file_contents
+                        .lines()
+                        .nth(line as usize - 1)
+                        .unwrap_or_default()
+                        .trim()
+                ),
+                None => String::new(),
+            };
+            bail!("{:?}{}", failure.error, location);
+        }
+    };
 
-    //For each file, read in the contents and push a YulFile to the yul_files Vec
-    for path in paths {
-        let contents = fs::read_to_string(path.path())
-            .expect("Something went wrong reading from the contracts directory");
+    let outcome = ExecutionOutcome::from_memory(&result.memory, &layout)
+        .map_err(|error| anyhow!("{:?}", error))?;
+    println!("{} {:?}", "status:".bold(), outcome.status);
+    if !outcome.return_data.is_empty() {
+        println!("return data: 0x{}", hex::encode(&outcome.return_data));
+    }
+    println!("{}", "stack:".bold());
+    let values = decode_stack(&stages.outputs, &result.stack)
+        .ok_or_else(|| anyhow!("the stack is too short for the outputs of the program"))?;
+    for value in values {
+        println!("  {}: {}", value.yul_type(), value);
+    }
+    //each mstore offset holds a 32 byte word, and words that were never written are left out
+    println!("{}", "memory:".bold());
+    for (offset, word) in result.memory.heap_bytes(&layout).chunks(32).enumerate() {
+        if word.iter().any(|byte| *byte != 0) {
+            println!("  {}: 0x{}", offset, hex::encode(word));
+        }
+    }
+    println!("{}", "storage:".bold());
+    for (key, value) in &outcome.storage {
+        println!("  {:#x}: {:#x}", key, value);
+    }
+    println!("{}", "logs:".bold());
+    for log in &outcome.logs {
+        let topics = log
+            .topics
+            .iter()
+            .map(|topic| format!("{:#x}", topic))
+            .join(", ");
+        println!("  [{}] 0x{}", topics, hex::encode(&log.data));
+    }
+    println!("{} {}", "cycles:".bold(), result.cycles);
+    Ok(())
+}
+
+const DEBUG_HELP: &str = "\
+step [n]       run n cycles, 1 by default
+back [n]       go back n cycles, 1 by default
+next           run until the next statement starts
+continue       run until a line with a breakpoint starts
+break <line>   stop at a line
+delete <line>  remove the breakpoint at a line
+breakpoints    list the breakpoints
+quit           stop debugging
+step, back, next, continue, delete, quit and help can be shortened to their first letter, and an
+empty line repeats the last command.";
 
-        yul_files.push(YulFile {
-            file_path: path.path(),
-            file_contents: contents,
-        });
+//Compiles a Yul file with source markers and steps through it, showing the Miden instruction, the
+//Yul line and the stack, with the variables that the transpiler put in each element
+fn debug(opts: &DebugOpts) -> Result<()> {
+    let yul_file = &read_yul_files(&[opts.file.clone()])?[0];
+    let stages = compile(yul_file, &opts.compile, true)
+        .with_context(|| format!("{}", opts.file.display()))?;
+    let layout = opts.compile.compile_options().memory_layout;
+    let config = execution_config(&opts.calldata, &opts.stack)?.with_layout(layout);
+    let mut debugger = Debugger::new(stages.masm, &config, stages.source_map)
+        .map_err(|error| anyhow!("{:?}", error))?;
+    for line in &opts.breakpoints {
+        debugger.add_breakpoint(*line);
     }
+    let source = yul_file.file_contents.lines().collect::<Vec<_>>();
+    println!("{}", "type help for the commands".dimmed());
+    print_debug_view(&debugger, &source);
 
-    //Return the yul files
-    yul_files
+    let mut editor = Editor::<()>::new();
+    let mut last_command = String::new();
+    loop {
+        let input = match editor.readline(&"(debug) ".blue()) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        let input = match input.trim() {
+            "" => last_command.clone(),
+            input => input.to_string(),
+        };
+        last_command = input.clone();
+        let mut words = input.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next().map(str::parse::<u32>).transpose();
+        let argument = match argument {
+            Ok(argument) => argument,
+            Err(_) => {
+                println!("{} takes a number", command);
+                continue;
+            }
+        };
+        match (command, argument) {
+            ("step" | "s", count) => {
+                for _ in 0..count.unwrap_or(1) {
+                    debugger.step();
+                }
+            }
+            ("back" | "b", count) => {
+                for _ in 0..count.unwrap_or(1) {
+                    debugger.step_back();
+                }
+            }
+            ("next" | "n", None) => debugger.next_statement(),
+            ("continue" | "c", None) => {
+                if let Some(line) = debugger.continue_to_breakpoint() {
+                    println!("{} {}", "breakpoint at line".yellow(), line);
+                }
+            }
+            ("break", Some(line)) => {
+                debugger.add_breakpoint(line);
+                continue;
+            }
+            ("delete" | "d", Some(line)) => {
+                if !debugger.remove_breakpoint(line) {
+                    println!("there's no breakpoint at line {}", line);
+                }
+                continue;
+            }
+            ("breakpoints", None) => {
+                println!("{}", debugger.breakpoints().join(", "));
+                continue;
+            }
+            ("quit" | "q", None) => return Ok(()),
+            ("help" | "h", None) => {
+                println!("{}", DEBUG_HELP);
+                continue;
+            }
+            _ => {
+                println!("unknown command {}, type help for the commands", input);
+                continue;
+            }
+        }
+        print_debug_view(&debugger, &source);
+    }
+}
+
+fn print_debug_view(debugger: &Debugger, source: &[&str]) {
+    let view = debugger.view();
+    let line = match view.line {
+        Some(line) => format!(
+            "line {}: {}",
+            line,
+            source.get(line as usize - 1).unwrap_or(&"").trim()
+        ),
+        None => "before the first statement".to_string(),
+    };
+    println!("{} {}  {}", "cycle".bold(), view.clk, line);
+    println!("  {}", view.instruction.green());
+    for (i, (value, label)) in view.stack.iter().enumerate() {
+        println!(
+            "  {:>2}: {:<12} {}",
+            i,
+            value,
+            label.as_deref().unwrap_or("")
+        );
+    }
+    if debugger.is_finished() {
+        match debugger.error() {
+            Some(error) => println!("{} {:?}", "program failed:".red().bold(), error),
+            None => println!("{}", "program finished".green()),
+        }
+    }
+}
+
+//Compiles a Yul file and proves its execution. The proof doesn't include the calldata.
+fn prove(opts: &ProveOpts) -> Result<()> {
+    let yul_file = &read_yul_files(&[opts.file.clone()])?[0];
+    let stages = compile(yul_file, &opts.compile, false)
+        .with_context(|| format!("{}", opts.file.display()))?;
+    let config = execution_config(&opts.calldata, &opts.stack)?;
+    let proof = executor::prove(stages.masm, &config).map_err(|error| anyhow!("{:?}", error))?;
+    let out = opts
+        .out
+        .clone()
+        .unwrap_or_else(|| opts.file.with_extension("proof"));
+    fs::write(&out, proof.encode()).with_context(|| format!("couldn't write {}", out.display()))?;
+    println!("{} {}", "proved".green(), out.display());
+    println!(
+        "stack outputs: {}",
+        proof.stack_outputs.iter().map(u64::to_string).join(",")
+    );
+    Ok(())
+}
+
+//Checks a proof, and that it's for the given program if there is one
+fn verify(opts: &VerifyOpts) -> Result<()> {
+    let text = fs::read_to_string(&opts.proof)
+        .with_context(|| format!("couldn't read {}", opts.proof.display()))?;
+    let proof = ProgramProof::decode(&text).map_err(|error| anyhow!("{:?}", error))?;
+    if let Some(program) = &opts.program {
+        let yul_file = &read_yul_files(&[program.clone()])?[0];
+        let stages = compile(yul_file, &opts.compile, false)
+            .with_context(|| format!("{}", program.display()))?;
+        let program_hash =
+            executor::program_hash(stages.masm).map_err(|error| anyhow!("{:?}", error))?;
+        if program_hash != proof.program_hash {
+            bail!(
+                "the proof is for a different program than {}",
+                program.display()
+            );
+        }
+    }
+    executor::verify(&proof).map_err(|error| anyhow!("{:?}", error))?;
+    println!("{} {}", "verified".green(), opts.proof.display());
+    Ok(())
+}
+
+//The inputs for a run, with the calldata given as hex
+fn execution_config(calldata: &str, stack: &[u64]) -> Result<ExecutionConfig> {
+    let calldata =
+        hex::decode(calldata.trim_start_matches("0x")).context("the calldata isn't valid hex")?;
+    let context = ExecutionContext {
+        tx: TxContext {
+            calldata,
+            ..TxContext::default()
+        },
+        ..ExecutionContext::default()
+    };
+    Ok(stack.iter().fold(
+        ExecutionConfig::default().with_context(&context),
+        |config, value| config.with_stack_input(*value),
+    ))
+}
+
+//The output of each stage of compiling a Yul file
+struct Stages {
+    ast: String,
+    typed_ast: String,
+    masm: String,
+    cost: String,
+    //the types of the values the program leaves on the stack, top first
+    outputs: Vec<YulType>,
+    source_map: SourceMap,
+    warnings: Vec<String>,
+}
+
+//The transpiler panics on invalid programs, so the panic is turned into an error for the file.
+//Source markers let run map failures back to Yul lines.
+fn compile(yul_file: &YulFile, opts: &CompileOpts, source_markers: bool) -> Result<Stages> {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let parsed = if source_markers {
+            parser::parse_yul_syntax_with_source_markers(&yul_file.file_contents)
+        } else {
+            parser::parse_yul_syntax(&yul_file.file_contents)
+        };
+        let ast = optimize_ast(parsed);
+        let typed_ast = match opts.opt_level {
+            0 => infer_types(&ast),
+            _ => infer_types_with_narrowing(&ast),
+        };
+        let program = miden_generator::compile_program(typed_ast.clone(), opts.compile_options());
+        Stages {
+            ast: expressions_to_tree(&ast),
+            typed_ast: expressions_to_tree(&typed_ast),
+            cost: cost_report(&program.masm),
+            masm: program.masm,
+            outputs: program.outputs,
+            source_map: program.source_map,
+            warnings: program.warnings,
+        }
+    }));
+    panic::set_hook(default_hook);
+    let stages = result.map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "the compiler panicked".to_string());
+        anyhow!(message)
+    })?;
+    for warning in &stages.warnings {
+        eprintln!("{} {}", "warning:".yellow(), warning);
+    }
+    Ok(stages)
+}
+
+//Counts the instructions in each proc and in the body of the program. Instructions inside
+//control flow count once for each time they appear, not for each time they run.
+fn cost_report(masm: &str) -> String {
+    let mut counts: Vec<(String, usize)> = vec![];
+    let mut depth = 0;
+    //comments are the odd pieces between #s
+    let tokens = masm
+        .split('#')
+        .step_by(2)
+        .flat_map(|code| code.split_whitespace());
+    for token in tokens {
+        match token {
+            _ if token.starts_with("use.") => {}
+            "begin" => counts.push(("begin".to_string(), 0)),
+            _ if token.starts_with("proc.") => {
+                let name = token.split('.').nth(1).unwrap_or_default();
+                counts.push((format!("proc {}", name), 0));
+            }
+            "end" if depth == 0 => {}
+            "end" => depth -= 1,
+            "else" => {}
+            _ => {
+                if ["if.", "while.", "repeat."]
+                    .iter()
+                    .any(|block| token.starts_with(block))
+                {
+                    depth += 1;
+                }
+                if let Some((_, count)) = counts.last_mut() {
+                    *count += 1;
+                }
+            }
+        }
+    }
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    counts
+        .iter()
+        .map(|(name, count)| format!("{}: {} instructions\n", name, count))
+        .chain(std::iter::once(format!("total: {} instructions\n", total)))
+        .collect()
+}
+
+//Reads the Yul files given on the command line, and the .yul files in the directories, sorted by
+//path
+fn read_yul_files(inputs: &[PathBuf]) -> Result<Vec<YulFile>> {
+    let mut paths = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            let mut dir_paths = fs::read_dir(input)
+                .with_context(|| format!("couldn't read {}", input.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("couldn't read {}", input.display()))?;
+            dir_paths.retain(|path| is_yul_file(path));
+            dir_paths.sort();
+            paths.extend(dir_paths);
+        } else if input.is_file() {
+            paths.push(input.clone());
+        } else {
+            bail!("{} doesn't exist", input.display());
+        }
+    }
+    if paths.is_empty() {
+        bail!("no Yul files to compile");
+    }
+    paths
+        .into_iter()
+        .map(|path| {
+            let file_contents = fs::read_to_string(&path)
+                .with_context(|| format!("couldn't read {}", path.display()))?;
+            Ok(YulFile {
+                file_path: path,
+                file_contents,
+            })
+        })
+        .collect()
+}
+
+fn is_yul_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map_or(false, |extension| extension == "yul")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_instructions_per_proc() {
+        let masm = "use.std::math::u256
+proc.f.0
+    push.1 # a comment # add
+end
+begin
+    push.1
+    if.true
+        exec.f
+    else
+        push.2
+    end
+end";
+        assert_eq!(
+            cost_report(masm),
+            "proc f: 2 instructions\nbegin: 4 instructions\ntotal: 6 instructions\n"
+        );
+    }
+
+    #[test]
+    fn reads_yul_files_from_directories() {
+        let dir = std::env::temp_dir().join(format!("scribe-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.yul"), "let b := 2").unwrap();
+        fs::write(dir.join("a.yul"), "let a := 1").unwrap();
+        fs::write(dir.join("notes.txt"), "not yul").unwrap();
+
+        let files = read_yul_files(&[dir.clone()]).unwrap();
+        let names = files
+            .iter()
+            .map(|file| file.file_path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.yul", "b.yul"]);
+        assert_eq!(files[0].file_contents, "let a := 1");
+
+        //files given directly are read whatever their extension
+        let files = read_yul_files(&[dir.join("notes.txt")]).unwrap();
+        assert_eq!(files[0].file_contents, "not yul");
+
+        assert!(read_yul_files(&[dir.join("missing.yul")]).is_err());
+        fs::remove_file(dir.join("a.yul")).unwrap();
+        fs::remove_file(dir.join("b.yul")).unwrap();
+        let error = read_yul_files(&[dir.clone()]).unwrap_err();
+        assert_eq!(error.to_string(), "no Yul files to compile");
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
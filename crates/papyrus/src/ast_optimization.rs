@@ -1,5 +1,8 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    vec,
+};
 
 use crate::types::*;
 
@@ -313,3 +316,887 @@ fn walk_expr<V: ExpressionVisitor>(expr: Expr, visitor: &mut V) -> Option<Expr>
     }
     None
 }
+
+//Yul functions can be defined in any block, and can be called from anywhere in that block, even
+//before their definition. Miden procs are all defined before the program, so nested functions are
+//moved to the top level. A nested function can't see the variables around it, so only the calls
+//need updating: nested functions are renamed after the function they're defined in, so that
+//functions with the same name in different blocks don't collide, and the calls in their scope are
+//renamed with them. Names are also limited to the characters Miden allows in a proc name.
+pub fn hoist_functions(ast: Vec<Expr>) -> Vec<Expr> {
+    let mut hoister = Hoister::default();
+    let exprs = hoister.hoist_block(ast, None);
+    hoister.hoisted.into_iter().chain(exprs).collect()
+}
+
+#[derive(Default)]
+struct Hoister {
+    hoisted: Vec<Expr>,
+    function_names: HashSet<String>,
+    //the names functions are called by in each enclosing block, and the names they're renamed to
+    scopes: Vec<HashMap<String, String>>,
+}
+
+impl Hoister {
+    fn hoist_block(&mut self, exprs: Vec<Expr>, parent: Option<&str>) -> Vec<Expr> {
+        let mut scope = HashMap::new();
+        for expr in &exprs {
+            if let Expr::FunctionDefinition(op) = expr {
+                let name = match parent {
+                    Some(parent) => format!("{}_{}", parent, op.function_name),
+                    None => op.function_name.clone(),
+                };
+                scope.insert(op.function_name.clone(), self.unique_name(&name));
+            }
+        }
+        self.scopes.push(scope);
+        let mut new_exprs = vec![];
+        for expr in exprs {
+            match expr {
+                Expr::FunctionDefinition(ExprFunctionDefinition {
+                    function_name,
+                    params,
+                    returns,
+                    block,
+                }) => {
+                    let function_name = self.scopes.last().unwrap()[&function_name].clone();
+                    let block = ExprBlock {
+                        exprs: self.hoist_block(block.exprs, Some(&function_name)),
+                    };
+                    self.hoisted
+                        .push(Expr::FunctionDefinition(ExprFunctionDefinition {
+                            function_name,
+                            params,
+                            returns,
+                            block,
+                        }));
+                }
+                expr => new_exprs.push(self.hoist_expr(expr, parent)),
+            }
+        }
+        self.scopes.pop();
+        new_exprs
+    }
+
+    fn hoist_expr(&mut self, expr: Expr, parent: Option<&str>) -> Expr {
+        match expr {
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                inferred_return_types,
+                inferred_param_types,
+            }) => Expr::FunctionCall(ExprFunctionCall {
+                function_name: self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(&function_name).cloned())
+                    .unwrap_or(function_name),
+                exprs: Box::new(
+                    exprs
+                        .into_iter()
+                        .map(|expr| self.hoist_expr(expr, parent))
+                        .collect(),
+                ),
+                inferred_return_types,
+                inferred_param_types,
+            }),
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs,
+            }) => Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs: rhs.map(|rhs| Box::new(self.hoist_expr(*rhs, parent))),
+            }),
+            Expr::Assignment(ExprAssignment {
+                identifiers,
+                inferred_types,
+                rhs,
+            }) => Expr::Assignment(ExprAssignment {
+                identifiers,
+                inferred_types,
+                rhs: Box::new(self.hoist_expr(*rhs, parent)),
+            }),
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => Expr::IfStatement(ExprIfStatement {
+                first_expr: Box::new(self.hoist_expr(*first_expr, parent)),
+                second_expr: Box::new(ExprBlock {
+                    exprs: self.hoist_block(second_expr.exprs, parent),
+                }),
+            }),
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => Expr::ForLoop(ExprForLoop {
+                init_block: Box::new(ExprBlock {
+                    exprs: self.hoist_block(init_block.exprs, parent),
+                }),
+                conditional: Box::new(self.hoist_expr(*conditional, parent)),
+                after_block: Box::new(ExprBlock {
+                    exprs: self.hoist_block(after_block.exprs, parent),
+                }),
+                interior_block: Box::new(ExprBlock {
+                    exprs: self.hoist_block(interior_block.exprs, parent),
+                }),
+            }),
+            Expr::Repeat(ExprRepeat {
+                interior_block,
+                iterations,
+            }) => Expr::Repeat(ExprRepeat {
+                iterations,
+                interior_block: Box::new(ExprBlock {
+                    exprs: self.hoist_block(interior_block.exprs, parent),
+                }),
+            }),
+            Expr::Block(ExprBlock { exprs }) => Expr::Block(ExprBlock {
+                exprs: self.hoist_block(exprs, parent),
+            }),
+            Expr::Switch(ExprSwitch {
+                default_case,
+                inferred_type,
+                expr,
+                cases,
+            }) => Expr::Switch(ExprSwitch {
+                expr: Box::new(self.hoist_expr(*expr, parent)),
+                inferred_type,
+                cases: cases
+                    .into_iter()
+                    .map(|case| ExprCase {
+                        literal: case.literal,
+                        block: ExprBlock {
+                            exprs: self.hoist_block(case.block.exprs, parent),
+                        },
+                    })
+                    .collect(),
+                default_case: default_case.map(|block| ExprBlock {
+                    exprs: self.hoist_block(block.exprs, parent),
+                }),
+            }),
+            expr => expr,
+        }
+    }
+
+    fn unique_name(&mut self, name: &str) -> String {
+        let name = name
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c,
+                false => '_',
+            })
+            .collect::<String>();
+        let mut unique_name = name.clone();
+        let mut suffix = 0;
+        while self.function_names.contains(&unique_name) {
+            unique_name = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        self.function_names.insert(unique_name.clone());
+        unique_name
+    }
+}
+
+//Replaces calls to small user functions with the body of the function, so they don't pay for
+//shuffling the arguments into place and dropping everything but the return values. A function is
+//inlined if its body has at most max_cost expressions, it isn't recursive, and it doesn't use
+//leave. Only calls that make up a whole statement are inlined, like f(x), let a := f(x) or
+//a, b := f(x), since the body has to run before the statement. A max_cost of 0 turns inlining off.
+//
+//The params, return variables and locals of the inlined body are renamed, so they can't collide
+//with the caller's variables. This runs after type inference, so the new declarations keep the
+//types of the function's signature.
+pub fn inline_functions(ast: Vec<Expr>, max_cost: usize) -> Vec<Expr> {
+    if max_cost == 0 {
+        return ast;
+    }
+    let definitions = ast
+        .iter()
+        .filter_map(|expr| match expr {
+            Expr::FunctionDefinition(op) => Some((op.function_name.clone(), op.clone())),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let functions = definitions
+        .iter()
+        .filter(|(name, op)| {
+            block_cost(&op.block.exprs) <= max_cost
+                && !calls_function(&op.block.exprs, name, &definitions, &mut HashSet::new())
+                && can_inline_block(&op.block.exprs)
+        })
+        .map(|(name, op)| (name.clone(), op.clone()))
+        .collect::<HashMap<_, _>>();
+    if functions.is_empty() {
+        return ast;
+    }
+
+    let mut used_identifiers = HashSet::new();
+    collect_identifiers(&ast, &mut used_identifiers);
+    let mut inliner = Inliner {
+        functions,
+        used_identifiers,
+    };
+    let ast = inliner.inline_block(ast);
+
+    //functions that are no longer called anywhere don't need a proc
+    let mut called_functions = HashSet::new();
+    collect_called_functions(&ast, &mut called_functions);
+    ast.into_iter()
+        .filter(|expr| match expr {
+            Expr::FunctionDefinition(op) => {
+                !inliner.functions.contains_key(&op.function_name)
+                    || called_functions.contains(&op.function_name)
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+struct Inliner {
+    functions: HashMap<String, ExprFunctionDefinition>,
+    used_identifiers: HashSet<String>,
+}
+
+impl Inliner {
+    fn inline_block(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        let mut new_exprs = vec![];
+        for expr in exprs {
+            match expr {
+                Expr::FunctionCall(call) if self.functions.contains_key(&call.function_name) => {
+                    let return_variables = self.inline_call(call, &mut new_exprs);
+                    new_exprs.extend(return_variables.into_iter().map(Expr::Variable));
+                }
+                Expr::DeclareVariable(ExprDeclareVariable {
+                    typed_identifiers,
+                    rhs: Some(rhs),
+                }) if self.is_inlined_call(&rhs) => {
+                    let call = match *rhs {
+                        Expr::FunctionCall(call) => call,
+                        _ => unreachable!(),
+                    };
+                    let return_variables = self.inline_call(call, &mut new_exprs);
+                    for (typed_identifier, return_variable) in
+                        typed_identifiers.into_iter().zip(return_variables)
+                    {
+                        new_exprs.push(Expr::DeclareVariable(ExprDeclareVariable {
+                            typed_identifiers: vec![typed_identifier],
+                            rhs: Some(Box::new(Expr::Variable(return_variable))),
+                        }));
+                    }
+                }
+                Expr::Assignment(ExprAssignment {
+                    identifiers,
+                    inferred_types,
+                    rhs,
+                }) if self.is_inlined_call(&rhs) => {
+                    let call = match *rhs {
+                        Expr::FunctionCall(call) => call,
+                        _ => unreachable!(),
+                    };
+                    let return_variables = self.inline_call(call, &mut new_exprs);
+                    for ((identifier, inferred_type), return_variable) in identifiers
+                        .into_iter()
+                        .zip(inferred_types)
+                        .zip(return_variables)
+                    {
+                        new_exprs.push(Expr::Assignment(ExprAssignment {
+                            identifiers: vec![identifier],
+                            inferred_types: vec![inferred_type],
+                            rhs: Box::new(Expr::Variable(return_variable)),
+                        }));
+                    }
+                }
+                expr => new_exprs.push(self.inline_nested_blocks(expr)),
+            }
+        }
+        new_exprs
+    }
+
+    fn is_inlined_call(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::FunctionCall(call) if self.functions.contains_key(&call.function_name))
+    }
+
+    //Adds the statements of an inlined call to exprs, and returns the variables holding the
+    //return values
+    fn inline_call(
+        &mut self,
+        call: ExprFunctionCall,
+        exprs: &mut Vec<Expr>,
+    ) -> Vec<ExprVariableReference> {
+        let function = self.functions[&call.function_name].clone();
+        let mut names = HashMap::new();
+        let params = function
+            .params
+            .iter()
+            .map(|param| self.rename(&function.function_name, param, &mut names))
+            .collect::<Vec<_>>();
+        //calls evaluate their arguments from last to first, so the params are bound in that order
+        for (typed_identifier, arg) in params.into_iter().zip(*call.exprs).rev() {
+            exprs.push(Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers: vec![typed_identifier],
+                rhs: Some(Box::new(arg)),
+            }));
+        }
+        let mut return_variables = vec![];
+        for ret in &function.returns {
+            let typed_identifier = self.rename(&function.function_name, ret, &mut names);
+            return_variables.push(ExprVariableReference {
+                identifier: typed_identifier.identifier.clone(),
+                inferred_type: Some(typed_identifier.yul_type),
+            });
+            //return variables start out as zero
+            exprs.push(Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers: vec![typed_identifier],
+                rhs: None,
+            }));
+        }
+        let body = self.rename_block(&function.function_name, function.block.exprs, &mut names);
+        //the body can call other functions that are inlined
+        exprs.extend(self.inline_block(body));
+        return_variables
+    }
+
+    fn inline_nested_blocks(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr: Box::new(ExprBlock {
+                    exprs: self.inline_block(second_expr.exprs),
+                }),
+            }),
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => Expr::ForLoop(ExprForLoop {
+                init_block: Box::new(ExprBlock {
+                    exprs: self.inline_block(init_block.exprs),
+                }),
+                conditional,
+                after_block: Box::new(ExprBlock {
+                    exprs: self.inline_block(after_block.exprs),
+                }),
+                interior_block: Box::new(ExprBlock {
+                    exprs: self.inline_block(interior_block.exprs),
+                }),
+            }),
+            Expr::Repeat(ExprRepeat {
+                interior_block,
+                iterations,
+            }) => Expr::Repeat(ExprRepeat {
+                iterations,
+                interior_block: Box::new(ExprBlock {
+                    exprs: self.inline_block(interior_block.exprs),
+                }),
+            }),
+            Expr::Block(ExprBlock { exprs }) => Expr::Block(ExprBlock {
+                exprs: self.inline_block(exprs),
+            }),
+            Expr::Switch(ExprSwitch {
+                default_case,
+                inferred_type,
+                expr,
+                cases,
+            }) => Expr::Switch(ExprSwitch {
+                default_case: default_case.map(|block| ExprBlock {
+                    exprs: self.inline_block(block.exprs),
+                }),
+                inferred_type,
+                expr,
+                cases: cases
+                    .into_iter()
+                    .map(|case| ExprCase {
+                        literal: case.literal,
+                        block: ExprBlock {
+                            exprs: self.inline_block(case.block.exprs),
+                        },
+                    })
+                    .collect(),
+            }),
+            Expr::FunctionDefinition(ExprFunctionDefinition {
+                function_name,
+                params,
+                returns,
+                block,
+            }) => Expr::FunctionDefinition(ExprFunctionDefinition {
+                function_name,
+                params,
+                returns,
+                block: ExprBlock {
+                    exprs: self.inline_block(block.exprs),
+                },
+            }),
+            expr => expr,
+        }
+    }
+
+    //Gives a variable of an inlined function a name that isn't used anywhere else in the program
+    fn rename(
+        &mut self,
+        function_name: &str,
+        typed_identifier: &TypedIdentifier,
+        names: &mut HashMap<String, String>,
+    ) -> TypedIdentifier {
+        let mut suffix = 0;
+        let identifier = loop {
+            let identifier = format!(
+                "{}_{}_{}",
+                function_name, typed_identifier.identifier, suffix
+            );
+            if !self.used_identifiers.contains(&identifier) {
+                break identifier;
+            }
+            suffix += 1;
+        };
+        self.used_identifiers.insert(identifier.clone());
+        names.insert(typed_identifier.identifier.clone(), identifier.clone());
+        TypedIdentifier {
+            identifier,
+            yul_type: typed_identifier.yul_type,
+        }
+    }
+
+    fn rename_block(
+        &mut self,
+        function_name: &str,
+        exprs: Vec<Expr>,
+        names: &mut HashMap<String, String>,
+    ) -> Vec<Expr> {
+        exprs
+            .into_iter()
+            .map(|expr| self.rename_expr(function_name, expr, names))
+            .collect()
+    }
+
+    fn rename_expr(
+        &mut self,
+        function_name: &str,
+        expr: Expr,
+        names: &mut HashMap<String, String>,
+    ) -> Expr {
+        let rename_identifier =
+            |identifier: String, names: &HashMap<String, String>| match names.get(&identifier) {
+                Some(name) => name.clone(),
+                None => identifier,
+            };
+        match expr {
+            Expr::Variable(ExprVariableReference {
+                identifier,
+                inferred_type,
+            }) => Expr::Variable(ExprVariableReference {
+                identifier: rename_identifier(identifier, names),
+                inferred_type,
+            }),
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name: called_function,
+                exprs,
+                inferred_return_types,
+                inferred_param_types,
+            }) => Expr::FunctionCall(ExprFunctionCall {
+                function_name: called_function,
+                exprs: Box::new(self.rename_block(function_name, *exprs, names)),
+                inferred_return_types,
+                inferred_param_types,
+            }),
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs,
+            }) => {
+                //the rhs can't refer to the variables it declares
+                let rhs = rhs.map(|rhs| Box::new(self.rename_expr(function_name, *rhs, names)));
+                Expr::DeclareVariable(ExprDeclareVariable {
+                    typed_identifiers: typed_identifiers
+                        .iter()
+                        .map(|ti| self.rename(function_name, ti, names))
+                        .collect(),
+                    rhs,
+                })
+            }
+            Expr::Assignment(ExprAssignment {
+                identifiers,
+                inferred_types,
+                rhs,
+            }) => Expr::Assignment(ExprAssignment {
+                identifiers: identifiers
+                    .into_iter()
+                    .map(|identifier| rename_identifier(identifier, names))
+                    .collect(),
+                inferred_types,
+                rhs: Box::new(self.rename_expr(function_name, *rhs, names)),
+            }),
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => Expr::IfStatement(ExprIfStatement {
+                first_expr: Box::new(self.rename_expr(function_name, *first_expr, names)),
+                second_expr: Box::new(ExprBlock {
+                    exprs: self.rename_block(function_name, second_expr.exprs, names),
+                }),
+            }),
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => {
+                let init_block = self.rename_block(function_name, init_block.exprs, names);
+                Expr::ForLoop(ExprForLoop {
+                    init_block: Box::new(ExprBlock { exprs: init_block }),
+                    conditional: Box::new(self.rename_expr(function_name, *conditional, names)),
+                    after_block: Box::new(ExprBlock {
+                        exprs: self.rename_block(function_name, after_block.exprs, names),
+                    }),
+                    interior_block: Box::new(ExprBlock {
+                        exprs: self.rename_block(function_name, interior_block.exprs, names),
+                    }),
+                })
+            }
+            Expr::Repeat(ExprRepeat {
+                interior_block,
+                iterations,
+            }) => Expr::Repeat(ExprRepeat {
+                iterations,
+                interior_block: Box::new(ExprBlock {
+                    exprs: self.rename_block(function_name, interior_block.exprs, names),
+                }),
+            }),
+            Expr::Block(ExprBlock { exprs }) => Expr::Block(ExprBlock {
+                exprs: self.rename_block(function_name, exprs, names),
+            }),
+            Expr::Switch(ExprSwitch {
+                default_case,
+                inferred_type,
+                expr,
+                cases,
+            }) => Expr::Switch(ExprSwitch {
+                expr: Box::new(self.rename_expr(function_name, *expr, names)),
+                inferred_type,
+                cases: cases
+                    .into_iter()
+                    .map(|case| ExprCase {
+                        literal: case.literal,
+                        block: ExprBlock {
+                            exprs: self.rename_block(function_name, case.block.exprs, names),
+                        },
+                    })
+                    .collect(),
+                default_case: default_case.map(|block| ExprBlock {
+                    exprs: self.rename_block(function_name, block.exprs, names),
+                }),
+            }),
+            expr => expr,
+        }
+    }
+}
+
+//The number of expressions in a block, used to decide whether a function is small enough to inline
+//Source markers are left out, so that they don't change which functions are inlined
+fn block_cost(exprs: &[Expr]) -> usize {
+    exprs
+        .iter()
+        .filter(
+            |expr| !matches!(expr, Expr::FunctionCall(op) if op.function_name == "__source_line"),
+        )
+        .map(expr_cost)
+        .sum()
+}
+
+fn expr_cost(expr: &Expr) -> usize {
+    1 + match expr {
+        Expr::FunctionCall(op) => block_cost(&op.exprs),
+        Expr::DeclareVariable(op) => op.rhs.as_ref().map_or(0, |rhs| expr_cost(rhs)),
+        Expr::Assignment(op) => expr_cost(&op.rhs),
+        Expr::IfStatement(op) => expr_cost(&op.first_expr) + block_cost(&op.second_expr.exprs),
+        Expr::ForLoop(op) => {
+            block_cost(&op.init_block.exprs)
+                + expr_cost(&op.conditional)
+                + block_cost(&op.after_block.exprs)
+                + block_cost(&op.interior_block.exprs)
+        }
+        Expr::Repeat(op) => block_cost(&op.interior_block.exprs),
+        Expr::Block(op) => block_cost(&op.exprs),
+        Expr::Switch(op) => {
+            expr_cost(&op.expr)
+                + op.cases
+                    .iter()
+                    .map(|case| block_cost(&case.block.exprs))
+                    .sum::<usize>()
+                + op.default_case
+                    .as_ref()
+                    .map_or(0, |block| block_cost(&block.exprs))
+        }
+        _ => 0,
+    }
+}
+
+//leave returns from the middle of a function, which an inlined body can't do. Nested function
+//definitions would have to be inlined along with it.
+fn can_inline_block(exprs: &[Expr]) -> bool {
+    exprs.iter().all(|expr| match expr {
+        Expr::Leave | Expr::FunctionDefinition(_) => false,
+        Expr::IfStatement(op) => can_inline_block(&op.second_expr.exprs),
+        Expr::ForLoop(op) => {
+            can_inline_block(&op.init_block.exprs)
+                && can_inline_block(&op.after_block.exprs)
+                && can_inline_block(&op.interior_block.exprs)
+        }
+        Expr::Repeat(op) => can_inline_block(&op.interior_block.exprs),
+        Expr::Block(op) => can_inline_block(&op.exprs),
+        Expr::Switch(op) => {
+            op.cases
+                .iter()
+                .all(|case| can_inline_block(&case.block.exprs))
+                && op
+                    .default_case
+                    .as_ref()
+                    .map_or(true, |block| can_inline_block(&block.exprs))
+        }
+        _ => true,
+    })
+}
+
+//Returns true if the block calls function_name, directly or through the functions it calls
+fn calls_function(
+    exprs: &[Expr],
+    function_name: &str,
+    definitions: &HashMap<String, ExprFunctionDefinition>,
+    visited: &mut HashSet<String>,
+) -> bool {
+    let mut called_functions = HashSet::new();
+    collect_called_functions(exprs, &mut called_functions);
+    called_functions.into_iter().any(|called_function| {
+        if called_function == function_name {
+            return true;
+        }
+        match definitions.get(&called_function) {
+            Some(definition) if visited.insert(called_function.clone()) => {
+                calls_function(&definition.block.exprs, function_name, definitions, visited)
+            }
+            _ => false,
+        }
+    })
+}
+
+//Returns the names of the functions called in a block, sorted so the order is deterministic
+pub(crate) fn called_functions(exprs: &[Expr]) -> Vec<String> {
+    let mut functions = HashSet::new();
+    collect_called_functions(exprs, &mut functions);
+    let mut functions = functions.into_iter().collect::<Vec<_>>();
+    functions.sort();
+    functions
+}
+
+fn collect_called_functions(exprs: &[Expr], functions: &mut HashSet<String>) {
+    for expr in exprs {
+        visit_children(expr, &mut |child| {
+            collect_called_functions(std::slice::from_ref(child), functions)
+        });
+        if let Expr::FunctionCall(op) = expr {
+            functions.insert(op.function_name.clone());
+        }
+    }
+}
+
+fn collect_identifiers(exprs: &[Expr], identifiers: &mut HashSet<String>) {
+    for expr in exprs {
+        visit_children(expr, &mut |child| {
+            collect_identifiers(std::slice::from_ref(child), identifiers)
+        });
+        match expr {
+            Expr::Variable(op) => {
+                identifiers.insert(op.identifier.clone());
+            }
+            Expr::DeclareVariable(op) => {
+                identifiers.extend(op.typed_identifiers.iter().map(|ti| ti.identifier.clone()))
+            }
+            Expr::Assignment(op) => identifiers.extend(op.identifiers.iter().cloned()),
+            Expr::FunctionDefinition(op) => identifiers.extend(
+                op.params
+                    .iter()
+                    .chain(op.returns.iter())
+                    .map(|ti| ti.identifier.clone()),
+            ),
+            _ => (),
+        }
+    }
+}
+
+//Calls visit on each expression directly inside expr
+fn visit_children(expr: &Expr, visit: &mut impl FnMut(&Expr)) {
+    fn visit_block(exprs: &[Expr], visit: &mut impl FnMut(&Expr)) {
+        for expr in exprs {
+            visit(expr)
+        }
+    }
+    match expr {
+        Expr::FunctionCall(op) => visit_block(&op.exprs, visit),
+        Expr::DeclareVariable(op) => {
+            if let Some(rhs) = &op.rhs {
+                visit(rhs)
+            }
+        }
+        Expr::Assignment(op) => visit(&op.rhs),
+        Expr::IfStatement(op) => {
+            visit(&op.first_expr);
+            visit_block(&op.second_expr.exprs, visit);
+        }
+        Expr::ForLoop(op) => {
+            visit_block(&op.init_block.exprs, visit);
+            visit(&op.conditional);
+            visit_block(&op.after_block.exprs, visit);
+            visit_block(&op.interior_block.exprs, visit);
+        }
+        Expr::Repeat(op) => visit_block(&op.interior_block.exprs, visit),
+        Expr::Block(op) => visit_block(&op.exprs, visit),
+        Expr::Switch(op) => {
+            visit(&op.expr);
+            for case in &op.cases {
+                visit_block(&case.block.exprs, visit);
+            }
+            if let Some(block) = &op.default_case {
+                visit_block(&block.exprs, visit);
+            }
+        }
+        Expr::FunctionDefinition(op) => visit_block(&op.block.exprs, visit),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{decode_stack, execute, ExecutionConfig};
+    use crate::miden_generator::{compile_program, CompileOptions};
+    use crate::parser::parse_yul_syntax;
+    use crate::type_inference::infer_types;
+    use primitive_types::U256;
+
+    fn inline(yul: &str) -> String {
+        let ast = infer_types(&parse_yul_syntax(yul));
+        expressions_to_tree(&inline_functions(ast, 16))
+    }
+
+    #[test]
+    fn inlines_small_functions() {
+        let tree = inline(
+            "function double(x) -> y {
+                let two := 2
+                y := mul(x, two)
+            }
+            let x := 3
+            let two := double(x)",
+        );
+        assert!(!tree.contains("double("));
+        assert!(!tree.contains("function definition"));
+        assert!(tree.contains("declare - double_x_0:u256"));
+        assert!(tree.contains("declare - double_two_0:u256"));
+        assert!(tree.contains("assign - double_y_0:u256"));
+    }
+
+    #[test]
+    fn keeps_recursive_functions() {
+        let tree = inline(
+            "function count(n) -> r {
+                if gt(n, 0) {
+                    r := add(count(sub(n, 1)), 1)
+                }
+            }
+            let r := count(3)",
+        );
+        assert!(tree.contains("function definition - count"));
+        assert!(tree.contains("count(u256): u256"));
+    }
+
+    #[test]
+    fn hoists_nested_functions() {
+        let ast = infer_types(&parse_yul_syntax(
+            "function outer(x) -> y {
+                function inner(a) -> b {
+                    b := add(a, 1)
+                }
+                y := inner(x)
+            }
+            let z := 0
+            {
+                function inner(a) -> b {
+                    b := mul(a, 2)
+                }
+                z := inner(outer(1))
+            }",
+        ));
+        let hoisted = hoist_functions(ast.clone());
+        let tree = expressions_to_tree(&hoisted);
+        assert!(tree.contains("function definition - outer_inner"));
+        assert!(tree.contains("function definition - inner"));
+        let outer_body = hoisted
+            .iter()
+            .find_map(|expr| match expr {
+                Expr::FunctionDefinition(op) if op.function_name == "outer" => {
+                    Some(op.block.exprs.clone())
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(called_functions(&outer_body), vec!["outer_inner"]);
+        let block = hoisted
+            .iter()
+            .filter(|expr| matches!(expr, Expr::Block(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(called_functions(&block), vec!["inner", "outer"]);
+        //outer calls its own inner, which adds 1, and the block calls the other one
+        let program = compile_program(ast, CompileOptions::default());
+        let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+        assert_eq!(
+            decode_stack(&program.outputs, &result.stack).unwrap(),
+            vec![YulValue::U256(U256::from(4))]
+        );
+    }
+
+    #[test]
+    fn inlining_keeps_the_order_of_side_effects() {
+        let yul = "function bump() -> n {
+                n := add(mload(0), 1)
+                mstore(0, n)
+            }
+            function pair(a, b) -> x, y {
+                x := a
+                y := b
+            }
+            function store(slot, v) {
+                mstore(mul(slot, 32), v)
+            }
+            for { let i := 2 } lt(i, 5) { i := add(i, 1) } {
+                store(i, add(mul(i, 10), bump()))
+            }
+            let p := 0
+            let q := 0
+            p, q := pair(bump(), bump())";
+        let run = |inline_threshold| {
+            let options = CompileOptions {
+                inline_threshold,
+                ..CompileOptions::default()
+            };
+            let layout = options.memory_layout.clone();
+            let program = compile_program(infer_types(&parse_yul_syntax(yul)), options);
+            let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+            (
+                decode_stack(&program.outputs, &result.stack).unwrap(),
+                result.memory.heap_bytes(&layout),
+            )
+        };
+        let (stack, heap) = run(16);
+        assert_eq!(run(0), (stack.clone(), heap));
+        //arguments are evaluated from last to first, so p gets the second bump. q is on top.
+        assert_eq!(
+            stack[..2],
+            [YulValue::U256(U256::from(4)), YulValue::U256(U256::from(5))]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeSet;
+
+use miden_core::StarkField;
+use miden_processor::ExecutionError;
+
+use crate::executor::{assemble_with_debug_info, ExecutionConfig, MidenError};
+use crate::miden_generator::{SourceMap, StackSlot};
+
+//Steps through the execution of a program compiled with source markers, one VM cycle at a time,
+//in either direction. The whole program is executed up front, so stepping back is free. Only what
+//the views need is kept for each cycle, since the states of the VM each hold a copy of memory.
+//
+//The running Yul line is read from the address that the source markers write to. Each marker also
+//has the transpiler's stack at the start of its statement in the source map, which labels the
+//concrete stack with the variables that sit in each element.
+pub struct Debugger {
+    cycles: Vec<Cycle>,
+    error: Option<ExecutionError>,
+    position: usize,
+    source_map: SourceMap,
+    breakpoints: BTreeSet<u32>,
+}
+
+//What the debugger keeps of one cycle
+struct Cycle {
+    clk: u32,
+    instruction: String,
+    //top first
+    stack: Vec<u64>,
+    //the running line, and how deep the stack was when that line started
+    line: Option<(u32, usize)>,
+    //whether a statement starts at this cycle, which happens every time a marker runs, so the same
+    //line starts again on each iteration of a loop
+    starts: bool,
+}
+
+//What the debugger shows for one cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugView {
+    pub clk: u32,
+    //the assembly instruction being executed, or the VM operation if there isn't one
+    pub instruction: String,
+    pub line: Option<u32>,
+    //the concrete stack, top first, along with the variable each element belongs to if it's known
+    pub stack: Vec<(u64, Option<String>)>,
+}
+
+impl Debugger {
+    pub fn new(
+        program: String,
+        config: &ExecutionConfig,
+        source_map: SourceMap,
+    ) -> Result<Self, MidenError> {
+        let program = assemble_with_debug_info(config.preload_memory(program))?;
+        let source_line = config.layout.source_line() as u64;
+        let marker = format!("pop.mem.{}", source_line);
+        let mut cycles = vec![];
+        let mut error = None;
+        let mut current: Option<(u32, usize)> = None;
+        let mut previous_instruction = None;
+        for state in miden_processor::execute_iter(
+            &program,
+            config.stack_inputs()?,
+            config.advice_provider()?,
+        ) {
+            let state = match state {
+                Ok(state) => state,
+                Err(execution_error) => {
+                    error = Some(execution_error);
+                    break;
+                }
+            };
+            let line = state
+                .memory
+                .iter()
+                .find(|(address, _)| *address == source_line)
+                .map(|(_, word)| word[0].as_int() as u32)
+                .filter(|line| *line != 0);
+            let changed = line != current.map(|(line, _)| line);
+            if changed {
+                current = line.map(|line| (line, state.stack.len()));
+            }
+            let asm_instruction = state.asmop.as_ref().map(|asmop| asmop.op().to_string());
+            //a marker takes several cycles, and only the first one starts the statement
+            let marker_started = asm_instruction.as_ref() == Some(&marker)
+                && previous_instruction != asm_instruction;
+            cycles.push(Cycle {
+                clk: state.clk,
+                instruction: match (&asm_instruction, &state.op) {
+                    (Some(instruction), _) => instruction.clone(),
+                    (None, Some(op)) => op.to_string(),
+                    (None, None) => "start".to_string(),
+                },
+                stack: state.stack.iter().map(|felt| felt.as_int()).collect(),
+                line: current,
+                starts: (changed && line.is_some()) || marker_started,
+            });
+            previous_instruction = asm_instruction;
+        }
+        if cycles.is_empty() {
+            return Err(MidenError::ExecutionError(
+                error.expect("Miden execution either runs a cycle or fails"),
+            ));
+        }
+
+        Ok(Debugger {
+            cycles,
+            error,
+            position: 0,
+            source_map,
+            breakpoints: BTreeSet::new(),
+        })
+    }
+
+    pub fn view(&self) -> DebugView {
+        let cycle = &self.cycles[self.position];
+        let labels = match cycle.line {
+            Some((line, depth)) => match self.source_map.get(&line) {
+                Some(slots) => label_stack(cycle.stack.len(), depth, slots),
+                None => vec![None; cycle.stack.len()],
+            },
+            None => vec![None; cycle.stack.len()],
+        };
+        DebugView {
+            clk: cycle.clk,
+            instruction: cycle.instruction.clone(),
+            line: self.line(),
+            stack: cycle.stack.iter().copied().zip(labels).collect(),
+        }
+    }
+
+    //The Yul line that is running, if a statement with a source marker has started
+    pub fn line(&self) -> Option<u32> {
+        self.cycles[self.position].line.map(|(line, _)| line)
+    }
+
+    //Moves forward one cycle, returning false at the end of the program
+    pub fn step(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        self.position += 1;
+        true
+    }
+
+    //Moves back one cycle, returning false at the start of the program
+    pub fn step_back(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        true
+    }
+
+    //Runs until the next statement starts, or to the end of the program
+    pub fn next_statement(&mut self) {
+        while self.step() && !self.cycles[self.position].starts {}
+    }
+
+    //Runs until a statement on a line with a breakpoint starts, returning the line, or to the end
+    //of the program
+    pub fn continue_to_breakpoint(&mut self) -> Option<u32> {
+        while self.step() {
+            if self.cycles[self.position].starts {
+                if let Some(line) = self.line().filter(|line| self.breakpoints.contains(line)) {
+                    return Some(line);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn add_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    //Returns false if there wasn't a breakpoint on the line
+    pub fn remove_breakpoint(&mut self, line: u32) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    //Whether the debugger is at the last cycle that ran
+    pub fn is_finished(&self) -> bool {
+        self.position + 1 == self.cycles.len()
+    }
+
+    //The error that stopped the program after the last cycle, if it failed
+    pub fn error(&self) -> Option<&ExecutionError> {
+        self.error.as_ref()
+    }
+}
+
+//Labels the elements of a stack that is depth elements deep with the variables of the
+//transpiler's stack at the start of the running statement, when the stack was statement_depth
+//deep. Whatever the statement pushed since sits on top. The limbs of values wider than one element
+//are numbered from the most significant one.
+fn label_stack(depth: usize, statement_depth: usize, slots: &[StackSlot]) -> Vec<Option<String>> {
+    let mut labels = vec![None; depth];
+    let mut index = match depth.checked_sub(statement_depth) {
+        Some(index) => index,
+        None => return labels,
+    };
+    for slot in slots {
+        let width = slot.yul_type.miden_stack_width() as usize;
+        for limb in 0..width {
+            if let (Some(label), Some(name)) = (labels.get_mut(index + limb), &slot.name) {
+                *label = Some(match width {
+                    1 => name.clone(),
+                    _ => format!("{}[{}]", name, limb),
+                });
+            }
+        }
+        index += width;
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miden_generator::{compile_program, CompileOptions};
+    use crate::parser::parse_yul_syntax_with_source_markers;
+    use crate::type_inference::infer_types;
+    use crate::types::YulType;
+
+    #[test]
+    fn step_through_statements() {
+        let yul = "let x := 1\nlet y := add(x, 2)\nlet z := add(y, 3)";
+        let program = compile_program(
+            infer_types(&parse_yul_syntax_with_source_markers(yul)),
+            CompileOptions::default(),
+        );
+        let mut debugger = Debugger::new(
+            program.masm,
+            &ExecutionConfig::default(),
+            program.source_map,
+        )
+        .unwrap();
+        assert_eq!(debugger.line(), None);
+        let mut lines = vec![];
+        while !debugger.is_finished() {
+            debugger.next_statement();
+            lines.extend(debugger.line());
+        }
+        lines.dedup();
+        assert_eq!(lines, vec![1, 2, 3]);
+        debugger.add_breakpoint(2);
+        while debugger.step_back() {}
+        assert_eq!(debugger.continue_to_breakpoint(), Some(2));
+        assert!(debugger.error().is_none());
+    }
+
+    #[test]
+    fn labels_the_stack_under_temporary_values() {
+        let slots = vec![
+            StackSlot {
+                name: Some("x".to_string()),
+                yul_type: YulType::U32,
+            },
+            StackSlot {
+                name: None,
+                yul_type: YulType::U32,
+            },
+            StackSlot {
+                name: Some("y".to_string()),
+                yul_type: YulType::U64,
+            },
+        ];
+        assert_eq!(
+            label_stack(7, 5, &slots),
+            vec![
+                None,
+                None,
+                Some("x".to_string()),
+                None,
+                Some("y[0]".to_string()),
+                Some("y[1]".to_string()),
+                None,
+            ]
+        );
+    }
+}
@@ -1,15 +1,425 @@
-use miden_processor::ExecutionTrace;
-pub use miden_processor::{ExecutionError, MemAdviceProvider, StackInputs};
+use std::collections::HashMap;
 
-//Compiles and executes a compiled Miden program, returning the stack and any Miden errors.
-//The program is passed in as a String, passed to the Miden Assembler, and then passed into the Miden Processor to be executed
-pub fn execute(program: String, _pub_inputs: Vec<u128>) -> Result<ExecutionTrace, MidenError> {
-    let program = miden_assembly::Assembler::default()
+use itertools::Itertools;
+
+use crate::memory_layout::MemoryLayout;
+use crate::miden_generator::{
+    CONTEXT_BUILTINS, LOG_ENTRY_SIZE, STATUS_INVALID, STATUS_REVERT, STATUS_RUNNING, STATUS_SUCCESS,
+};
+use crate::types::{YulType, YulValue};
+use crate::utils::{join_u32s_to_u256, split_u256_to_u32s};
+use miden_core::utils::{Deserializable, Serializable};
+use miden_core::{Felt, Program, StarkField};
+use miden_processor::{ExecutionTrace, VmState};
+use miden_prover::{Digest, ExecutionProof, ProofOptions, StackOutputs};
+use primitive_types::U256;
+
+pub use miden_processor::{AdviceInputs, ExecutionError, MemAdviceProvider, StackInputs};
+pub use miden_verifier::VerificationError;
+
+//Compiles and executes a Miden program, starting it from the inputs in the config. The program is
+//passed in as a String, passed to the Miden Assembler, and then passed into the Miden Processor to
+//be executed. Programs compiled with source markers report the line of the Yul statement that was
+//running when execution failed.
+pub fn execute(
+    program: String,
+    config: &ExecutionConfig,
+) -> Result<ExecutionResult, ExecutionFailure> {
+    let failure = |error| ExecutionFailure {
+        error,
+        source_line: None,
+    };
+    let program = config.preload_memory(program);
+    let program = assemble(program).map_err(failure)?;
+    let trace = miden_processor::execute(
+        &program,
+        config.stack_inputs().map_err(failure)?,
+        config.advice_provider().map_err(failure)?,
+    );
+    let trace = match trace {
+        Ok(trace) => trace,
+        Err(error) => {
+            //line 0 means no statement with a marker has run yet
+            let source_line = last_state(&program, config)
+                .map(|state| {
+                    MemorySnapshot::from_state(&state).read_u32(config.layout.source_line() as u64)
+                })
+                .filter(|line| *line != 0);
+            return Err(ExecutionFailure {
+                error: MidenError::ExecutionError(error),
+                source_line,
+            });
+        }
+    };
+    //the trace doesn't include memory, so it's read from the last state of a replay
+    let state =
+        last_state(&program, config).expect("Miden execution should have at least one cycle");
+    let trace = if config.trace { Some(trace) } else { None };
+    Ok(ExecutionResult {
+        stack: state.stack.iter().map(|felt| felt.as_int()).collect(),
+        memory: MemorySnapshot::from_state(&state),
+        cycles: state.clk,
+        trace,
+    })
+}
+
+//Replays a program one cycle at a time, returning the state of the last cycle that ran, before any
+//error
+fn last_state(program: &Program, config: &ExecutionConfig) -> Option<VmState> {
+    let stack_inputs = config.stack_inputs().ok()?;
+    let advice_provider = config.advice_provider().ok()?;
+    miden_processor::execute_iter(program, stack_inputs, advice_provider)
+        .map_while(Result::ok)
+        .last()
+}
+
+//Compiles and executes a transpiled Yul program, returning how it finished along with any data
+//passed to return or revert, and the logs it emitted
+pub fn execute_outcome(program: String) -> Result<ExecutionOutcome, MidenError> {
+    execute_with_context(program, &ExecutionContext::default())
+}
+
+//Like execute_outcome, but with the values that the environment builtins (caller(), timestamp(),
+//...) should return
+pub fn execute_with_context(
+    program: String,
+    context: &ExecutionContext,
+) -> Result<ExecutionOutcome, MidenError> {
+    execute_with_layout(program, context, &MemoryLayout::default())
+}
+
+//Like execute_with_context, for programs compiled with a different MemoryLayout
+pub fn execute_with_layout(
+    program: String,
+    context: &ExecutionContext,
+    layout: &MemoryLayout,
+) -> Result<ExecutionOutcome, MidenError> {
+    let config = ExecutionConfig::default()
+        .with_context(context)
+        .with_layout(layout.clone());
+    let result = execute(program, &config).map_err(|failure| failure.error)?;
+    ExecutionOutcome::from_memory(&result.memory, layout)
+}
+
+//Decodes the values a program left on the stack, given their types top first like
+//CompiledProgram::outputs, from a stack that is also top first like ExecutionResult::stack.
+//Values wider than one element have their most significant limb on top. Returns None if the stack
+//is too short for the outputs.
+pub fn decode_stack(outputs: &[YulType], stack: &[u64]) -> Option<Vec<YulValue>> {
+    let mut limbs = stack.iter().map(|limb| *limb as u32);
+    outputs
+        .iter()
+        .map(|yul_type| {
+            let width = yul_type.miden_stack_width() as usize;
+            let value = limbs.by_ref().take(width).collect::<Vec<_>>();
+            if value.len() < width {
+                return None;
+            }
+            let wide = value
+                .iter()
+                .fold(0u128, |wide, limb| (wide << 32) | *limb as u128);
+            Some(match yul_type {
+                YulType::U8 => YulValue::U8(value[0] as u8),
+                YulType::U32 => YulValue::U32(value[0]),
+                YulType::Bool => YulValue::Bool(value[0] != 0),
+                YulType::U64 => YulValue::U64(wide as u64),
+                YulType::U128 => YulValue::U128(wide),
+                YulType::U256 => YulValue::U256(join_u32s_to_u256(value)),
+                YulType::Address => YulValue::Address(join_u32s_to_u256(value)),
+            })
+        })
+        .collect()
+}
+
+//Like decode_stack, from the last state of an execution trace. The trace only keeps the top 16
+//elements of the stack, so outputs wider than that return None.
+pub fn decode_trace(outputs: &[YulType], trace: &ExecutionTrace) -> Option<Vec<YulValue>> {
+    let stack = trace
+        .last_stack_state()
+        .iter()
+        .map(|felt| felt.as_int())
+        .collect::<Vec<_>>();
+    decode_stack(outputs, &stack)
+}
+
+//Passes a Miden program to the Miden Assembler
+pub fn assemble(program: String) -> Result<Program, MidenError> {
+    miden_assembly::Assembler::default()
+        .compile(program)
+        .map_err(MidenError::AssemblyError)
+}
+
+//Like assemble, but keeps the assembly instruction behind each VM operation, for the debugger
+pub(crate) fn assemble_with_debug_info(program: String) -> Result<Program, MidenError> {
+    miden_assembly::Assembler::default()
+        .with_debug_mode(true)
         .compile(program)
-        .map_err(MidenError::AssemblyError)?;
+        .map_err(MidenError::AssemblyError)
+}
 
-    miden_processor::execute(&program, StackInputs::empty(), MemAdviceProvider::empty())
-        .map_err(MidenError::ExecutionError)
+//What a program starts with, built up like
+//ExecutionConfig::default().with_u256_stack_input(x).with_memory_word(100, [1, 0, 0, 0])
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionConfig {
+    //pushed in order, so the last one ends up on top
+    stack_inputs: Vec<u64>,
+    //read in order by adv_push
+    advice_stack: Vec<u64>,
+    advice_map: Vec<([u8; 32], Vec<u64>)>,
+    memory: Vec<(u32, [u64; 4])>,
+    //where the source line is read from when execution fails
+    pub(crate) layout: MemoryLayout,
+    trace: bool,
+}
+
+impl ExecutionConfig {
+    //Pushes a value to the stack before the program starts
+    pub fn with_stack_input(mut self, value: u64) -> Self {
+        self.stack_inputs.push(value);
+        self
+    }
+
+    //Pushes a u256 to the stack before the program starts, laid out like the transpiler lays out
+    //u256 values, with the most significant limb on top
+    pub fn with_u256_stack_input(mut self, value: U256) -> Self {
+        self.stack_inputs
+            .extend(split_u256_to_u32s(&value).into_iter().rev().map(u64::from));
+        self
+    }
+
+    //Adds values to the end of the advice stack
+    pub fn with_advice_stack(mut self, values: impl IntoIterator<Item = u64>) -> Self {
+        self.advice_stack.extend(values);
+        self
+    }
+
+    //Adds the values of the environment builtins and the calldata to the end of the advice stack,
+    //where transpiled programs read them from
+    pub fn with_context(self, context: &ExecutionContext) -> Self {
+        self.with_advice_stack(context.advice_stack())
+    }
+
+    //Adds an entry to the advice map
+    pub fn with_advice_map_entry(mut self, key: [u8; 32], values: Vec<u64>) -> Self {
+        self.advice_map.push((key, values));
+        self
+    }
+
+    //Stores a word in memory before the program starts. Miden has no way to start with memory
+    //already set, so the program is prefixed with the instructions that store it, which take the
+    //cycles that preload_cycles counts.
+    pub fn with_memory_word(mut self, address: u32, word: [u64; 4]) -> Self {
+        self.memory.push((address, word));
+        self
+    }
+
+    pub fn with_layout(mut self, layout: MemoryLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    //Also returns the execution trace
+    pub fn with_trace(mut self) -> Self {
+        self.trace = true;
+        self
+    }
+
+    //Fails if an input is too big to be a field element
+    pub(crate) fn stack_inputs(&self) -> Result<StackInputs, MidenError> {
+        StackInputs::try_from_values(self.stack_inputs.iter().copied())
+            .map_err(|error| MidenError::InvalidInput(format!("bad stack inputs: {}", error)))
+    }
+
+    //Fails if a value on the advice stack or in the advice map is too big to be a field element
+    pub(crate) fn advice_provider(&self) -> Result<MemAdviceProvider, MidenError> {
+        let map_values = self.advice_map.iter().flat_map(|(_, values)| values);
+        if let Some(value) = map_values.copied().find(|value| *value >= Felt::MODULUS) {
+            return Err(MidenError::InvalidInput(format!(
+                "the advice map value {} isn't a field element",
+                value
+            )));
+        }
+        let advice_map = self
+            .advice_map
+            .iter()
+            .map(|(key, values)| (*key, values.iter().map(|value| Felt::new(*value)).collect()));
+        let advice_inputs = AdviceInputs::default()
+            .with_stack_values(self.advice_stack.iter().copied())
+            .map_err(|error| MidenError::InvalidInput(format!("bad advice stack: {}", error)))?
+            .with_map(advice_map);
+        Ok(MemAdviceProvider::from(advice_inputs))
+    }
+
+    //How many cycles the stores of the preloaded memory words take. Miden pushes 1 with two
+    //operations and any other value with one, and popw.mem pushes the address, stores the word and
+    //drops it. The NOOPs that Miden sometimes pads spans with aren't counted.
+    pub fn preload_cycles(&self) -> u32 {
+        let push = |value: u64| if value == 1 { 2 } else { 1 };
+        self.memory
+            .iter()
+            .map(|(address, word)| {
+                word.iter().map(|value| push(*value)).sum::<u32>() + push(*address as u64) + 5
+            })
+            .sum()
+    }
+
+    //Adds the stores of the preloaded memory words to the start of the program's begin block
+    pub(crate) fn preload_memory(&self, program: String) -> String {
+        if self.memory.is_empty() {
+            return program;
+        }
+        let stores = self
+            .memory
+            .iter()
+            .map(|(address, word)| {
+                format!(
+                    "\npush.{}.{}.{}.{} popw.mem.{}",
+                    word[0], word[1], word[2], word[3], address
+                )
+            })
+            .collect::<String>();
+        let begin = program
+            .lines()
+            .scan(0, |position, line| {
+                let start = *position;
+                *position += line.len() + 1;
+                Some((start, line))
+            })
+            .find(|(_, line)| line.trim() == "begin")
+            .map(|(start, line)| start + line.len())
+            .expect("the program has a begin block");
+        let mut program = program;
+        program.insert_str(begin, &stores);
+        program
+    }
+}
+
+//The state of the VM after a program ran
+pub struct ExecutionResult {
+    //the stack after the last cycle, top first
+    pub stack: Vec<u64>,
+    pub memory: MemorySnapshot,
+    pub cycles: u32,
+    //only kept if the config asked for it
+    pub trace: Option<ExecutionTrace>,
+}
+
+//Why a program failed, and the line of the Yul statement that was running if the program was
+//compiled with source markers
+#[derive(Debug)]
+pub struct ExecutionFailure {
+    pub error: MidenError,
+    pub source_line: Option<u32>,
+}
+
+//Executes a transpiled Yul program with Miden's STARK prover, returning a proof of the execution.
+//The advice inputs, such as the environment and the calldata, stay private, while the stack inputs
+//and outputs are public. The outcome of the program, its return data, logs and storage are kept in
+//memory, so the proof doesn't commit to them, only to what the program leaves on the stack.
+pub fn prove(program: String, config: &ExecutionConfig) -> Result<ProgramProof, MidenError> {
+    let program = assemble(config.preload_memory(program))?;
+    let (stack_outputs, proof) = miden_prover::prove(
+        &program,
+        config.stack_inputs()?,
+        config.advice_provider()?,
+        ProofOptions::default(),
+    )
+    .map_err(MidenError::ExecutionError)?;
+    Ok(ProgramProof {
+        program_hash: program.hash().to_bytes(),
+        stack_inputs: config.stack_inputs.clone(),
+        stack_outputs: stack_outputs.stack().to_vec(),
+        overflow_addrs: stack_outputs.overflow_addrs().to_vec(),
+        proof: proof.to_bytes(),
+    })
+}
+
+//Checks a proof without running the program again
+pub fn verify(proof: &ProgramProof) -> Result<(), MidenError> {
+    let program_hash = Digest::read_from_bytes(&proof.program_hash)
+        .map_err(|error| MidenError::InvalidProof(format!("bad program hash: {}", error)))?;
+    let execution_proof = ExecutionProof::from_bytes(&proof.proof)
+        .map_err(|error| MidenError::InvalidProof(format!("bad proof: {}", error)))?;
+    let stack_inputs = StackInputs::try_from_values(proof.stack_inputs.iter().copied())
+        .map_err(|error| MidenError::InvalidProof(format!("bad stack inputs: {}", error)))?;
+    let stack_outputs =
+        StackOutputs::new(proof.stack_outputs.clone(), proof.overflow_addrs.clone());
+    miden_verifier::verify(program_hash, stack_inputs, stack_outputs, execution_proof)
+        .map(|_| ())
+        .map_err(MidenError::VerificationError)
+}
+
+//Returns the hash that proofs of the program are checked against
+pub fn program_hash(program: String) -> Result<Vec<u8>, MidenError> {
+    Ok(assemble(program)?.hash().to_bytes())
+}
+
+//Proof files start with this, since the outcome of the run isn't part of the proof
+const PROOF_NOTE: &str = "# this proof only covers the stack inputs and outputs, not the status, \
+return data, logs or storage of the run";
+
+//A proof that a program ran with the given stack inputs and ended with the given stack outputs.
+//It says nothing about the status, return data, logs or storage of the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramProof {
+    pub program_hash: Vec<u8>,
+    pub stack_inputs: Vec<u64>,
+    pub stack_outputs: Vec<u64>,
+    pub overflow_addrs: Vec<u64>,
+    pub proof: Vec<u8>,
+}
+
+impl ProgramProof {
+    //Writes the proof as text, one field per line, with the bytes in hex. The first line is a note
+    //on what the proof covers, which decode skips.
+    pub fn encode(&self) -> String {
+        let numbers = |values: &[u64]| values.iter().join(",");
+        format!(
+            "{}\nprogram_hash: {}\nstack_inputs: {}\nstack_outputs: {}\noverflow_addrs: {}\nproof: {}\n",
+            PROOF_NOTE,
+            hex::encode(&self.program_hash),
+            numbers(&self.stack_inputs),
+            numbers(&self.stack_outputs),
+            numbers(&self.overflow_addrs),
+            hex::encode(&self.proof)
+        )
+    }
+
+    //Reads a proof written by encode
+    pub fn decode(text: &str) -> Result<Self, MidenError> {
+        let fields = text
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .collect::<HashMap<_, _>>();
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .copied()
+                .ok_or_else(|| MidenError::InvalidProof(format!("missing {}", name)))
+        };
+        let bytes = |name: &str| {
+            hex::decode(field(name)?)
+                .map_err(|_| MidenError::InvalidProof(format!("{} isn't valid hex", name)))
+        };
+        let numbers = |name: &str| {
+            field(name)?
+                .split(',')
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value.parse().map_err(|_| {
+                        MidenError::InvalidProof(format!("{} isn't a list of numbers", name))
+                    })
+                })
+                .collect::<Result<Vec<u64>, _>>()
+        };
+        Ok(ProgramProof {
+            program_hash: bytes("program_hash")?,
+            stack_inputs: numbers("stack_inputs")?,
+            stack_outputs: numbers("stack_outputs")?,
+            overflow_addrs: numbers("overflow_addrs")?,
+            proof: bytes("proof")?,
+        })
+    }
 }
 
 //Errors that are returned from the Miden processor during execution.
@@ -17,6 +427,259 @@ pub fn execute(program: String, _pub_inputs: Vec<u128>) -> Result<ExecutionTrace
 pub enum MidenError {
     AssemblyError(miden_assembly::AssemblyError),
     ExecutionError(ExecutionError),
+    VerificationError(VerificationError),
+    //a proof file that couldn't be read
+    InvalidProof(String),
+    //a status code in the outcome region that the transpiler never writes
+    UnknownStatus(u32),
+    //a stack input or advice value that isn't a field element
+    InvalidInput(String),
+}
+
+//The block a transaction is executed in, for the environment builtins
+#[derive(Debug, Clone, Default)]
+pub struct BlockContext {
+    pub number: U256,
+    pub timestamp: U256,
+    pub chainid: U256,
+    pub coinbase: U256,
+}
+
+//The transaction being executed, for the environment builtins
+#[derive(Debug, Clone, Default)]
+pub struct TxContext {
+    pub caller: U256,
+    pub callvalue: U256,
+    pub address: U256,
+    pub origin: U256,
+    //read by calldataload and calldatasize
+    pub calldata: Vec<u8>,
+}
+
+//Everything the environment builtins can read
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    pub block: BlockContext,
+    pub tx: TxContext,
+}
+
+impl ExecutionContext {
+    //Returns the value of an environment builtin, such as "caller", or None if there is no such
+    //builtin
+    pub fn value(&self, builtin: &str) -> Option<U256> {
+        CONTEXT_BUILTINS
+            .iter()
+            .position(|name| *name == builtin)
+            .map(|i| self.values()[i])
+    }
+
+    //The values of the environment builtins, in the order of CONTEXT_BUILTINS
+    fn values(&self) -> [U256; 8] {
+        [
+            self.tx.caller,
+            self.tx.callvalue,
+            self.tx.address,
+            self.tx.origin,
+            self.block.timestamp,
+            self.block.number,
+            self.block.chainid,
+            self.block.coinbase,
+        ]
+    }
+
+    //Returns the values of every environment builtin in the order they are read from the advice
+    //stack, with the limbs of each u256 going from least to most significant so that the most
+    //significant limb ends up on top of the Miden stack. The size of the calldata and its bytes
+    //come after them.
+    pub fn advice_stack(&self) -> Vec<u64> {
+        self.values()
+            .iter()
+            .flat_map(|value| split_u256_to_u32s(value).into_iter().rev().map(u64::from))
+            .chain(std::iter::once(self.tx.calldata.len() as u64))
+            .chain(self.tx.calldata.iter().map(|byte| *byte as u64))
+            .collect()
+    }
+}
+
+//How a Yul program finished. Programs that call return or stop, or that fall off the end of
+//their code, succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    Success,
+    Revert,
+    Invalid,
+}
+
+//The result of running a transpiled Yul program, following the output convention used by
+//return(offset, size) and revert(offset, size). The return data is read from the Yul heap after
+//execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionOutcome {
+    pub status: ExecutionStatus,
+    pub return_data: Vec<u8>,
+    pub logs: Vec<Log>,
+    //the storage keys the program used and their values, in the order they were first used
+    pub storage: Vec<(U256, U256)>,
+}
+
+//An event emitted by log0 through log4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+impl ExecutionOutcome {
+    //Reads the status code and return data that the program left in the outcome region of memory.
+    //Fails if the status code isn't one the transpiler writes, e.g. when the program wasn't
+    //compiled with the same layout.
+    pub fn from_memory(memory: &MemorySnapshot, layout: &MemoryLayout) -> Result<Self, MidenError> {
+        let outcome_address = layout.outcome as u64;
+        let status = match memory.read_u32(outcome_address) {
+            STATUS_RUNNING | STATUS_SUCCESS => ExecutionStatus::Success,
+            STATUS_REVERT => ExecutionStatus::Revert,
+            STATUS_INVALID => ExecutionStatus::Invalid,
+            code => return Err(MidenError::UnknownStatus(code)),
+        };
+        let offset = memory.read_u32(outcome_address + 1);
+        let size = memory.read_u32(outcome_address + 2);
+        Ok(ExecutionOutcome {
+            status,
+            return_data: memory.read_heap_bytes(layout, offset, size),
+            logs: decode_logs(memory, layout),
+            storage: decode_storage(memory, layout),
+        })
+    }
+}
+
+//Decodes the log buffer that log0 through log4 append to. See MemoryLayout::logs for the layout.
+pub fn decode_logs(memory: &MemorySnapshot, layout: &MemoryLayout) -> Vec<Log> {
+    let log_count = memory.read_u32(layout.logs as u64);
+    (0..log_count)
+        .map(|i| {
+            let entry_address = (layout.logs + 1 + i * LOG_ENTRY_SIZE) as u64;
+            let topic_count = memory.read_u32(entry_address) as u64;
+            let data_address = memory.read_u32(entry_address + 1) as u64;
+            let size = memory.read_u32(entry_address + 2);
+            Log {
+                topics: (0..topic_count)
+                    .map(|topic| memory.read_u256(entry_address + 3 + 2 * topic))
+                    .collect(),
+                data: memory.read_bytes(data_address, size),
+            }
+        })
+        .collect()
+}
+
+//Decodes the storage region that sstore and sload use. See MemoryLayout::storage for the layout.
+pub fn decode_storage(memory: &MemorySnapshot, layout: &MemoryLayout) -> Vec<(U256, U256)> {
+    let key_count = memory.read_u32(layout.storage as u64) as u64;
+    (0..key_count)
+        .map(|i| {
+            let entry_address = layout.storage as u64 + 1 + 4 * i;
+            (
+                memory.read_u256(entry_address),
+                memory.read_u256(entry_address + 2),
+            )
+        })
+        .collect()
+}
+
+//The contents of Miden memory at some point during execution, keyed by address.
+//Addresses that were never written to read as zero.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySnapshot(HashMap<u64, [u64; 4]>);
+
+impl MemorySnapshot {
+    pub fn from_state(state: &VmState) -> Self {
+        MemorySnapshot(
+            state
+                .memory
+                .iter()
+                .map(|(address, word)| (*address, word.map(|felt| felt.as_int())))
+                .collect(),
+        )
+    }
+
+    //The addresses that were written to, in order
+    pub fn addresses(&self) -> Vec<u64> {
+        self.0.keys().copied().sorted().collect()
+    }
+
+    pub fn read_word(&self, address: u64) -> [u64; 4] {
+        self.0.get(&address).cloned().unwrap_or_default()
+    }
+
+    //Reads a u32 saved with pop.mem, which stores the value in the first element of the word
+    pub fn read_u32(&self, address: u64) -> u32 {
+        self.read_word(address)[0] as u32
+    }
+
+    //Reads a u256 saved with two popw.mem instructions. The most significant half of the value is
+    //at address, and the least significant half is at address + 1, with the limbs in each word
+    //going from least to most significant.
+    pub fn read_u256(&self, address: u64) -> U256 {
+        let limbs = self
+            .read_word(address + 1)
+            .iter()
+            .chain(self.read_word(address).iter())
+            .map(|limb| *limb as u32)
+            .rev()
+            .collect::<Vec<_>>();
+        join_u32s_to_u256(limbs)
+    }
+
+    //Reads the Yul heap up to the end of the last slot that was written to
+    pub fn heap_bytes(&self, layout: &MemoryLayout) -> Vec<u8> {
+        let heap = layout.heap as u64..layout.calldata as u64;
+        let slots = self
+            .0
+            .keys()
+            .filter(|address| heap.contains(address))
+            .map(|address| (address - heap.start) / 2 + 1)
+            .max()
+            .unwrap_or(0);
+        self.read_heap_bytes(layout, 0, (32 * slots) as u32)
+    }
+
+    //Reads a value that the transpiler moved to memory, laid out like
+    //pop_top_stack_value_to_memory stores it. u64 values keep their most significant limb at
+    //address and the other at address + 1.
+    pub fn read_value(&self, address: u64, yul_type: YulType) -> YulValue {
+        let word = self.read_word(address);
+        match yul_type {
+            YulType::U8 => YulValue::U8(word[0] as u8),
+            YulType::U32 => YulValue::U32(word[0] as u32),
+            YulType::Bool => YulValue::Bool(word[0] != 0),
+            YulType::U64 => YulValue::U64((word[0] << 32) | self.read_word(address + 1)[0]),
+            YulType::U128 => YulValue::U128(
+                word.iter()
+                    .rev()
+                    .fold(0u128, |value, limb| (value << 32) | *limb as u128),
+            ),
+            YulType::U256 => YulValue::U256(self.read_u256(address)),
+            YulType::Address => YulValue::Address(self.read_u256(address)),
+        }
+    }
+
+    //Reads size bytes of the Yul heap, starting at the given mstore slot
+    pub fn read_heap_bytes(&self, layout: &MemoryLayout, slot: u32, size: u32) -> Vec<u8> {
+        self.read_bytes(layout.heap as u64 + 2 * slot as u64, size)
+    }
+
+    //Reads size bytes laid out like the Yul heap, starting at address. Every two addresses hold a
+    //u256, which is read as a big-endian 32 byte word like in the EVM.
+    pub fn read_bytes(&self, address: u64, size: u32) -> Vec<u8> {
+        let slots = (size as u64 + 31) / 32;
+        let mut bytes = Vec::new();
+        for i in 0..slots {
+            let mut word = [0u8; 32];
+            self.read_u256(address + 2 * i).to_big_endian(&mut word);
+            bytes.extend_from_slice(&word);
+        }
+        bytes.truncate(size as usize);
+        bytes
+    }
 }
 
 #[ignore]
@@ -31,12 +694,170 @@ fn debug_execution() {
         end
         "##
         .to_string(),
-        vec![],
+        &ExecutionConfig::default(),
     )
     .unwrap();
 
     println!("Miden Output");
-    let stack = execution_value.last_stack_state();
+    let stack = execution_value.stack;
     dbg!(&stack);
     let _last_stack_value = stack.first().unwrap();
 }
+
+#[test]
+fn decode_typed_stack_values() {
+    let stack = [1, 0, 0, 0, 0, 0, 0, 7, 9, 5, 2, 0, 0];
+    assert_eq!(
+        decode_stack(&[YulType::U256, YulType::U32, YulType::U64], &stack),
+        Some(vec![
+            YulValue::U256((U256::one() << 224) + 7),
+            YulValue::U32(9),
+            YulValue::U64((5 << 32) + 2),
+        ])
+    );
+    assert_eq!(decode_stack(&[YulType::U256, YulType::U256], &stack), None);
+}
+
+#[test]
+fn read_u256_from_memory_words() {
+    let mut memory = MemorySnapshot::default();
+    memory.0.insert(10, [5, 6, 7, 8]);
+    memory.0.insert(11, [1, 2, 3, 4]);
+    let expected = (1..=8u64).fold(U256::zero(), |acc, limb| {
+        acc + (U256::from(limb) << (32 * (limb - 1)))
+    });
+    assert_eq!(memory.read_u256(10), expected);
+}
+
+#[test]
+fn read_typed_values_from_memory() {
+    let mut memory = MemorySnapshot::default();
+    memory.0.insert(0, [300, 0, 0, 0]);
+    memory.0.insert(1, [5, 0, 0, 0]);
+    memory.0.insert(2, [1, 2, 3, 4]);
+    assert_eq!(memory.read_value(0, YulType::U8), YulValue::U8(44));
+    assert_eq!(memory.read_value(0, YulType::U32), YulValue::U32(300));
+    assert_eq!(memory.read_value(0, YulType::Bool), YulValue::Bool(true));
+    assert_eq!(memory.read_value(9, YulType::Bool), YulValue::Bool(false));
+    assert_eq!(
+        memory.read_value(0, YulType::U64),
+        YulValue::U64((300 << 32) + 5)
+    );
+    assert_eq!(
+        memory.read_value(2, YulType::U128),
+        YulValue::U128((4 << 96) + (3 << 64) + (2 << 32) + 1)
+    );
+    assert_eq!(
+        memory.read_value(1, YulType::U256),
+        YulValue::U256(memory.read_u256(1))
+    );
+}
+
+#[test]
+fn encode_and_decode_proofs() {
+    let proof = ProgramProof {
+        program_hash: vec![1, 2, 3],
+        stack_inputs: vec![],
+        stack_outputs: vec![5, 0, 7],
+        overflow_addrs: vec![],
+        proof: vec![255, 0],
+    };
+    assert_eq!(ProgramProof::decode(&proof.encode()).unwrap(), proof);
+}
+
+#[test]
+fn prove_and_verify_a_program() {
+    let program = "begin\n  push.3 push.4 add\nend".to_string();
+    let proof = prove(program.clone(), &ExecutionConfig::default()).unwrap();
+    assert_eq!(proof.stack_outputs[0], 7);
+    assert_eq!(proof.program_hash, program_hash(program).unwrap());
+    let proof = ProgramProof::decode(&proof.encode()).unwrap();
+    verify(&proof).unwrap();
+    let forged = ProgramProof {
+        stack_outputs: [vec![8], proof.stack_outputs[1..].to_vec()].concat(),
+        ..proof
+    };
+    assert!(verify(&forged).is_err());
+}
+
+#[test]
+fn decode_storage_entries() {
+    let layout = MemoryLayout::default();
+    let storage = layout.storage as u64;
+    let mut memory = MemorySnapshot::default();
+    memory.0.insert(storage, [2, 0, 0, 0]);
+    memory.0.insert(storage + 2, [7, 0, 0, 0]);
+    memory.0.insert(storage + 4, [9, 0, 0, 0]);
+    memory.0.insert(storage + 6, [1, 0, 0, 0]);
+    memory.0.insert(storage + 8, [3, 0, 0, 0]);
+    assert_eq!(
+        decode_storage(&memory, &layout),
+        vec![
+            (U256::from(7), U256::from(9)),
+            (U256::from(1), U256::from(3)),
+        ]
+    );
+}
+
+#[test]
+fn preload_memory_at_the_start_of_begin() {
+    let config = ExecutionConfig::default().with_memory_word(7, [1, 2, 3, 4]);
+    assert_eq!(
+        config.preload_memory("proc.f\n  push.1\nend\nbegin\n  exec.f\nend".to_string()),
+        "proc.f\n  push.1\nend\nbegin\npush.1.2.3.4 popw.mem.7\n  exec.f\nend"
+    );
+}
+
+#[test]
+fn count_the_cycles_of_preloaded_memory() {
+    let config = ExecutionConfig::default()
+        .with_memory_word(7, [1, 2, 3, 4])
+        .with_memory_word(1, [0, 0, 0, 5]);
+    assert_eq!(config.preload_cycles(), (5 + 1 + 5) + (4 + 2 + 5));
+    assert_eq!(ExecutionConfig::default().preload_cycles(), 0);
+}
+
+#[test]
+fn unknown_status_is_an_error() {
+    let layout = MemoryLayout::default();
+    let mut memory = MemorySnapshot::default();
+    memory.0.insert(layout.outcome as u64, [9, 0, 0, 0]);
+    assert!(matches!(
+        ExecutionOutcome::from_memory(&memory, &layout),
+        Err(MidenError::UnknownStatus(9))
+    ));
+}
+
+#[test]
+fn look_up_environment_builtins() {
+    let mut context = ExecutionContext::default();
+    context.block.chainid = U256::from(5);
+    assert_eq!(context.value("chainid"), Some(U256::from(5)));
+    assert_eq!(context.value("blockhash"), None);
+    assert_eq!(context.advice_stack().len(), 8 * CONTEXT_BUILTINS.len() + 1);
+}
+
+#[test]
+fn inputs_have_to_be_field_elements() {
+    let program = "begin\n  push.1\nend".to_string();
+    let failure = |config: ExecutionConfig| execute(program.clone(), &config).err().unwrap().error;
+    assert!(matches!(
+        failure(ExecutionConfig::default().with_stack_input(u64::MAX)),
+        MidenError::InvalidInput(_)
+    ));
+    assert!(matches!(
+        failure(ExecutionConfig::default().with_advice_stack([u64::MAX])),
+        MidenError::InvalidInput(_)
+    ));
+    assert!(matches!(
+        failure(ExecutionConfig::default().with_advice_map_entry([0; 32], vec![u64::MAX])),
+        MidenError::InvalidInput(_)
+    ));
+    assert!(matches!(
+        prove(
+            program,
+            &ExecutionConfig::default().with_stack_input(u64::MAX)
+        ),
+        Err(MidenError::InvalidInput(_))
+    ));
+}
@@ -1,7 +1,11 @@
 pub mod ast_optimization;
+pub mod debugger;
 pub mod executor;
+pub mod memory_layout;
 pub mod miden_generator;
 pub mod parser;
+pub mod range_analysis;
+pub mod recursion;
 pub mod type_inference;
 pub mod types;
 pub mod utils;
@@ -0,0 +1,214 @@
+use crate::miden_generator::{CONTEXT_BUILTINS, LOG_ENTRY_SIZE};
+
+//Miden addresses go up to 2^32 - 1
+const MEMORY_SIZE: u64 = 1 << 32;
+
+//Miden keeps the locals of procs, both the transpiled ones and those of the standard library, in
+//memory starting at 2^30, and the frame pointer can't go past 3 * 2^30. None of the regions can
+//overlap them.
+const PROC_LOCALS_ADDRESS: u32 = 1 << 30;
+const PROC_LOCALS_SIZE: u64 = 1 << 31;
+
+//Where the regions of Miden memory that a transpiled program uses start. Each region runs until
+//the next one starts, so the bases have to be in the order the fields are declared, and the
+//regions from context on have to come after the proc locals. The defaults leave the variables of
+//the top level code 1024 addresses, and the heap, calldata, the frames of recursive functions and
+//storage 2^28 addresses each.
+//
+//The executor reads the outcome, the logs and the heap back after execution, so programs
+//compiled with a different layout have to be executed with the same one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryLayout {
+    //variables of the top level code that don't fit on the stack. Procs keep theirs in locals.
+    pub variables: u32,
+    //the Yul heap that mstore and mload use. Slot n takes up two addresses, with the most
+    //significant half of its u256 at heap + 2n and the rest at heap + 2n + 1.
+    pub heap: u32,
+    //the size of the calldata, followed by one byte per address
+    pub calldata: u32,
+    //the frame pointer and the stack pointer, followed by the frames of recursive functions
+    pub frames: u32,
+    //the number of storage keys used, followed by four addresses for each key: the key, then its
+    //value, both u256s
+    pub storage: u32,
+    //the values of the environment builtins, two addresses each, in the order of CONTEXT_BUILTINS
+    pub context: u32,
+    //the status code, the heap slot and byte length of the return data, and the line of the Yul
+    //statement that is running, for programs compiled with source markers
+    pub outcome: u32,
+    //the number of logs emitted, followed by LOG_ENTRY_SIZE addresses for each log: the number of
+    //topics, the address of its data, the size of the data in bytes, then up to four u256 topics
+    pub logs: u32,
+    //log data is copied out of the heap when the log is emitted, since the heap can change
+    //afterwards. The number of addresses used comes first, followed by the data of each log.
+    pub log_data: u32,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        let context = 3 << 30;
+        let outcome = context + 2 * CONTEXT_BUILTINS.len() as u32;
+        MemoryLayout {
+            variables: 0,
+            heap: 1024,
+            calldata: 1 << 28,
+            frames: 1 << 29,
+            storage: 3 << 28,
+            context,
+            outcome,
+            logs: outcome + 4,
+            log_data: outcome + (1 << 24),
+        }
+    }
+}
+
+//A region of memory and how many of its addresses the program is known to need
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub name: &'static str,
+    pub start: u64,
+    pub end: u64,
+    pub required: u64,
+}
+
+impl MemoryLayout {
+    //The address that stores where the current frame starts, as an offset from the first frame
+    pub fn frame_pointer(&self) -> u32 {
+        self.frames
+    }
+
+    //The address that stores where the next frame would start, as an offset from the first frame
+    pub fn stack_pointer(&self) -> u32 {
+        self.frames + 1
+    }
+
+    //Where the first frame of a recursive function starts
+    pub fn first_frame(&self) -> u32 {
+        self.frames + 2
+    }
+
+    //Where programs compiled with source markers store the line of the running statement
+    pub fn source_line(&self) -> u32 {
+        self.outcome + 3
+    }
+
+    //How many mstore slots fit in the heap
+    pub fn heap_slots(&self) -> u32 {
+        (self.calldata - self.heap) / 2
+    }
+
+    //How many log entries fit before the log data starts
+    pub fn max_logs(&self) -> u32 {
+        (self.log_data - self.logs - 1) / LOG_ENTRY_SIZE
+    }
+
+    //Returns the regions in order, given how many addresses the variables of the top level code
+    //use. Panics if a region doesn't fit before the next one starts.
+    pub fn plan(&self, variable_addresses: u32) -> Vec<MemoryRegion> {
+        let regions = [
+            ("variables", self.variables, variable_addresses as u64),
+            ("heap", self.heap, 0),
+            ("calldata", self.calldata, 1),
+            ("frames", self.frames, 2),
+            ("storage", self.storage, 1),
+            ("proc locals", PROC_LOCALS_ADDRESS, PROC_LOCALS_SIZE),
+            ("context", self.context, 2 * CONTEXT_BUILTINS.len() as u64),
+            ("outcome", self.outcome, 4),
+            ("logs", self.logs, 1 + LOG_ENTRY_SIZE as u64),
+            ("log data", self.log_data, 1),
+        ];
+        regions
+            .iter()
+            .enumerate()
+            .map(|(i, (name, start, required))| {
+                let start = *start as u64;
+                let end = regions
+                    .get(i + 1)
+                    .map_or(MEMORY_SIZE, |(_, next_start, _)| *next_start as u64);
+                if end < start + required {
+                    panic!(
+                        "the {} region needs {} addresses starting at {}, but the next region starts at {}",
+                        name, required, start, end
+                    );
+                }
+                MemoryRegion {
+                    name,
+                    start,
+                    end,
+                    required: *required,
+                }
+            })
+            .collect()
+    }
+
+    //Describes the layout, one region per line
+    pub fn report(&self, variable_addresses: u32) -> String {
+        self.plan(variable_addresses)
+            .iter()
+            .map(|region| {
+                let used = match region.name {
+                    "variables" => format!(", {} used", region.required),
+                    _ => String::new(),
+                };
+                format!(
+                    "{}: {}..{} ({} addresses{})",
+                    region.name,
+                    region.start,
+                    region.end,
+                    region.end - region.start,
+                    used
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_the_default_layout() {
+        let regions = MemoryLayout::default().plan(10);
+        assert_eq!(regions[0].name, "variables");
+        assert_eq!((regions[0].start, regions[0].end), (0, 1024));
+        assert_eq!(regions[1].end, MemoryLayout::default().calldata as u64);
+        assert_eq!(regions.last().unwrap().end, MEMORY_SIZE);
+    }
+
+    #[test]
+    fn reserves_the_proc_locals() {
+        let regions = MemoryLayout::default().plan(0);
+        let locals = regions
+            .iter()
+            .find(|region| region.name == "proc locals")
+            .unwrap();
+        assert_eq!((locals.start, locals.end), (1 << 30, 3 << 30));
+        assert_eq!(MemoryLayout::default().heap_slots(), ((1 << 28) - 1024) / 2);
+    }
+
+    #[test]
+    fn log_entries_stop_before_the_log_data() {
+        let layout = MemoryLayout::default();
+        let last_entry = layout.logs + 1 + (layout.max_logs() - 1) * LOG_ENTRY_SIZE;
+        assert!(last_entry + LOG_ENTRY_SIZE <= layout.log_data);
+        assert!(last_entry + 2 * LOG_ENTRY_SIZE > layout.log_data);
+    }
+
+    #[test]
+    #[should_panic(expected = "the storage region needs 1 addresses")]
+    fn regions_cant_overlap_the_proc_locals() {
+        MemoryLayout {
+            storage: 1 << 30,
+            ..MemoryLayout::default()
+        }
+        .plan(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "the variables region needs 2000 addresses")]
+    fn variables_cant_overflow_into_the_heap() {
+        MemoryLayout::default().plan(2000);
+    }
+}
@@ -4,23 +4,69 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use include_dir::{include_dir, Dir};
 use primitive_types::U256;
 
-use crate::{ast_optimization::optimize_ast, types::*, utils::convert_u256_to_pushes};
+use crate::{
+    ast_optimization::{called_functions, hoist_functions, inline_functions, optimize_ast},
+    memory_layout::MemoryLayout,
+    recursion::lower_recursive_functions,
+    types::*,
+    utils::{convert_bytes_to_u256, convert_u256_to_pushes, split_u256_to_u32s},
+};
+
+//Status codes stored at the start of MemoryLayout::outcome. A program that is still running, or
+//that fell off the end of its code, leaves the status at STATUS_RUNNING
+pub const STATUS_RUNNING: u32 = 0;
+pub const STATUS_SUCCESS: u32 = 1;
+pub const STATUS_REVERT: u32 = 2;
+pub const STATUS_INVALID: u32 = 3;
+
+//How many addresses each entry of the log buffer takes up, see MemoryLayout::logs
+pub const LOG_ENTRY_SIZE: u32 = 11;
+
+//Environment builtins, in the order their values are laid out in MemoryLayout::context. Each
+//value is a u256 and takes up two addresses. The executor passes the values in on the advice
+//stack, and they are saved to memory at the start of the program.
+pub const CONTEXT_BUILTINS: [&str; 8] = [
+    "caller",
+    "callvalue",
+    "address",
+    "origin",
+    "timestamp",
+    "number",
+    "chainid",
+    "coinbase",
+];
+
+//Yul builtins that end execution
+const HALTING_BUILTINS: [&str; 4] = ["return", "revert", "stop", "invalid"];
+
+//Yul builtins that emit a log, indexed by their number of topics
+const LOG_BUILTINS: [&str; 5] = ["log0", "log1", "log2", "log3", "log4"];
 
 //Struct that enables transpilation management. Through implementations, this struct keeps track of the variables,
 //open memory addresses, the stack, indentation of Miden assembly and user defined functions.
+#[derive(Clone)]
 struct Transpiler {
     options: CompileOptions,
     variables: HashMap<TypedIdentifier, u32>,
     indentation: u32,
     next_open_memory_address: u32,
+    //the number of locals used by the proc being transpiled, or None in the top level code. Procs
+    //keep their variables in locals, so that calls can't overwrite each other's variables.
+    locals: Option<u32>,
     stack: Stack,
     program: String,
     user_functions: HashMap<String, Stack>,
     scoped_identifiers: HashMap<String, TypedIdentifier>,
     branches: VecDeque<Branch>,
     accept_overflow: bool,
-    memory_offset: u64,
+    layout: MemoryLayout,
     procs_used: HashSet<String>,
+    halting_functions: HashSet<String>,
+    context_used: bool,
+    calldata_used: bool,
+    source_map: SourceMap,
+    //things the Yul program does that Miden can only approximate, for the caller to report
+    warnings: Vec<String>,
 }
 
 //A branch is a temporary represnetation of a stack to keep track of where variables are
@@ -66,6 +112,18 @@ impl From<&TypedIdentifier> for StackValue {
     }
 }
 
+//A value on the stack at the start of a Yul statement, as the transpiler sees it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackSlot {
+    //the variable the value belongs to, or None for temporary values
+    pub name: Option<String>,
+    pub yul_type: YulType,
+}
+
+//The stack at the start of each Yul statement, top first, keyed by the line of the statement. Only
+//programs compiled with source markers have one.
+pub type SourceMap = HashMap<u32, Vec<StackSlot>>;
+
 //Struct to represent the stack, consisting of a Vec of Stack Values
 #[derive(Default, Clone)]
 struct Stack(Vec<StackValue>);
@@ -97,6 +155,30 @@ impl std::fmt::Debug for Stack {
 //FIXME: Add a note about how the transpiler variables, stack ect do not cross over with miden. They are just to keep track of
 //what the miden stack/memory will look like so we essentially need to states, one for miden and one for the transpiler
 impl Transpiler {
+    fn new(options: CompileOptions) -> Self {
+        let layout = options.memory_layout.clone();
+        Transpiler {
+            next_open_memory_address: layout.variables,
+            layout,
+            options,
+            variables: HashMap::new(),
+            locals: None,
+            indentation: 0,
+            stack: Stack::default(),
+            scoped_identifiers: HashMap::new(),
+            program: "".to_string(),
+            user_functions: HashMap::default(),
+            branches: VecDeque::new(),
+            accept_overflow: false,
+            procs_used: HashSet::new(),
+            halting_functions: HashSet::new(),
+            context_used: false,
+            calldata_used: false,
+            source_map: SourceMap::new(),
+            warnings: vec![],
+        }
+    }
+
     //Function to indent the Miden assembly by four spaces.
     //Ex.
     // if.true
@@ -263,21 +345,36 @@ impl Transpiler {
             .get(&typed_identifier)
             .cloned()
             .unwrap_or_else(|| panic!("{} not found in memory", &typed_identifier.identifier));
-        self.push_from_memory_to_top_of_stack(address, &typed_identifier.yul_type);
+        let segment = self.variable_segment();
+        self.push_from_memory_to_top_of_stack(segment, address, &typed_identifier.yul_type);
         self.stack.0.first_mut().unwrap().typed_identifier = Some(typed_identifier);
     }
 
-    //Push a value from memory to the top of the stack.
-    //If the element is a u32, it takes up one 32bit element of one memory address. When the
-    //element is a u256, it takes up two addresses completely.
-    fn push_from_memory_to_top_of_stack(&mut self, address: u32, yul_type: &YulType) {
+    //Push a value from memory, or from the locals of the proc if the segment is "local", to the
+    //top of the stack.
+    //If the element is a u32, it takes up one 32bit element of one memory address. A u64 is
+    //saved as two u32 values, a u128 takes up one address completely, and when the element is a
+    //u256, it takes up two addresses completely.
+    fn push_from_memory_to_top_of_stack(
+        &mut self,
+        segment: &str,
+        address: u32,
+        yul_type: &YulType,
+    ) {
         match yul_type {
-            YulType::U32 => {
-                self.add_line(&format!("push.mem.{}", address));
+            YulType::U8 | YulType::U32 | YulType::Bool => {
+                self.add_line(&format!("push.{}.{}", segment, address));
+            }
+            YulType::U64 => {
+                self.add_line(&format!("push.{}.{}", segment, address + 1));
+                self.add_line(&format!("push.{}.{}", segment, address));
             }
-            YulType::U256 => {
-                self.add_line(&format!("pushw.mem.{}", address + 1));
-                self.add_line(&format!("pushw.mem.{}", address));
+            YulType::U128 => {
+                self.add_line(&format!("pushw.{}.{}", segment, address));
+            }
+            YulType::U256 | YulType::Address => {
+                self.add_line(&format!("pushw.{}.{}", segment, address + 1));
+                self.add_line(&format!("pushw.{}.{}", segment, address));
             }
         }
         self.stack.0.insert(
@@ -290,6 +387,50 @@ impl Transpiler {
         self.newline();
     }
 
+    //Pushes the address of a frame slot. The slot is the first argument of the frame builtins.
+    fn transpile_frame_address(&mut self, op: &ExprFunctionCall) {
+        self.transpile_op(&op.exprs[0]);
+        self.convert_top(YulType::U32);
+        let pointer = match op.function_name.starts_with("__frame") {
+            true => self.layout.frame_pointer(),
+            false => self.layout.stack_pointer(),
+        };
+        self.add_line(&format!(
+            "push.mem.{} add add.{}",
+            pointer,
+            self.layout.first_frame()
+        ));
+    }
+
+    //Replaces the address on top of the stack with the value stored there, laid out like
+    //push_from_memory_to_top_of_stack
+    fn load_from_address_on_stack(&mut self, yul_type: YulType) {
+        match yul_type {
+            YulType::U8 | YulType::U32 | YulType::Bool => self.add_line("push.mem"),
+            YulType::U64 => self.add_line("dup add.1 push.mem swap push.mem"),
+            YulType::U128 => self.add_line("pushw.mem"),
+            YulType::U256 | YulType::Address => {
+                self.add_line("dup add.1 pushw.mem movup.4 pushw.mem")
+            }
+        }
+        self._consume_top_stack_values(1);
+        self.add_unknown(yul_type);
+    }
+
+    //Stores the value below the address on top of the stack at that address, laid out like
+    //pop_top_stack_value_to_memory
+    fn store_to_address_on_stack(&mut self, yul_type: YulType) {
+        match yul_type {
+            YulType::U8 | YulType::U32 | YulType::Bool => self.add_line("pop.mem"),
+            YulType::U64 => self.add_line("dup movdn.3 pop.mem swap add.1 pop.mem"),
+            YulType::U128 => self.add_line("popw.mem"),
+            YulType::U256 | YulType::Address => {
+                self.add_line("dup movdn.5 popw.mem add.1 popw.mem")
+            }
+        }
+        self._consume_top_stack_values(2);
+    }
+
     //FIXME: Still needs comments
 
     //not pushing but duping to the top, offset is just where the value starts
@@ -299,12 +440,26 @@ impl Transpiler {
     }
 
     //TODO: explain the function and then explain what an offset is
+    //Values wider than one element are duplicated one limb at a time, starting with the deepest,
+    //unless they line up with a word and can be duplicated with dupw
     fn dup_from_offset(&mut self, offset: u32, yul_type: YulType) {
         match yul_type {
-            YulType::U32 => {
+            YulType::U8 | YulType::U32 | YulType::Bool => {
                 self.add_line(&format!("dup.{}", offset));
             }
-            YulType::U256 => match offset + 7 {
+            YulType::U64 => {
+                self.add_line(&format!("dup.{}", offset + 1));
+                self.add_line(&format!("dup.{}", offset + 1));
+            }
+            YulType::U128 => match offset {
+                0 | 4 | 8 | 12 => self.add_line(&format!("dupw.{}", offset / 4)),
+                o => {
+                    for _ in 0..4 {
+                        self.add_line(&format!("dup.{}", o + 3));
+                    }
+                }
+            },
+            YulType::U256 | YulType::Address => match offset + 7 {
                 7 => {
                     self.add_line("dupw.1");
                     self.add_line("dupw.1");
@@ -326,6 +481,24 @@ impl Transpiler {
         };
     }
 
+    //Function to push a u64 or u128 value on both the miden stack and our stack, with the
+    //least significant limb pushed first so that the most significant limb ends up on top
+    fn push_limbs(&mut self, value: U256, yul_type: YulType) {
+        self.prepare_for_stack_values(&yul_type);
+        self.add_unknown(yul_type);
+        self.add_comment(&format!("{} literal {}", yul_type, value));
+        let limbs = split_u256_to_u32s(&value);
+        self.add_line(
+            &limbs
+                .iter()
+                .rev()
+                .take(yul_type.miden_stack_width() as usize)
+                .map(|limb| format!("push.{}", limb))
+                .join(" "),
+        );
+        self.newline();
+    }
+
     //Function to push a u32 value on both the miden stack and our stack
     fn push(&mut self, value: U256) {
         self.prepare_for_stack_values(&YulType::U32);
@@ -394,7 +567,7 @@ impl Transpiler {
 
     fn move_from_offset(&mut self, offset: u32, yul_type: YulType) {
         match yul_type {
-            YulType::U32 => match offset {
+            YulType::U8 | YulType::U32 | YulType::Bool => match offset {
                 0 => {}
                 1 => {
                     self.add_line("swap");
@@ -403,7 +576,16 @@ impl Transpiler {
                     self.add_line(&format!("movup.{}", n));
                 }
             },
-            YulType::U256 => {
+            //like dup_from_offset, the limbs are moved up one at a time starting with the deepest
+            YulType::U64 | YulType::U128 => {
+                if offset > 0 {
+                    let width = yul_type.miden_stack_width();
+                    for _ in 0..width {
+                        self.add_line(&format!("movup.{}", offset + width - 1));
+                    }
+                }
+            }
+            YulType::U256 | YulType::Address => {
                 match offset {
                     1 => {
                         self.add_line("movdn.8");
@@ -422,7 +604,8 @@ impl Transpiler {
         }
     }
 
-    //Function to remove the top stack value from the stack and save it into memory.
+    //Function to remove the top stack value from the stack and save it into memory, or into the
+    //locals of the proc being transpiled.
     //If the variable is already stored in memory, the transpiler will update the value. Else, the
     //transpiler will get the next available memory address. If the value is a u256,
     //mem.pop.address and mem.pop.address+1 can be used to pop two words into memory
@@ -437,11 +620,11 @@ impl Transpiler {
             {
                 Some(address) => *address,
                 None => {
-                    let address = self.next_open_memory_address;
+                    let address =
+                        self.allocate_memory(stack_value.yul_type.miden_memory_addresses());
                     if let Some(ref typed_identifier) = stack_value.typed_identifier {
                         self.variables.insert(typed_identifier.clone(), address);
                     }
-                    self.next_open_memory_address += stack_value.yul_type.miden_memory_addresses();
                     address
                 }
             },
@@ -455,19 +638,51 @@ impl Transpiler {
                 .unwrap_or_else(|| "unknown".to_string())
         ));
         self.stack.0.remove(0);
+        let segment = self.variable_segment();
         match stack_value.yul_type {
-            YulType::U32 => {
-                self.add_line(&format!("pop.mem.{}", address));
+            YulType::U8 | YulType::U32 | YulType::Bool => {
+                self.add_line(&format!("pop.{}.{}", segment, address));
+            }
+            YulType::U64 => {
+                self.add_line(&format!("pop.{}.{}", segment, address));
+                self.add_line(&format!("pop.{}.{}", segment, address + 1));
             }
-            YulType::U256 => {
-                self.add_line(&format!("popw.mem.{}", address));
-                self.add_line(&format!("popw.mem.{}", address + 1));
+            YulType::U128 => {
+                self.add_line(&format!("popw.{}.{}", segment, address));
+            }
+            YulType::U256 | YulType::Address => {
+                self.add_line(&format!("popw.{}.{}", segment, address));
+                self.add_line(&format!("popw.{}.{}", segment, address + 1));
             }
         }
         self.newline();
         address
     }
 
+    //Returns the first of n free addresses for variables. Procs get them from their locals, which
+    //are sized once the proc has been transpiled, and the top level code from global memory.
+    fn allocate_memory(&mut self, n: u32) -> u32 {
+        match self.locals.as_mut() {
+            Some(locals) => {
+                *locals += n;
+                *locals - n
+            }
+            None => {
+                self.next_open_memory_address += n;
+                self.next_open_memory_address - n
+            }
+        }
+    }
+
+    //Where variables are stored: the locals of the proc, or memory in the top level code
+    fn variable_segment(&self) -> &'static str {
+        if self.locals.is_some() {
+            "local"
+        } else {
+            "mem"
+        }
+    }
+
     //Return the size of the stack, accounting for u256 values taking up 8 values.
     fn get_size_of_stack(&self) -> u32 {
         self.stack
@@ -477,6 +692,65 @@ impl Transpiler {
             .sum()
     }
 
+    //Converts the value on top of the stack to another type, if it isn't that type already.
+    //Bools are a single element that is 0 or 1, so turning a number into a bool checks whether it
+    //is zero. Since the most significant limb is on top, widening a value pushes zeroed limbs
+    //above it, and narrowing drops the limbs on top, keeping the least significant ones. Narrowing
+    //to a u8 or an address also clears the bits above the new width.
+    fn convert_top(&mut self, yul_type: YulType) {
+        let from = match self.stack.0.first() {
+            Some(stack_value) if stack_value.yul_type != yul_type => stack_value.yul_type,
+            _ => return,
+        };
+        self.add_comment(&format!("converting {} to {}", from, yul_type));
+        let from_width = from.miden_stack_width();
+        let to_width = yul_type.miden_stack_width();
+        match (from, yul_type) {
+            (YulType::Bool, _) if to_width == 1 => {}
+            (_, YulType::Bool) => match from {
+                YulType::U256 | YulType::Address => {
+                    self.add_proc_exec("u256::iszero_unsafe");
+                    self.add_line("not");
+                }
+                _ => {
+                    self.add_line("neq.0");
+                    for _ in 1..from_width {
+                        self.add_line("swap neq.0 or");
+                    }
+                }
+            },
+            _ if from_width < to_width => {
+                self.prepare_for_stack_values(&yul_type);
+                match to_width - from_width {
+                    4 => self.add_line("padw"),
+                    n => self.add_line(&"push.0 ".repeat(n as usize)),
+                }
+            }
+            _ => {
+                match from_width - to_width {
+                    0 => {}
+                    4 => self.add_line("dropw"),
+                    n => self.add_line(&"drop ".repeat(n as usize)),
+                }
+                match yul_type {
+                    YulType::U8 => self.add_line("push.255 u32checked_and"),
+                    //the top three limbs of an address are always zero
+                    YulType::Address => self.add_line("drop drop drop push.0 push.0 push.0"),
+                    _ => {}
+                }
+            }
+        }
+        self.stack.0.first_mut().unwrap().yul_type = yul_type;
+    }
+
+    //Transpiles the arguments of a function call, converting each of them to yul_type
+    fn transpile_function_args_as(&mut self, op: &ExprFunctionCall, yul_type: YulType) {
+        for expr in op.exprs.iter() {
+            self.transpile_op(expr);
+            self.convert_top(yul_type);
+        }
+    }
+
     //Push a u256 value to the stack. See convert_u256_to_pushes for more details on how u256 segments are pushed.
     fn push_u256(&mut self, value: U256) {
         self.prepare_for_stack_values(&YulType::U256);
@@ -503,7 +777,8 @@ impl Transpiler {
     //For example if the stack is [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
     // drop_after(10) will result [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0]
     fn drop_after_returns(&mut self, returns: Vec<TypedIdentifier>) {
-        let mut address = 0;
+        //the return values are saved to memory of their own while the rest of the stack is dropped
+        let mut address = self.allocate_memory(2 * returns.len() as u32);
         for _ in &returns {
             self.pop_top_stack_value_to_memory(Some(address));
             address += 2;
@@ -521,7 +796,8 @@ impl Transpiler {
         self.stack.0 = vec![];
         for function_return in returns {
             address -= 2;
-            self.push_from_memory_to_top_of_stack(address, &function_return.yul_type);
+            let segment = self.variable_segment();
+            self.push_from_memory_to_top_of_stack(segment, address, &function_return.yul_type);
         }
     }
 
@@ -534,13 +810,19 @@ impl Transpiler {
         stack_value.typed_identifier = Some(typed_identifier);
     }
 
+    //Assigns the values a function call returned to variables. The last return value is on top.
+    fn top_values_are_vars(&mut self, typed_identifiers: &[TypedIdentifier]) {
+        for (i, typed_identifier) in typed_identifiers.iter().rev().enumerate() {
+            self.stack.0[i].typed_identifier = Some(typed_identifier.clone());
+        }
+    }
+
     //Modifies the stack to include values from developer written functions in Yul
     //For example, if someone were to write a function called return_two_numbers() that returns two values,
     //when that function is called, we have to modify our stack by pushing those two numbers to the top of the stack.
     fn add_function_stack(&mut self, function_stack: &Stack) {
-        let mut new_stack = Stack::default();
-        new_stack.0.append(&mut self.stack.0.clone());
-        new_stack.0.append(&mut function_stack.0.clone());
+        let mut new_stack = function_stack.clone();
+        new_stack.0.append(&mut self.stack.0);
         self.stack = new_stack;
     }
 
@@ -556,14 +838,18 @@ impl Transpiler {
     //Transpile a variable declaration.
     //Ex. let x := 1000 or let x:u256 := 1000
     fn transpile_variable_declaration(&mut self, op: &ExprDeclareVariable) {
-        assert_eq!(op.typed_identifiers.len(), 1);
         for typed_identifier in &op.typed_identifiers {
             self.scoped_identifiers.insert(
                 typed_identifier.identifier.clone(),
                 typed_identifier.clone(),
             );
         }
-        assert_eq!(op.typed_identifiers.len(), 1);
+        if op.typed_identifiers.len() > 1 {
+            self.add_comment("Assigning to multiple values");
+            self.transpile_op(op.rhs.as_ref().expect("multiple variables need a value"));
+            self.top_values_are_vars(&op.typed_identifiers);
+            return;
+        }
         self.add_comment(&format!(
             "Assigning to {}",
             op.typed_identifiers.first().unwrap().identifier
@@ -571,6 +857,7 @@ impl Transpiler {
         self.indent();
         if let Some(rhs) = &op.rhs {
             self.transpile_op(rhs);
+            self.convert_top(op.typed_identifiers.first().unwrap().yul_type);
             self.top_is_var(op.typed_identifiers.first().unwrap().clone());
         }
         self.outdent();
@@ -583,8 +870,22 @@ impl Transpiler {
     // pub inferred_types: Vec<Option<YulType>>, (ie. data types)
     // pub rhs: Box<Expr>, (right hand side of :=)
     fn transpile_assignment(&mut self, op: &ExprAssignment) {
-        // TODO: more than one identifier in assignment
-        assert_eq!(op.identifiers.len(), 1);
+        if op.identifiers.len() > 1 {
+            let typed_identifiers = op
+                .identifiers
+                .iter()
+                .map(|identifier| self.get_typed_identifier(identifier).clone())
+                .collect::<Vec<_>>();
+            if let Some(branch) = self.branches.front_mut() {
+                branch
+                    .modified_identifiers
+                    .extend(typed_identifiers.iter().cloned());
+            }
+            self.add_comment("Assigning to multiple values");
+            self.transpile_op(&op.rhs);
+            self.top_values_are_vars(&typed_identifiers);
+            return;
+        }
         let typed_identifier = self
             .get_typed_identifier(op.identifiers.first().unwrap())
             .clone();
@@ -596,6 +897,7 @@ impl Transpiler {
 
         //Transpiles the right hand side expression and pushes the expr to the top
         self.transpile_op(&op.rhs);
+        self.convert_top(typed_identifier.yul_type);
         //Assigns the top stack value (right hand side of the expression) to the variable name passed in as typed_identifier
         self.top_is_var(typed_identifier);
         self.outdent();
@@ -603,11 +905,46 @@ impl Transpiler {
 
     //Transpile a block. Loops through an ExprBlock which is a Vec of expressions and transpiles each expression.
     fn transpile_block(&mut self, op: &ExprBlock) {
-        for op in &op.exprs {
+        self.transpile_statements(&op.exprs);
+    }
+
+    //Transpile a list of statements. Once a statement that can halt execution has been transpiled
+    //(ie. a call to return, or to a function that calls revert), the rest of the statements are
+    //wrapped in a check that execution is still running, since Miden has no way to stop early.
+    fn transpile_statements(&mut self, exprs: &[Expr]) {
+        for (i, op) in exprs.iter().enumerate() {
             self.transpile_op(op);
+            let rest = &exprs[i + 1..];
+            if !rest.is_empty() && may_halt(op, &self.halting_functions) {
+                self.transpile_if_still_running(rest);
+                return;
+            }
         }
     }
 
+    //Transpiles statements that should only run if nothing before them has halted execution
+    fn transpile_if_still_running(&mut self, exprs: &[Expr]) {
+        self.add_comment("skipping the rest of the block if execution has halted");
+        let outcome = self.layout.outcome;
+        self.add_line(&format!("push.mem.{} eq.{}", outcome, STATUS_RUNNING));
+        self.add_line("if.true");
+        self.indent();
+        self.begin_branch();
+        self.transpile_statements(exprs);
+        self.end_branch();
+        self.outdent();
+        self.add_line("end");
+    }
+
+    //Combines the loop condition on top of the stack with a check that execution hasn't halted,
+    //so that loops stop iterating after a return or revert in their body
+    fn and_still_running(&mut self) {
+        self.add_line(&format!(
+            "push.mem.{} eq.{} and",
+            self.layout.outcome, STATUS_RUNNING
+        ));
+    }
+
     //Transpile a for loop. A for loop is made up of an init block, a conditional, an after block
     // and an interior block. Under the hood, while.true evaluates the top value of the stack so we must push the result of the conditional
     // after each loop. Here is an example of each block.
@@ -622,9 +959,15 @@ impl Transpiler {
     //After block: { i := add(i, 1)}
     //Interior Block: {if lt(i, 2) { mstore(i, 1)}}
     fn transpile_for_loop(&mut self, op: &ExprForLoop) {
+        let body_may_halt = block_may_halt(&op.interior_block, &self.halting_functions)
+            || block_may_halt(&op.after_block, &self.halting_functions);
         self.transpile_block(&op.init_block);
         self.add_comment("-- conditional --");
         self.transpile_op(&op.conditional);
+        self.convert_top(YulType::Bool);
+        if body_may_halt {
+            self.and_still_running();
+        }
         self.add_line("while.true");
         // Because the while.true will consume the top of the stack
         self._consume_top_stack_values(1);
@@ -648,6 +991,10 @@ impl Transpiler {
         self.add_comment("-- conditional --");
         self.indent();
         self.transpile_op(&op.conditional);
+        self.convert_top(YulType::Bool);
+        if body_may_halt {
+            self.and_still_running();
+        }
         self.outdent();
         self.newline();
         self._consume_top_stack_values(1);
@@ -680,7 +1027,7 @@ impl Transpiler {
         //Define a variable to keep in the transpiler scoped identifiers that represents if the switch expression has been matched
         let transpiler_switch_matched_bool = TypedIdentifier {
             identifier: "switch_matched".to_string(),
-            yul_type: YulType::U32,
+            yul_type: YulType::Bool,
         };
 
         //Add the switch_matched variable to the transpiler's known variables
@@ -691,11 +1038,11 @@ impl Transpiler {
 
         //Push 0 on the Miden stack. This is the equivalent of pushing the switch_matched variable
         self.add_comment("keeping track of whether we've hit any cases");
-        self.prepare_for_stack_values(&YulType::U32);
+        self.prepare_for_stack_values(&YulType::Bool);
         self.add_line("push.0");
 
         //push an unknown on transpiler stack
-        self.add_unknown(YulType::U32);
+        self.add_unknown(YulType::Bool);
         //declare that top value in stack is the switch_matched variable
         self.top_is_var(transpiler_switch_matched_bool.clone());
 
@@ -769,82 +1116,315 @@ impl Transpiler {
     fn transpile_miden_function(&mut self, op: &ExprFunctionCall) {
         self.add_comment(&format!("{}()", op.function_name));
 
+        if HALTING_BUILTINS.contains(&op.function_name.as_str()) {
+            self.transpile_halt(op);
+            return;
+        }
+
+        if let Some(index) = CONTEXT_BUILTINS
+            .iter()
+            .position(|builtin| *builtin == op.function_name)
+        {
+            self.context_used = true;
+            self.prepare_for_stack_values(&YulType::U256);
+            self.push_from_memory_to_top_of_stack(
+                "mem",
+                self.layout.context + 2 * index as u32,
+                &YulType::U256,
+            );
+            return;
+        }
+
+        if op.function_name == "gas" {
+            self.transpile_gas();
+            return;
+        }
+
         if let Some(function_stack) = self.user_functions.clone().get(&op.function_name) {
-            self.transpile_function_args(op);
+            //arguments are evaluated from right to left, so the first argument ends up on top,
+            //where the proc expects its first param
+            for (expr, param_type) in op.exprs.iter().zip(&op.inferred_param_types).rev() {
+                self.transpile_op(expr);
+                if let Some(param_type) = param_type {
+                    self.convert_top(*param_type);
+                }
+            }
             self.add_line(&format!("exec.{}", op.function_name));
+            self._consume_top_stack_values(op.exprs.len() as u32);
             self.add_function_stack(function_stack);
             return;
         }
 
-        match (
-            op.inferred_param_types.first().unwrap(),
-            op.function_name.as_ref(),
-        ) {
+        //user functions named like a log builtin shadow it
+        if let Some(topics) = LOG_BUILTINS
+            .iter()
+            .position(|name| *name == op.function_name)
+        {
+            self.transpile_log(op, topics as u32);
+            return;
+        }
+
+        //Arguments of different widths are converted to the widest of them before the operation.
+        //u128 operations are done on u256 values, and the result is narrowed afterwards.
+        let op_type = op
+            .inferred_param_types
+            .iter()
+            .flatten()
+            .copied()
+            .reduce(YulType::common_type);
+        let arg_type = op_type.unwrap_or(YulType::U32);
+
+        match (op_type, op.function_name.as_ref()) {
+            //checked conversion, which fails unless the upper limbs of the u256 are all zero
+            (_, "u256_to_u32" | "__u256_to_u32") => {
+                self.transpile_function_args_as(op, YulType::U256);
+                self.add_line(&"assertz ".repeat(7));
+                self._consume_top_stack_values(1);
+                self.add_unknown(YulType::U32);
+            }
+            //keeps the least significant limb, without checking the others
+            (_, "u256_to_u32_truncating") => {
+                self.transpile_function_args_as(op, YulType::U256);
+                self.convert_top(YulType::U32);
+            }
+            (_, "u32_to_u256") => {
+                self.transpile_function_args_as(op, YulType::U32);
+                self.convert_top(YulType::U256);
+            }
+
+            //frames of recursive functions, see recursion.rs. Slots in the current frame are read
+            //with __frame_load and __frame_store, and slots in the frame of the function that is
+            //being called, or has just returned, with __callee_load and __callee_store.
+            (_, "__frame_load" | "__callee_load") => {
+                self.transpile_frame_address(op);
+                self.load_from_address_on_stack(op.inferred_return_types[0].unwrap());
+            }
+            (_, "__frame_store" | "__callee_store") => {
+                let yul_type = op.inferred_param_types[1].unwrap();
+                self.transpile_op(&op.exprs[1]);
+                self.convert_top(yul_type);
+                self.transpile_frame_address(op);
+                self.store_to_address_on_stack(yul_type);
+            }
+            (_, "__frame_push") => {
+                let size = match &op.exprs[0] {
+                    Expr::Literal(ExprLiteral::Number(ExprLiteralNumber { value, .. })) => {
+                        value.as_u32()
+                    }
+                    _ => unreachable!("frame sizes are known at compile time"),
+                };
+                //the frames run until the storage region starts
+                let frames_size = self.layout.storage - self.layout.first_frame();
+                self.add_comment("pushing a frame, which saves the frame pointer in its header");
+                self.add_line(&format!("push.mem.{}", self.layout.stack_pointer()));
+                self.add_line(&format!(
+                    "dup push.mem.{} swap add.{} pop.mem",
+                    self.layout.frame_pointer(),
+                    self.layout.first_frame() + 1
+                ));
+                self.add_line(&format!("dup pop.mem.{}", self.layout.frame_pointer()));
+                self.add_comment("failing if the frame doesn't fit in the frames region");
+                self.add_line(&format!("add.{} dup push.{} lt assert", size, frames_size));
+                self.add_line(&format!("pop.mem.{}", self.layout.stack_pointer()));
+            }
+            (_, "__frame_pop") => {
+                self.add_comment("popping a frame, restoring the frame pointer from its header");
+                self.add_line(&format!(
+                    "push.mem.{} dup pop.mem.{}",
+                    self.layout.frame_pointer(),
+                    self.layout.stack_pointer()
+                ));
+                self.add_line(&format!(
+                    "add.{} push.mem pop.mem.{}",
+                    self.layout.first_frame() + 1,
+                    self.layout.frame_pointer()
+                ));
+            }
+
             //u256 operations
-            (Some(YulType::U256), "add" | "mul" | "sub" | "iszero") => {
-                self.transpile_function_args(op);
+            (Some(YulType::U256 | YulType::Address | YulType::U128), "add" | "mul" | "sub") => {
+                self.transpile_function_args_as(op, YulType::U256);
                 self.add_proc_exec(&format!("u256::{}_unsafe", op.function_name.as_str()));
                 self._consume_top_stack_values(2);
                 self.add_unknown(YulType::U256);
             }
-            (Some(YulType::U256), "eq") => {
-                self.transpile_function_args(op);
+            (Some(YulType::U256 | YulType::Address | YulType::U128), "iszero") => {
+                self.transpile_function_args_as(op, YulType::U256);
+                self.add_proc_exec("u256::iszero_unsafe");
+                self._consume_top_stack_values(1);
+                self.add_unknown(YulType::Bool);
+            }
+            (Some(YulType::U256 | YulType::Address | YulType::U128), "eq") => {
+                self.transpile_function_args_as(op, YulType::U256);
                 self.add_proc_exec(&format!("u256::{}_unsafe", op.function_name.as_str()));
                 self._consume_top_stack_values(2);
-                self.add_unknown(YulType::U32);
+                self.add_unknown(YulType::Bool);
             }
-            (Some(YulType::U256), "lt" | "gt") => {
-                self.transpile_function_args(op);
+            (Some(YulType::U256 | YulType::Address | YulType::U128), "lt" | "gt") => {
+                self.transpile_function_args_as(op, YulType::U256);
                 self.add_proc_exec(&format!("u256{}_unsafe", op.function_name.as_str()));
                 self._consume_top_stack_values(2);
-                self.add_unknown(YulType::U256);
+                self.add_unknown(YulType::Bool);
             }
-            (Some(YulType::U256), "and" | "or" | "xor") => {
-                self.transpile_function_args(op);
+            (Some(YulType::U256 | YulType::Address | YulType::U128), "and" | "or" | "xor") => {
+                self.transpile_function_args_as(op, YulType::U256);
                 self.add_proc_exec(&format!("u256::{}", op.function_name.as_str()));
                 self._consume_top_stack_values(2);
                 self.add_unknown(YulType::U256);
             }
-            (Some(YulType::U32), "mstore") => {
+
+            //u64 operations, using Miden's u64 standard library
+            (
+                Some(YulType::U64),
+                "add" | "sub" | "mul" | "div" | "and" | "or" | "xor" | "lt" | "gt" | "eq",
+            ) => {
+                self.transpile_function_args_as(op, YulType::U64);
+                self.add_proc_exec(&format!("u64::{}", u64_proc(&op.function_name)));
+                self._consume_top_stack_values(2);
+                match op.function_name.as_str() {
+                    "lt" | "gt" | "eq" => self.add_unknown(YulType::Bool),
+                    _ => self.add_unknown(YulType::U64),
+                }
+            }
+            (Some(YulType::U64), "iszero") => {
+                self.transpile_function_args_as(op, YulType::U64);
+                self.add_proc_exec("u64::unchecked_eqz");
+                self._consume_top_stack_values(1);
+                self.add_unknown(YulType::Bool);
+            }
+
+            (_, "mstore") => {
                 let value_expr = op.exprs.get(1).unwrap();
                 self.transpile_op(value_expr);
+                //every value is zero-extended and saved as a full u256 slot, so that nothing
+                //stored in the slot before is left in its upper limbs
+                self.convert_top(YulType::U256);
                 let address_expr = op.exprs.first().unwrap();
                 self.transpile_op(address_expr);
-                match value_expr.get_inferred_type().unwrap() {
-                    YulType::U32 => {
-                        self.add_line(&format!("mul.2 push.{} add", self.memory_offset));
-                        self.add_line("pop.mem");
-                        self._consume_top_stack_values(2);
-                    }
-                    YulType::U256 => {
-                        self.add_line(&format!(
-                            "mul.2 push.{} add dup movdn.5",
-                            self.memory_offset
-                        ));
-                        self.add_line("popw.mem");
-                        self.add_line("add.1 popw.mem");
-                        self._consume_top_stack_values(2);
-                    }
-                };
+                self.convert_top(YulType::U32);
+                self.assert_heap_slot();
+                self.add_line(&format!("mul.2 push.{} add dup movdn.5", self.layout.heap));
+                self.add_line("popw.mem");
+                self.add_line("add.1 popw.mem");
+                self._consume_top_stack_values(2);
             }
-            (Some(YulType::U32), "mload") => {
+            (_, "mload") => {
                 let address_expr = op.exprs.first().unwrap();
                 self.transpile_op(address_expr);
-                match op.inferred_return_types.first().unwrap().unwrap() {
-                    YulType::U32 => {
-                        self.add_line(&format!("mul.2 push.{} add", self.memory_offset));
+                self.convert_top(YulType::U32);
+                self.assert_heap_slot();
+                let return_type = op.inferred_return_types.first().unwrap().unwrap();
+                match return_type {
+                    YulType::U8 | YulType::U32 | YulType::Bool => {
+                        self.add_line(&format!("mul.2 push.{} add", self.layout.heap + 1));
                         self.add_line("push.mem");
                         self._consume_top_stack_values(1);
                         self.add_unknown(YulType::U32);
                     }
-                    YulType::U256 => {
-                        self.add_line(&format!("mul.2 push.{} add dup", self.memory_offset));
+                    _ => {
+                        self.add_line(&format!("mul.2 push.{} add dup", self.layout.heap));
                         self.add_line("add.1 pushw.mem");
                         self.add_line("movup.4 pushw.mem");
                         self._consume_top_stack_values(1);
                         self.add_unknown(YulType::U256);
                     }
                 };
+                self.convert_top(return_type);
+            }
+            (_, "sstore") => {
+                self.transpile_op(&op.exprs[1]);
+                self.convert_top(YulType::U256);
+                self.transpile_op(&op.exprs[0]);
+                self.convert_top(YulType::U256);
+                self.storage_slot();
+                self.store_to_address_on_stack(YulType::U256);
+            }
+            (_, "sload") => {
+                self.transpile_op(&op.exprs[0]);
+                self.convert_top(YulType::U256);
+                self.storage_slot();
+                self.load_from_address_on_stack(YulType::U256);
+                self.convert_top(
+                    op.inferred_return_types
+                        .first()
+                        .copied()
+                        .flatten()
+                        .unwrap_or(YulType::U256),
+                );
+            }
+            (_, "calldatasize") => {
+                self.calldata_used = true;
+                self.prepare_for_stack_values(&YulType::U32);
+                self.add_line(&format!("push.mem.{}", self.layout.calldata));
+                self.add_unknown(YulType::U32);
+                self.convert_top(
+                    op.inferred_return_types
+                        .first()
+                        .copied()
+                        .flatten()
+                        .unwrap_or(YulType::U32),
+                );
+            }
+            (_, "calldataload") => {
+                self.calldata_used = true;
+                self.transpile_op(&op.exprs[0]);
+                self.convert_top(YulType::U32);
+                //offsets past the end of the calldata are clamped to its size, so that the reads
+                //stay in the calldata region and still only see zeros
+                self.add_line(&format!(
+                    "push.mem.{} dup.1 dup.1 u32checked_gt",
+                    self.layout.calldata
+                ));
+                self.add_line("if.true swap drop else drop end");
+                //each limb is four calldata bytes, big endian, and the least significant limb is
+                //pushed first. Bytes past the end of the calldata were never written, so they
+                //read as zero.
+                for limb in 0..8 {
+                    let address = self.layout.calldata + 1 + 4 * (7 - limb);
+                    self.add_line(&format!("dup add.{} push.mem", address));
+                    for byte in 1..4 {
+                        self.add_line(&format!(
+                            "mul.256 dup.1 add.{} push.mem add",
+                            address + byte
+                        ));
+                    }
+                    self.add_line("swap");
+                }
+                self.add_line("drop");
+                self._consume_top_stack_values(1);
+                self.add_unknown(YulType::U256);
+                self.convert_top(
+                    op.inferred_return_types
+                        .first()
+                        .copied()
+                        .flatten()
+                        .unwrap_or(YulType::U256),
+                );
+            }
+            (_, "__source_line") => {
+                let line = match &op.exprs[0] {
+                    Expr::Literal(ExprLiteral::Number(number)) => number.value,
+                    _ => panic!("__source_line takes a line number"),
+                };
+                self.add_line(&format!(
+                    "push.{} pop.mem.{}",
+                    line,
+                    self.layout.source_line()
+                ));
+                //a statement that was inlined in several places keeps the first stack it had
+                let stack = self
+                    .stack
+                    .0
+                    .iter()
+                    .map(|stack_value| StackSlot {
+                        name: stack_value
+                            .typed_identifier
+                            .as_ref()
+                            .map(|typed_identifier| typed_identifier.identifier.clone()),
+                        yul_type: stack_value.yul_type,
+                    })
+                    .collect();
+                self.source_map.entry(line.as_u32()).or_insert(stack);
             }
 
             (Some(YulType::U256), "shl" | "shr") => {
@@ -854,29 +1434,239 @@ impl Transpiler {
                 self.add_unknown(YulType::U256);
             }
 
+            // u8 math wraps around at 256
+            (Some(YulType::U8), "add" | "sub" | "mul") => {
+                self.transpile_function_args_as(op, YulType::U8);
+                self._consume_top_stack_values(2);
+                self.add_unknown(YulType::U8);
+                self.add_line(&format!("u32wrapping_{}", op.function_name));
+                self.add_line("push.255 u32checked_and");
+            }
+
             // binary u32 math and boolean ops
             (
-                Some(YulType::U32) | None,
-                "add" | "sub" | "mul" | "div" | "gt" | "lt" | "eq" | "and" | "or",
+                Some(YulType::U8 | YulType::U32 | YulType::Bool) | None,
+                "add" | "sub" | "mul" | "div" | "and" | "or",
             ) => {
-                self.transpile_function_args(op);
+                self.transpile_function_args_as(op, arg_type);
                 self._consume_top_stack_values(2);
-                self.add_unknown(YulType::U32);
+                //u8 division and bitwise ops can't go past 255
+                if arg_type == YulType::U8 {
+                    self.add_unknown(YulType::U8);
+                } else {
+                    self.add_unknown(YulType::U32);
+                }
+                //the field's div is multiplication by an inverse, and its and and or only take
+                //binary values
+                match op.function_name.as_str() {
+                    "div" | "and" | "or" => {
+                        self.add_line(&format!("u32checked_{}", op.function_name))
+                    }
+                    function_name => self.add_line(function_name),
+                }
+            }
+
+            // u32 comparisons
+            (Some(YulType::U8 | YulType::U32 | YulType::Bool) | None, "gt" | "lt" | "eq") => {
+                self.transpile_function_args_as(op, arg_type);
+                self._consume_top_stack_values(2);
+                self.add_unknown(YulType::Bool);
                 self.add_line(op.function_name.as_ref());
             }
 
-            (Some(YulType::U32) | None, "iszero") => {
+            (Some(YulType::U8 | YulType::U32 | YulType::Bool) | None, "iszero") => {
                 self.transpile_function_args(op);
                 self.add_line("push.0");
                 self.add_line("eq");
                 self._consume_top_stack_values(1);
-                self.add_unknown(YulType::U32);
+                self.add_unknown(YulType::Bool);
             }
 
             _ => {
                 panic!("\"{}\" not implemented in miden yet", op.function_name)
             }
         };
+
+        if op_type == Some(YulType::U128) && self.stack.0[0].yul_type == YulType::U256 {
+            self.convert_top(YulType::U128);
+        }
+    }
+
+    //Fails execution unless the mstore slot on top of the stack is inside the heap, so that
+    //mstore and mload can't reach the regions after it
+    fn assert_heap_slot(&mut self) {
+        self.add_line(&format!(
+            "dup push.{} u32checked_lt assert",
+            self.layout.heap_slots()
+        ));
+    }
+
+    //Fails execution if the log region is full, so that another log entry can't overwrite the log
+    //data
+    fn assert_log_slot(&mut self) {
+        self.add_line(&format!(
+            "push.mem.{} push.{} u32checked_lt assert",
+            self.layout.logs,
+            self.layout.max_logs()
+        ));
+    }
+
+    //Transpiles return(offset, size), revert(offset, size), stop() and invalid().
+    //The status code and the location of the return data are written to the outcome region of
+    //memory (see MemoryLayout::outcome), where the executor picks them up after execution.
+    //Statements after a halt are skipped by transpile_statements.
+    fn transpile_halt(&mut self, op: &ExprFunctionCall) {
+        let status = match op.function_name.as_str() {
+            "return" | "stop" => STATUS_SUCCESS,
+            "revert" => STATUS_REVERT,
+            _ => STATUS_INVALID,
+        };
+        if matches!(op.function_name.as_str(), "return" | "revert") {
+            self.transpile_function_args_as(op, YulType::U32);
+            self.add_line(&format!("pop.mem.{}", self.layout.outcome + 2));
+            self.add_line(&format!("pop.mem.{}", self.layout.outcome + 1));
+            self._consume_top_stack_values(2);
+        }
+        self.add_line(&format!("push.{} pop.mem.{}", status, self.layout.outcome));
+    }
+
+    //Transpiles log0(offset, size) through log4(offset, size, topic1, ..., topic4).
+    //Each log is appended to the log buffer in memory (see MemoryLayout::logs). The topics are stored in
+    //the log entry, and the data is copied from the heap into the log data region.
+    fn transpile_log(&mut self, op: &ExprFunctionCall, topics: u32) {
+        assert_eq!(
+            op.exprs.len() as u32,
+            topics + 2,
+            "log{} takes {} arguments",
+            topics,
+            topics + 2
+        );
+        let logs = self.layout.logs;
+        let log_data = self.layout.log_data;
+        let entry_address = format!("push.mem.{} mul.{}", logs, LOG_ENTRY_SIZE);
+        let field_address = |field: u32| format!("{} add.{}", entry_address, logs + 1 + field);
+
+        self.assert_log_slot();
+        for (i, expr) in op.exprs.iter().enumerate() {
+            self.transpile_op(expr);
+            self.convert_top(if i < 2 { YulType::U32 } else { YulType::U256 });
+        }
+        for topic in (0..topics).rev() {
+            self.add_comment(&format!("saving topic {}", topic + 1));
+            self.add_line(&field_address(3 + 2 * topic));
+            self.add_line("dup movdn.5 popw.mem");
+            self.add_line("add.1 popw.mem");
+        }
+        self._consume_top_stack_values(topics);
+
+        self.add_comment("saving the number of topics and the size of the data");
+        self.add_line(&format!("dup {} pop.mem", field_address(2)));
+        self.add_line(&format!("push.{} {} pop.mem", topics, field_address(0)));
+
+        self.add_comment("copying the data out of the heap");
+        self.add_line(&format!("push.mem.{} add.{}", log_data, log_data + 1));
+        self.add_line(&format!("dup {} pop.mem", field_address(1)));
+        self.add_line("swap add.31 u32checked_div.32 mul.2");
+        self.add_line(&format!(
+            "dup push.mem.{} add pop.mem.{}",
+            log_data, log_data
+        ));
+        self.add_line(&format!("movup.2 mul.2 add.{} swap", self.layout.heap));
+        self.add_proc_exec("log_copy_words");
+        self._consume_top_stack_values(2);
+
+        self.add_line(&format!("push.mem.{} add.1 pop.mem.{}", logs, logs));
+    }
+
+    //Transpiles gas(). Miden doesn't meter gas, so this is either a constant or the number of
+    //cycles left before an assumed limit, depending on CompileOptions::gas
+    fn transpile_gas(&mut self) {
+        let warning = format!(
+            "gas() has no equivalent in Miden, using {:?} instead",
+            self.options.gas
+        );
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+        match self.options.gas {
+            GasModel::Constant(gas) => self.push_u256(U256::from(gas)),
+            GasModel::CycleEstimate { limit } => {
+                //clk is a u32, so a larger limit is clamped, and no gas is left once clk passes it
+                let limit = limit.min(u32::MAX as u64);
+                self.prepare_for_stack_values(&YulType::U256);
+                self.add_comment("estimating gas left from the cycle count");
+                self.add_line(&format!("clk dup push.{} u32checked_gt", limit));
+                self.add_line("if.true");
+                self.indent();
+                self.add_line("drop push.0");
+                self.outdent();
+                self.add_line("else");
+                self.indent();
+                self.add_line(&format!("push.{} swap u32checked_sub", limit));
+                self.outdent();
+                self.add_line("end");
+                self.add_line(&"push.0 ".repeat(7));
+                self.add_unknown(YulType::U256);
+            }
+        }
+    }
+
+    //Saves the environment values that the executor passes on the advice stack to the context
+    //region of memory. See CONTEXT_BUILTINS for the layout.
+    fn context_prologue(&self) -> String {
+        let indentation = " ".repeat(self.indentation.try_into().unwrap());
+        let mut prologue = String::new();
+        if self.options.comments {
+            prologue.push_str(&format!("\n{}# loading the environment #", indentation));
+        }
+        for (i, builtin) in CONTEXT_BUILTINS.iter().enumerate() {
+            let address = self.layout.context + 2 * i as u32;
+            if self.options.comments {
+                prologue.push_str(&format!("\n{}# {} #", indentation, builtin));
+            }
+            prologue.push_str(&format!(
+                "\n{0}adv_push.8\n{0}popw.mem.{1}\n{0}popw.mem.{2}",
+                indentation,
+                address,
+                address + 1
+            ));
+        }
+        prologue
+    }
+
+    //Copies the calldata that the executor passes on the advice stack after the environment values
+    //to the calldata region of memory, the size first and then one byte per address
+    fn calldata_prologue(&self) -> String {
+        let indentation = " ".repeat(self.indentation.try_into().unwrap());
+        let mut prologue = String::new();
+        if self.options.comments {
+            prologue.push_str(&format!("\n{}# loading the calldata #", indentation));
+        }
+        let lines = [
+            format!("adv_push.1 dup pop.mem.{}", self.layout.calldata),
+            "push.0 dup.1 dup.1 gt".to_string(),
+            "while.true".to_string(),
+            format!(
+                "  adv_push.1 dup.1 add.{} pop.mem",
+                self.layout.calldata + 1
+            ),
+            "  add.1 dup.1 dup.1 gt".to_string(),
+            "end".to_string(),
+            "drop drop".to_string(),
+        ];
+        for line in lines {
+            prologue.push_str(&format!("\n{}{}", indentation, line));
+        }
+        prologue
+    }
+
+    //Replaces the u256 storage key on top of the stack with the address of its value, adding the
+    //key to the storage region if it isn't there yet
+    fn storage_slot(&mut self) {
+        self.add_line(&format!("push.{}", self.layout.storage));
+        self.add_proc_exec("storage_slot");
+        self._consume_top_stack_values(1);
+        self.add_unknown(YulType::U32);
     }
 
     //Transpile an if statement
@@ -884,6 +1674,7 @@ impl Transpiler {
     //See the Branch struct for more details on this
     fn transpile_if_statement(&mut self, op: &ExprIfStatement) {
         self.transpile_op(&op.first_expr);
+        self.convert_top(YulType::Bool);
         self._consume_top_stack_values(1);
         self.add_line("if.true");
         self.indent();
@@ -897,20 +1688,43 @@ impl Transpiler {
     //Transpile a literal
     //If the value is u32, a single push will occur
     //If the value is u256, 8 elements will be pushed onto the stack. See push_u256 for more details
+    //u64 and u128 values push one element per limb
     fn transpile_literal(&mut self, literal: &ExprLiteral) {
         match literal {
             ExprLiteral::Number(ExprLiteralNumber {
                 value,
                 inferred_type,
-            }) => {
-                if inferred_type == &Some(YulType::U256) {
+            }) => match inferred_type {
+                Some(yul_type @ (YulType::U256 | YulType::Address)) => {
                     self.push_u256(*value);
-                } else {
+                    self.stack.0.first_mut().unwrap().yul_type = *yul_type;
+                }
+                Some(yul_type @ (YulType::U64 | YulType::U128)) => {
+                    self.push_limbs(*value, *yul_type);
+                }
+                Some(yul_type @ (YulType::U8 | YulType::Bool)) => {
                     self.push(*value);
+                    self.stack.0.first_mut().unwrap().yul_type = *yul_type;
                 }
+                Some(YulType::U32) | None => self.push(*value),
+            },
+            ExprLiteral::Hex(bytes) => {
+                self.add_comment(&format!("hex literal \"{}\"", hex::encode(bytes)));
+                self.push_u256(convert_bytes_to_u256(bytes));
+            }
+            ExprLiteral::String(bytes) => {
+                //# ends a comment in Miden assembly
+                let escaped = bytes.escape_ascii().to_string().replace('#', "\\x23");
+                self.add_comment(&format!("string literal \"{}\"", escaped));
+                self.push_u256(convert_bytes_to_u256(bytes));
+            }
+            ExprLiteral::Bool(value) => {
+                self.prepare_for_stack_values(&YulType::Bool);
+                self.add_unknown(YulType::Bool);
+                self.add_comment(&format!("bool literal {}", value));
+                self.add_line(&format!("push.{}", *value as u32));
+                self.newline();
             }
-            ExprLiteral::String(_) => todo!(),
-            &ExprLiteral::Bool(_) => todo!(),
         }
     }
 
@@ -927,17 +1741,28 @@ impl Transpiler {
     //After transpiling the function into a Miden procedure, the function is added to user functions with the output stack state.
     //The transpiler stack is reset after transpiling the function declaration and the scoped parameters are removed.
     fn transpile_function_declaration(&mut self, op: &ExprFunctionDefinition) {
+        //the variables of the top level code and other functions aren't visible in the body, and
+        //variables with the same name get their own memory
+        let variables = std::mem::take(&mut self.variables);
+        let scoped_identifiers = std::mem::take(&mut self.scoped_identifiers);
         self.stack = Stack(op.params.iter().map(StackValue::from).collect());
         for param in &op.params {
             self.scoped_identifiers
                 .insert(param.identifier.clone(), param.clone());
         }
-        self.add_line(&format!(
-            "proc.{}.{}",
-            op.function_name,
-            op.params.len() * 2
-        ));
+        self.locals = Some(0);
+        let header_position = self.program.len();
         self.indent();
+        //return variables start out as zero
+        for return_ident in &op.returns {
+            self.transpile_literal(&ExprLiteral::Number(ExprLiteralNumber {
+                value: U256::zero(),
+                inferred_type: Some(return_ident.yul_type),
+            }));
+            self.top_is_var(return_ident.clone());
+            self.scoped_identifiers
+                .insert(return_ident.identifier.clone(), return_ident.clone());
+        }
         self.transpile_block(&op.block);
         for return_ident in &op.returns {
             self.dup_identifier(return_ident.clone());
@@ -947,11 +1772,18 @@ impl Transpiler {
         self.stack = Stack::default();
         self.outdent();
         self.add_line("end");
+        //the header declares the locals, which are only known once the body has been transpiled
+        let header = format!(
+            "\n{}proc.{}.{}",
+            " ".repeat(self.indentation as usize),
+            op.function_name,
+            self.locals.take().unwrap()
+        );
+        self.program.insert_str(header_position, &header);
         self.user_functions
             .insert(op.function_name.clone(), function_stack);
-        for param in &op.params {
-            self.scoped_identifiers.remove(&param.identifier);
-        }
+        self.variables = variables;
+        self.scoped_identifiers = scoped_identifiers;
     }
 
     //TODO: update placeholder
@@ -1008,11 +1840,8 @@ impl Transpiler {
         //Assign the transpiler_switch_matched_bool the value of 1 in the transpiler and in the in Miden assembly
         self.transpile_assignment(&ExprAssignment {
             identifiers: vec![transpiler_switch_matched_bool.identifier],
-            inferred_types: vec![Some(YulType::U32)],
-            rhs: Box::new(Expr::Literal(ExprLiteral::Number(ExprLiteralNumber {
-                inferred_type: Some(YulType::U32),
-                value: U256::from(1_u32),
-            }))),
+            inferred_types: vec![Some(YulType::Bool)],
+            rhs: Box::new(Expr::Literal(ExprLiteral::Bool(true))),
         });
 
         //Transpile the block that will be executed if the case is matched to the switch expression during Miden runtime.
@@ -1105,12 +1934,40 @@ impl Transpiler {
     fn add_use(&mut self, library: &str) {
         self.program = format!("use.{}\n {}", library, self.program);
     }
+
+    //Adds the procs from src/miden_asm and the standard library modules that the program uses
+    fn add_libraries(&mut self) {
+        self.add_utility_functions();
+        self.add_use("std::math::u256");
+        if self.procs_used.iter().any(|proc| proc.starts_with("u64::")) {
+            self.add_use("std::math::u64");
+        }
+    }
+
+    //The environment is only loaded if the program reads it. The calldata comes after the
+    //environment on the advice stack, so reading the calldata loads both.
+    fn prologue(&self) -> String {
+        let mut prologue = String::new();
+        if self.context_used || self.calldata_used {
+            prologue.push_str(&self.context_prologue());
+        }
+        if self.calldata_used {
+            prologue.push_str(&self.calldata_prologue());
+        }
+        prologue
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct CompileOptions {
     pub comments: bool,
     pub auto_indent: bool,
+    pub gas: GasModel,
+    //User functions with at most this many expressions in their body are inlined where they're
+    //called as a statement, see inline_functions. 0 turns inlining off.
+    pub inline_threshold: usize,
+    //Where each region of memory starts
+    pub memory_layout: MemoryLayout,
 }
 
 impl Default for CompileOptions {
@@ -1118,52 +1975,820 @@ impl Default for CompileOptions {
         CompileOptions {
             comments: true,
             auto_indent: true,
+            gas: GasModel::Constant(u32::MAX as u64),
+            inline_threshold: 16,
+            memory_layout: MemoryLayout::default(),
         }
     }
 }
 
+//What gas() evaluates to. Miden has no gas metering, so either a fixed amount is reported, or the
+//number of cycles left before limit
+#[derive(Clone, Copy, Debug)]
+pub enum GasModel {
+    Constant(u64),
+    CycleEstimate { limit: u64 },
+}
+
 //Transpile a Miden program from a Vec of expressions and return the compiled Miden program as a string
 pub fn transpile_program(expressions: Vec<Expr>, options: CompileOptions) -> String {
+    compile_program(expressions, options).masm
+}
+
+//A compiled Miden program, along with the types of the values it leaves on the stack
+#[derive(Clone, Debug)]
+pub struct CompiledProgram {
+    pub masm: String,
+    //the types of the values on the stack when the program ends, top first
+    pub outputs: Vec<YulType>,
+    pub source_map: SourceMap,
+    //things the program does that Miden can only approximate, like gas()
+    pub warnings: Vec<String>,
+}
+
+//Like transpile_program, but also returns the types of the values left on the stack, so that
+//they can be decoded after execution
+pub fn compile_program(expressions: Vec<Expr>, options: CompileOptions) -> CompiledProgram {
     //Initalize the transpiler
-    let mut transpiler = Transpiler {
-        options,
-        variables: HashMap::new(),
-        next_open_memory_address: 0,
-        indentation: 0,
-        stack: Stack::default(),
-        scoped_identifiers: HashMap::new(),
-        program: "".to_string(),
-        user_functions: HashMap::default(),
-        branches: VecDeque::new(),
-        accept_overflow: false,
-        memory_offset: 1024,
-        procs_used: HashSet::new(),
-    };
+    let mut transpiler = Transpiler::new(options);
     //optimize the abstract syntax tree
-    let ast = optimize_ast(expressions);
+    let ast = hoist_functions(optimize_ast(expressions));
+    let ast = inline_functions(ast, transpiler.options.inline_threshold);
+    let ast = lower_recursive_functions(ast);
+    transpiler.halting_functions = find_halting_functions(&ast);
     // transpiler.add_line("# end std lib #");
 
     //transpile function declarations first so that the procs are generated before we begin
     //transpiling the body of the miden program
-    for expr in &ast {
-        if let Expr::FunctionDefinition(op) = expr {
-            transpiler.transpile_function_declaration(op)
-        }
+    for op in sort_function_definitions(&ast) {
+        transpiler.transpile_function_declaration(op)
     }
 
     //start the Miden program
     transpiler.add_line("begin");
     transpiler.indent();
+    let prologue_position = transpiler.program.len();
     //transpile each expression in the abstract syntax tree
-    for expr in ast {
-        transpiler.transpile_op(&expr);
-    }
+    transpiler.transpile_statements(&ast);
+    //the types of the values the program leaves on the stack, top first
+    let outputs = transpiler
+        .stack
+        .0
+        .iter()
+        .map(|stack_value| stack_value.yul_type)
+        .collect();
+    let prologue = transpiler.prologue();
+    transpiler.program.insert_str(prologue_position, &prologue);
     // transpiler.outdent();
     transpiler.indentation = 0;
     //end the Miden program
     transpiler.add_line("end");
     //return the Miden program as a string
-    transpiler.add_utility_functions();
-    transpiler.add_use("std::math::u256");
-    transpiler.program
+    transpiler.add_libraries();
+    //checking that the variables of the top level code fit before the heap now that they're known
+    let variable_addresses = transpiler.next_open_memory_address - transpiler.layout.variables;
+    let report = transpiler.layout.report(variable_addresses);
+    if transpiler.options.comments {
+        let report = std::iter::once("memory layout")
+            .chain(report.lines())
+            .map(|line| format!("# {} #\n", line))
+            .collect::<String>();
+        transpiler.program.insert_str(0, &report);
+    }
+    CompiledProgram {
+        masm: transpiler.program,
+        outputs,
+        source_map: transpiler.source_map,
+        warnings: transpiler.warnings,
+    }
+}
+
+//Transpiles Yul a few statements at a time, for the REPL. The transpiler keeps its stack,
+//variables and functions between calls, so later statements can use what earlier ones declared.
+//Functions can't be recursive or nested in other functions, and statements after one that halts
+//still run.
+#[derive(Clone)]
+pub struct YulSession {
+    transpiler: Transpiler,
+    procs: String,
+    body: String,
+}
+
+//Where a variable of the top level code is kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariableLocation {
+    //how many elements from the top of the stack the variable starts, with its most significant
+    //limb first
+    Stack(usize),
+    //the address the variable starts at, laid out like pop_top_stack_value_to_memory stores it
+    Memory(u32),
+}
+
+impl YulSession {
+    pub fn new(options: CompileOptions) -> Self {
+        YulSession {
+            transpiler: Transpiler::new(options),
+            procs: String::new(),
+            body: String::new(),
+        }
+    }
+
+    //Transpiles typed statements, adding their functions to the procs and the rest to the body of
+    //the program, and returns the warnings for them. Panics like transpile_program if the
+    //statements can't be transpiled.
+    pub fn add_statements(&mut self, ast: &[Expr]) -> Vec<String> {
+        let transpiler = &mut self.transpiler;
+        transpiler
+            .halting_functions
+            .extend(find_halting_functions(ast));
+        for op in sort_function_definitions(ast) {
+            transpiler.indentation = 0;
+            transpiler.transpile_function_declaration(op);
+            self.procs
+                .push_str(&std::mem::take(&mut transpiler.program));
+        }
+        transpiler.indentation = 4;
+        transpiler.transpile_statements(ast);
+        self.body.push_str(&std::mem::take(&mut transpiler.program));
+        std::mem::take(&mut transpiler.warnings)
+    }
+
+    //The whole Miden program for the statements so far
+    pub fn program(&self) -> String {
+        self.program_with_body(true, &self.body)
+    }
+
+    //A program of only the statements added since an earlier state of the session, which runs on
+    //top of the stack and memory that the earlier program left. Returns None if the new statements
+    //need the prologue, which only runs at the start of the program.
+    pub fn program_since(&self, earlier: &YulSession) -> Option<String> {
+        let body = self.body.strip_prefix(&earlier.body)?;
+        if self.transpiler.prologue() != earlier.transpiler.prologue() {
+            return None;
+        }
+        Some(self.program_with_body(false, body))
+    }
+
+    fn program_with_body(&self, prologue: bool, body: &str) -> String {
+        let mut transpiler = self.transpiler.clone();
+        transpiler.indentation = 4;
+        let prologue = match prologue {
+            true => transpiler.prologue(),
+            false => String::new(),
+        };
+        transpiler.program = format!("{}\nbegin{}{}\nend", self.procs, prologue, body);
+        transpiler.add_libraries();
+        transpiler.program
+    }
+
+    //The variables of the top level code in scope, and where they're kept. The ones on the stack
+    //come first, from the top down, followed by the ones in memory in the order of their addresses.
+    pub fn variables(&self) -> Vec<(TypedIdentifier, VariableLocation)> {
+        let mut variables: Vec<(TypedIdentifier, VariableLocation)> = vec![];
+        let mut offset = 0;
+        for stack_value in &self.transpiler.stack.0 {
+            if let Some(typed_identifier) = &stack_value.typed_identifier {
+                //the copy closest to the top is the current value
+                if !variables.iter().any(|(known, _)| known == typed_identifier) {
+                    variables.push((typed_identifier.clone(), VariableLocation::Stack(offset)));
+                }
+            }
+            offset += stack_value.yul_type.miden_stack_width() as usize;
+        }
+        let memory_variables = self
+            .transpiler
+            .variables
+            .iter()
+            .sorted_by_key(|(_, address)| **address);
+        for (typed_identifier, address) in memory_variables {
+            if !variables.iter().any(|(known, _)| known == typed_identifier) {
+                variables.push((typed_identifier.clone(), VariableLocation::Memory(*address)));
+            }
+        }
+        variables
+    }
+}
+
+//Orders the function definitions so that every function comes after the functions it calls, since
+//a proc has to be defined before it can be executed. Recursive functions have been lowered by then.
+fn sort_function_definitions(ast: &[Expr]) -> Vec<&ExprFunctionDefinition> {
+    fn visit<'a>(
+        name: &str,
+        functions: &HashMap<&str, &'a ExprFunctionDefinition>,
+        visiting: &mut HashSet<String>,
+        sorted: &mut Vec<&'a ExprFunctionDefinition>,
+    ) {
+        let function = match functions.get(name) {
+            Some(function) if !sorted.iter().any(|f| f.function_name == name) => *function,
+            _ => return,
+        };
+        if !visiting.insert(name.to_string()) {
+            panic!("{} calls itself", name);
+        }
+        for callee in called_functions(&function.block.exprs) {
+            visit(&callee, functions, visiting, sorted);
+        }
+        visiting.remove(name);
+        sorted.push(function);
+    }
+
+    let functions = ast
+        .iter()
+        .filter_map(|expr| match expr {
+            Expr::FunctionDefinition(op) => Some((op.function_name.as_str(), op)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let mut sorted = vec![];
+    for expr in ast {
+        if let Expr::FunctionDefinition(op) = expr {
+            visit(
+                &op.function_name,
+                &functions,
+                &mut HashSet::new(),
+                &mut sorted,
+            );
+        }
+    }
+    sorted
+}
+
+//Returns the name of the procedure in Miden's u64 standard library for a Yul builtin
+fn u64_proc(function_name: &str) -> &'static str {
+    match function_name {
+        "add" => "wrapping_add",
+        "sub" => "wrapping_sub",
+        "mul" => "wrapping_mul",
+        "div" => "unchecked_div",
+        "and" => "checked_and",
+        "or" => "checked_or",
+        "xor" => "checked_xor",
+        "lt" => "unchecked_lt",
+        "gt" => "unchecked_gt",
+        "eq" => "unchecked_eq",
+        _ => unreachable!("{} has no u64 procedure", function_name),
+    }
+}
+
+//Finds the user functions that can halt execution, either by calling a halting builtin directly or
+//by calling another function that halts
+fn find_halting_functions(ast: &[Expr]) -> HashSet<String> {
+    let functions = ast
+        .iter()
+        .filter_map(|expr| match expr {
+            Expr::FunctionDefinition(op) => Some(op),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut halting_functions = HashSet::new();
+    loop {
+        let known_halting = halting_functions.len();
+        for function in &functions {
+            if block_may_halt(&function.block, &halting_functions) {
+                halting_functions.insert(function.function_name.clone());
+            }
+        }
+        if halting_functions.len() == known_halting {
+            return halting_functions;
+        }
+    }
+}
+
+//Returns true if evaluating the expression could end execution
+fn may_halt(expr: &Expr, halting_functions: &HashSet<String>) -> bool {
+    match expr {
+        Expr::FunctionCall(op) => {
+            HALTING_BUILTINS.contains(&op.function_name.as_str())
+                || halting_functions.contains(&op.function_name)
+                || op.exprs.iter().any(|e| may_halt(e, halting_functions))
+        }
+        Expr::IfStatement(op) => {
+            may_halt(&op.first_expr, halting_functions)
+                || block_may_halt(&op.second_expr, halting_functions)
+        }
+        Expr::Assignment(op) => may_halt(&op.rhs, halting_functions),
+        Expr::DeclareVariable(op) => op
+            .rhs
+            .as_ref()
+            .map_or(false, |rhs| may_halt(rhs, halting_functions)),
+        Expr::ForLoop(op) => {
+            block_may_halt(&op.init_block, halting_functions)
+                || may_halt(&op.conditional, halting_functions)
+                || block_may_halt(&op.after_block, halting_functions)
+                || block_may_halt(&op.interior_block, halting_functions)
+        }
+        Expr::Block(op) => block_may_halt(op, halting_functions),
+        Expr::Switch(op) => {
+            may_halt(&op.expr, halting_functions)
+                || op
+                    .cases
+                    .iter()
+                    .any(|case| block_may_halt(&case.block, halting_functions))
+                || op
+                    .default_case
+                    .as_ref()
+                    .map_or(false, |block| block_may_halt(block, halting_functions))
+        }
+        Expr::Repeat(op) => block_may_halt(&op.interior_block, halting_functions),
+        //A function body can only halt where the function is called
+        _ => false,
+    }
+}
+
+fn block_may_halt(block: &ExprBlock, halting_functions: &HashSet<String>) -> bool {
+    block
+        .exprs
+        .iter()
+        .any(|expr| may_halt(expr, halting_functions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{
+        decode_stack, execute, execute_outcome, ExecutionConfig, ExecutionContext, ExecutionStatus,
+        Log, TxContext,
+    };
+    use crate::parser::{parse_yul_syntax, parse_yul_syntax_with_source_markers};
+    use crate::type_inference::infer_types;
+    use crate::types::YulValue;
+
+    fn compile(yul: &str) -> CompiledProgram {
+        compile_program(
+            infer_types(&parse_yul_syntax(yul)),
+            CompileOptions::default(),
+        )
+    }
+
+    //Runs a Yul program and decodes the values it leaves on the stack, top first
+    fn run(yul: &str) -> Vec<YulValue> {
+        let program = compile(yul);
+        let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+        decode_stack(&program.outputs, &result.stack).unwrap()
+    }
+
+    #[test]
+    fn mstore_zero_extends_u32_values() {
+        let masm = compile("let x:u32 := 5\nmstore(0, x)").masm;
+        assert!(masm.contains("converting u32 to u256"));
+    }
+
+    #[test]
+    fn u32_mstore_overwrites_the_whole_slot() {
+        let stack = run(
+            "mstore(0, 0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff)
+            let x:u32 := 5
+            mstore(0, x)
+            let y := mload(0)",
+        );
+        assert_eq!(stack[0], YulValue::U256(U256::from(5)));
+    }
+
+    #[test]
+    fn return_data_of_a_u32_mstore() {
+        let program = compile(
+            "mstore(0, 0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff)
+            let x:u32 := 7
+            mstore(0, x)
+            return(0, 32)",
+        );
+        let outcome = execute_outcome(program.masm).unwrap();
+        let mut expected = vec![0; 32];
+        expected[31] = 7;
+        assert_eq!(outcome.status, ExecutionStatus::Success);
+        assert_eq!(outcome.return_data, expected);
+    }
+
+    #[test]
+    fn emit_and_decode_logs() {
+        let program = compile(
+            "mstore(0, 42)
+            log0(0, 32)
+            log2(0, 32, 7, 9)",
+        );
+        let outcome = execute_outcome(program.masm).unwrap();
+        let mut data = vec![0; 32];
+        data[31] = 42;
+        assert_eq!(
+            outcome.logs,
+            vec![
+                Log {
+                    topics: vec![],
+                    data: data.clone(),
+                },
+                Log {
+                    topics: vec![U256::from(7), U256::from(9)],
+                    data,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn user_functions_shadow_log_builtins() {
+        let yul = "function log2(a) -> b {
+                b := add(a, 1)
+            }
+            function log10(a) -> b {
+                b := mul(a, 10)
+            }
+            let x := log2(5)
+            let y := log10(3)";
+        assert_eq!(
+            run(yul)[..2],
+            [
+                YulValue::U256(U256::from(30)),
+                YulValue::U256(U256::from(6))
+            ]
+        );
+        let outcome = execute_outcome(compile(yul).masm).unwrap();
+        assert!(outcome.logs.is_empty());
+    }
+
+    #[test]
+    fn gas_returns_a_warning() {
+        let program = compile("let a := gas()\nlet b := gas()");
+        assert_eq!(program.warnings.len(), 1);
+        assert!(program.warnings[0].starts_with("gas() has no equivalent in Miden"));
+        assert!(compile("let a := 1").warnings.is_empty());
+    }
+
+    #[test]
+    fn gas_estimate_stops_at_zero() {
+        let gas_left = |limit| {
+            let options = CompileOptions {
+                gas: GasModel::CycleEstimate { limit },
+                ..CompileOptions::default()
+            };
+            let program =
+                compile_program(infer_types(&parse_yul_syntax("let g := gas()")), options);
+            let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+            decode_stack(&program.outputs, &result.stack).unwrap()[0]
+        };
+        assert_eq!(gas_left(0), YulValue::U256(U256::zero()));
+        match gas_left(1_000_000) {
+            YulValue::U256(gas) => assert!(gas > U256::zero() && gas < U256::from(1_000_000)),
+            value => panic!("gas() returned {:?}", value),
+        }
+        //limits past what clk can reach are clamped instead of failing u32 checks
+        assert!(matches!(gas_left(u64::MAX), YulValue::U256(gas) if gas > U256::from(1_000_000)));
+    }
+
+    #[test]
+    fn comparisons_are_bools() {
+        let stack = run("let a:bool := lt(1, 2)
+            let x:u32 := 3
+            let b:bool := gt(x, 5)
+            let c:bool := eq(7, 7)
+            let d:bool := iszero(x)
+            let e:u32 := lt(x, 4)");
+        assert_eq!(
+            stack,
+            [
+                YulValue::U32(1),
+                YulValue::Bool(false),
+                YulValue::Bool(true),
+                YulValue::Bool(false),
+                YulValue::U32(3),
+                YulValue::Bool(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn conditions_are_converted_to_bools() {
+        let stack = run("let y := 0
+            let z:u32 := 0
+            if 0x100000000 {
+                y := 1
+            }
+            if iszero(lt(2, 1)) {
+                z := 7
+            }");
+        assert_eq!(stack, [YulValue::U32(7), YulValue::U256(U256::one())]);
+        //a u256 condition is true if any of its limbs isn't zero
+        let masm = compile("if 0x100000000 {\n}").masm;
+        assert!(masm.contains("converting u256 to bool"));
+    }
+
+    #[test]
+    fn u8_arithmetic_wraps_around() {
+        let stack = run("let a:u8 := 200
+            let b:u8 := add(a, 100)");
+        assert_eq!(stack, [YulValue::U8(44), YulValue::U8(200)]);
+    }
+
+    #[test]
+    fn u64_arithmetic_carries_between_limbs() {
+        let stack = run("let c:u64 := 0xffffffff
+            let d:u64 := add(c, 1)
+            let e:u64 := mul(d, 3)");
+        assert_eq!(stack[0], YulValue::U64(3 << 32));
+        assert_eq!(stack[1], YulValue::U64(1 << 32));
+    }
+
+    #[test]
+    fn u128_arithmetic_is_narrowed() {
+        let stack = run("let f:u128 := 0x300000000
+            let g:u128 := mul(f, f)");
+        assert_eq!(stack[0], YulValue::U128(9 << 64));
+        let stack = run("let h:u128 := add(0xffffffffffffffffffffffffffffffff, 2)");
+        assert_eq!(stack[0], YulValue::U128(1));
+    }
+
+    #[test]
+    fn convert_between_widths() {
+        let stack = run("let c:u64 := 0x1ffffffff
+            let i:u8 := c
+            let j:u256 := c");
+        assert_eq!(
+            stack,
+            [
+                YulValue::U256(U256::from(0x1ffffffffu64)),
+                YulValue::U8(255),
+                YulValue::U64(0x1ffffffff),
+            ]
+        );
+    }
+
+    #[test]
+    fn convert_between_u32_and_u256() {
+        let stack = run("let a := 0x100000005
+            let b:u32 := u256_to_u32_truncating(a)
+            let c := u32_to_u256(b)
+            let d:u32 := u256_to_u32(c)");
+        assert_eq!(
+            stack[..3],
+            [
+                YulValue::U32(5),
+                YulValue::U256(U256::from(5)),
+                YulValue::U32(5)
+            ]
+        );
+        let program = compile("let a := 0x100000005\nlet b:u32 := u256_to_u32(a)");
+        assert!(execute(program.masm, &ExecutionConfig::default()).is_err());
+    }
+
+    //Returns the number of locals in the header of a proc, and the highest local its body uses
+    fn proc_locals(masm: &str, name: &str) -> (u32, Option<u32>) {
+        let header = format!("proc.{}.", name);
+        let start = masm.find(&header).unwrap() + header.len();
+        let body = &masm[start..];
+        let body = &body[..body.find("\nend").unwrap()];
+        let count = body.split_whitespace().next().unwrap().parse().unwrap();
+        let highest = body
+            .split_whitespace()
+            .filter_map(|token| token.split(".local.").nth(1))
+            .map(|local| local.parse().unwrap())
+            .max();
+        (count, highest)
+    }
+
+    #[test]
+    fn procs_declare_the_locals_they_use() {
+        let options = CompileOptions {
+            inline_threshold: 0,
+            ..CompileOptions::default()
+        };
+        let compile =
+            |yul: &str| compile_program(infer_types(&parse_yul_syntax(yul)), options.clone());
+
+        let masm = compile(
+            "function f(a) -> b {
+                b := add(a, 1)
+            }
+            let x := f(2)",
+        )
+        .masm;
+        assert_eq!(proc_locals(&masm, "f"), (0, None));
+
+        //five u256s don't fit on the stack, so some of them are kept in locals
+        let yul = "function g(a, b) -> c {
+                let d := add(a, b)
+                let e := mul(d, 2)
+                c := add(d, e)
+            }
+            let x := g(3, 4)
+            let y := g(x, 1)";
+        let program = compile(yul);
+        let (count, highest) = proc_locals(&program.masm, "g");
+        assert!(count > 0);
+        assert_eq!(highest, Some(count - 1));
+        let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+        assert_eq!(
+            decode_stack(&program.outputs, &result.stack).unwrap()[..2],
+            [
+                YulValue::U256(U256::from(66)),
+                YulValue::U256(U256::from(21))
+            ]
+        );
+    }
+
+    #[test]
+    fn heap_accesses_stay_in_the_heap() {
+        let slots = MemoryLayout::default().heap_slots();
+        let last_slot = run(&format!(
+            "mstore({}, 7)\nlet x := mload({})",
+            slots - 1,
+            slots - 1
+        ));
+        assert_eq!(last_slot[0], YulValue::U256(U256::from(7)));
+        for yul in [
+            format!("mstore({}, 7)", slots),
+            format!("let x := mload({})", slots),
+        ] {
+            let program = compile(&yul);
+            assert!(execute(program.masm, &ExecutionConfig::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn logs_stay_in_the_log_region() {
+        let default = MemoryLayout::default();
+        let layout = MemoryLayout {
+            log_data: default.logs + 1 + 2 * LOG_ENTRY_SIZE,
+            ..default
+        };
+        let run_logs = |count: usize| {
+            let program = compile_program(
+                infer_types(&parse_yul_syntax(&"log0(0, 32)\n".repeat(count))),
+                CompileOptions {
+                    memory_layout: layout.clone(),
+                    ..CompileOptions::default()
+                },
+            );
+            execute(
+                program.masm,
+                &ExecutionConfig::default().with_layout(layout.clone()),
+            )
+        };
+        assert!(run_logs(2).is_ok());
+        assert!(run_logs(3).is_err());
+    }
+
+    #[test]
+    fn sstore_and_sload() {
+        let yul = "sstore(1, 10)
+            sstore(2, 20)
+            sstore(1, 30)
+            let a := sload(1)
+            let b := sload(3)";
+        assert_eq!(
+            run(yul),
+            [YulValue::U256(U256::zero()), YulValue::U256(U256::from(30))]
+        );
+        //keys that are only read are listed too, since storage_slot adds every key it looks up
+        let outcome = execute_outcome(compile(yul).masm).unwrap();
+        assert_eq!(
+            outcome.storage,
+            [
+                (U256::from(1), U256::from(30)),
+                (U256::from(2), U256::from(20)),
+                (U256::from(3), U256::zero()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_calldata() {
+        //a selector followed by a word holding 5
+        let mut calldata = vec![0x12, 0x34, 0x56, 0x78];
+        calldata.extend_from_slice(&[0; 31]);
+        calldata.push(5);
+        let config = ExecutionConfig::default().with_context(&ExecutionContext {
+            tx: TxContext {
+                calldata,
+                ..TxContext::default()
+            },
+            ..ExecutionContext::default()
+        });
+        let run_with_calldata = |yul: &str| {
+            let program = compile(yul);
+            let result = execute(program.masm, &config).unwrap();
+            decode_stack(&program.outputs, &result.stack).unwrap()[0]
+        };
+        let load = |offset: u32| run_with_calldata(&format!("let x := calldataload({})", offset));
+
+        assert_eq!(
+            run_with_calldata("let size:u32 := calldatasize()"),
+            YulValue::U32(36)
+        );
+        assert_eq!(load(0), YulValue::U256(U256::from(0x12345678) << 224));
+        assert_eq!(load(4), YulValue::U256(U256::from(5)));
+        assert_eq!(load(20), YulValue::U256(U256::from(5) << 128));
+        assert_eq!(load(100), YulValue::U256(U256::zero()));
+        assert_eq!(load(u32::MAX - 3), YulValue::U256(U256::zero()));
+    }
+
+    #[test]
+    fn map_failures_to_source_lines() {
+        let yul = "let a := 0x100000000
+            let b := add(a, 1)
+            let c:u32 := u256_to_u32(b)
+            let d := 1";
+        let program = compile_program(
+            infer_types(&parse_yul_syntax_with_source_markers(yul)),
+            CompileOptions::default(),
+        );
+        assert_eq!(
+            program.source_map[&3],
+            [
+                StackSlot {
+                    name: Some("b".to_string()),
+                    yul_type: YulType::U256
+                },
+                StackSlot {
+                    name: Some("a".to_string()),
+                    yul_type: YulType::U256
+                },
+            ]
+        );
+        let failure = execute(program.masm, &ExecutionConfig::default()).unwrap_err();
+        assert_eq!(failure.source_line, Some(3));
+    }
+
+    //Adds each input to a session like the REPL does, inferring the types of each input along with
+    //the statements before it
+    fn session(inputs: &[&str]) -> YulSession {
+        let mut session = YulSession::new(CompileOptions::default());
+        let mut statements = vec![];
+        for input in inputs {
+            let parsed = parse_yul_syntax(input);
+            let typed = infer_types(&[statements.clone(), parsed.clone()].concat());
+            session.add_statements(&typed[statements.len()..]);
+            statements.extend(parsed);
+        }
+        session
+    }
+
+    #[test]
+    fn run_a_session_input_by_input() {
+        let earlier = session(&["let x := 1", "function f(a) -> b { b := add(a, 2) }"]);
+        let later = session(&[
+            "let x := 1",
+            "function f(a) -> b { b := add(a, 2) }",
+            "let y := f(x)",
+        ]);
+        assert!(later.program().contains("proc.f"));
+        let result = execute(later.program(), &ExecutionConfig::default()).unwrap();
+        assert_eq!(
+            decode_stack(&[YulType::U256, YulType::U256], &result.stack).unwrap(),
+            vec![YulValue::U256(U256::from(3)), YulValue::U256(U256::one())]
+        );
+        //the new input picks up from the stack that the earlier program left
+        let earlier_result = execute(earlier.program(), &ExecutionConfig::default()).unwrap();
+        let config = earlier_result
+            .stack
+            .iter()
+            .rev()
+            .fold(ExecutionConfig::default(), |config, value| {
+                config.with_stack_input(*value)
+            });
+        let result = execute(later.program_since(&earlier).unwrap(), &config).unwrap();
+        assert_eq!(
+            decode_stack(&[YulType::U256], &result.stack).unwrap(),
+            vec![YulValue::U256(U256::from(3))]
+        );
+    }
+
+    #[test]
+    fn session_variables_on_the_stack_and_in_memory() {
+        let names = ["a", "b", "c", "d", "e", "f"];
+        let inputs = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("let {} := {}", name, i + 10))
+            .collect::<Vec<_>>();
+        let session = session(&inputs.iter().map(String::as_str).collect::<Vec<_>>());
+        let variables = session.variables();
+        assert_eq!(variables.len(), names.len());
+        let addresses = variables
+            .iter()
+            .filter_map(|(_, location)| match location {
+                VariableLocation::Memory(address) => Some(*address),
+                VariableLocation::Stack(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(!addresses.is_empty());
+        assert!(addresses.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(matches!(variables[0].1, VariableLocation::Stack(0)));
+        assert_eq!(variables[0].0.identifier, "f");
+
+        let result = execute(session.program(), &ExecutionConfig::default()).unwrap();
+        for (typed_identifier, location) in variables {
+            let value = match location {
+                VariableLocation::Stack(offset) => {
+                    decode_stack(&[typed_identifier.yul_type], &result.stack[offset..]).unwrap()[0]
+                }
+                VariableLocation::Memory(address) => result
+                    .memory
+                    .read_value(address as u64, typed_identifier.yul_type),
+            };
+            let i = names
+                .iter()
+                .position(|name| *name == typed_identifier.identifier)
+                .unwrap();
+            assert_eq!(value, YulValue::U256(U256::from(i + 10)));
+        }
+    }
 }
@@ -17,6 +17,17 @@ const DEFAULT_TYPE: YulType = YulType::U256;
 
 //To see examples for each Expr, check out types.rs
 pub fn parse_yul_syntax(syntax: &str) -> Vec<Expr> {
+    parse_file(syntax, false)
+}
+
+//Like parse_yul_syntax, but every statement is preceded by a call to __source_line with the line
+//it starts on. Programs compiled from this AST keep track of the statement that is running, so
+//that errors can be traced back to the Yul source.
+pub fn parse_yul_syntax_with_source_markers(syntax: &str) -> Vec<Expr> {
+    parse_file(syntax, true)
+}
+
+fn parse_file(syntax: &str, source_markers: bool) -> Vec<Expr> {
     let file = IdentParser::parse(Rule::file, syntax)
         .expect("unsuccessful parse")
         .next()
@@ -27,7 +38,10 @@ pub fn parse_yul_syntax(syntax: &str) -> Vec<Expr> {
     for statement in file.clone().into_inner() {
         match statement.as_rule() {
             Rule::statement => {
-                expressions.push(parse_statement(statement));
+                if source_markers {
+                    expressions.extend(source_marker(&statement));
+                }
+                expressions.push(parse_statement(statement, source_markers));
             }
 
             Rule::object => {
@@ -36,7 +50,7 @@ pub fn parse_yul_syntax(syntax: &str) -> Vec<Expr> {
                 let object_name = parts.next().unwrap();
                 dbg!(&object_name);
                 let code = parts.next().unwrap();
-                expressions.push(parse_statement(code));
+                expressions.push(parse_statement(code, source_markers));
             }
 
             Rule::EOI => (),
@@ -51,17 +65,20 @@ pub fn parse_yul_syntax(syntax: &str) -> Vec<Expr> {
 
 //Parses a Yul statement. This function matches a grammar rule and return an Expr struct
 //which is later added into the Abstract Syntax Tree
-fn parse_statement(expression: Pair<Rule>) -> Expr {
+fn parse_statement(expression: Pair<Rule>, source_markers: bool) -> Expr {
     let inner = expression.into_inner().next().unwrap();
     match inner.as_rule() {
         //Rule is expr
         Rule::expr => parse_expression(inner),
 
         //Rule is block
-        Rule::block => Expr::Block(parse_block(inner)),
+        Rule::block => Expr::Block(parse_block(inner, source_markers)),
 
         // Rule is code
-        Rule::code => Expr::Block(parse_block(inner.into_inner().next().unwrap())),
+        Rule::code => Expr::Block(parse_block(
+            inner.into_inner().next().unwrap(),
+            source_markers,
+        )),
 
         //If the rule is a function definition, parse the function name, parameters, returns and then return an Expr
         Rule::function_definition => {
@@ -82,7 +99,7 @@ fn parse_statement(expression: Pair<Rule>) -> Expr {
                 function_name: function_name.to_string(),
                 params,
                 returns,
-                block: parse_block(block),
+                block: parse_block(block, source_markers),
             })
         }
 
@@ -106,7 +123,7 @@ fn parse_statement(expression: Pair<Rule>) -> Expr {
             let second_arg = inners.next().unwrap();
             Expr::IfStatement(ExprIfStatement {
                 first_expr: Box::new(parse_expression(first_arg)),
-                second_expr: Box::new(parse_block(second_arg)),
+                second_expr: Box::new(parse_block(second_arg, source_markers)),
             })
         }
 
@@ -118,9 +135,12 @@ fn parse_statement(expression: Pair<Rule>) -> Expr {
             let expr = parse_expression(parts.next().unwrap());
             for part in parts {
                 match part.as_rule() {
-                    Rule::case => cases.push(parse_case(part)),
+                    Rule::case => cases.push(parse_case(part, source_markers)),
                     Rule::default => {
-                        default_case = Some(parse_block(part.into_inner().next().unwrap()))
+                        default_case = Some(parse_block(
+                            part.into_inner().next().unwrap(),
+                            source_markers,
+                        ))
                     }
                     _ => unreachable!(),
                 }
@@ -142,7 +162,7 @@ fn parse_statement(expression: Pair<Rule>) -> Expr {
 
             Expr::Case(ExprCase {
                 literal: parse_literal(literal),
-                block: parse_block(block),
+                block: parse_block(block, source_markers),
             })
         }
 
@@ -155,10 +175,10 @@ fn parse_statement(expression: Pair<Rule>) -> Expr {
             let interior_block = parts.next().unwrap();
 
             Expr::ForLoop(ExprForLoop {
-                init_block: Box::new(parse_block(init_block)),
+                init_block: Box::new(parse_block(init_block, source_markers)),
                 conditional: Box::new(parse_expression(conditional)),
-                after_block: Box::new(parse_block(after_block)),
-                interior_block: Box::new(parse_block(interior_block)),
+                after_block: Box::new(parse_block(after_block, source_markers)),
+                interior_block: Box::new(parse_block(interior_block, source_markers)),
             })
         }
 
@@ -206,10 +226,10 @@ fn parse_identifier_list(rule: Pair<Rule>) -> Vec<Identifier> {
 }
 
 //Parses a case statement into an Expr
-fn parse_case(rule: Pair<Rule>) -> ExprCase {
+fn parse_case(rule: Pair<Rule>, source_markers: bool) -> ExprCase {
     let mut parts = rule.into_inner();
     let literal = parse_literal(parts.next().unwrap());
-    let block = parse_block(parts.next().unwrap());
+    let block = parse_block(parts.next().unwrap(), source_markers);
     ExprCase { block, literal }
 }
 
@@ -237,10 +257,102 @@ fn parse_typed_identifier_list(rule: Pair<Rule>) -> Vec<TypedIdentifier> {
 //Literals can be a number literal, string literal, true/false literal or a hex literal.
 //Literals can also have an optional type in Yul.
 fn parse_literal(literal: Pair<Rule>) -> ExprLiteral {
-    match parse_expression(literal.clone()) {
+    let annotation = literal
+        .clone()
+        .into_inner()
+        .nth(1)
+        .map(|type_name| YulType::from_annotation(type_name.as_str()));
+    let parsed_literal = match parse_expression(literal.clone()) {
         Expr::Literal(literal) => literal,
         _ => unreachable!("This should only parse literals {:?}", &literal),
+    };
+    match annotation {
+        Some(yul_type) => annotate_literal(parsed_literal, yul_type),
+        None => parsed_literal,
+    }
+}
+
+//Applies an explicit type to a literal, such as 1:u32. Numbers keep the type in inferred_type so that
+//type inference doesn't replace it, while the other literals only have one type they can be.
+fn annotate_literal(literal: ExprLiteral, yul_type: YulType) -> ExprLiteral {
+    match (literal, yul_type) {
+        (ExprLiteral::Number(ExprLiteralNumber { value, .. }), YulType::Bool) => {
+            if value > U256::one() {
+                panic!("{} can't be used as a bool literal", value);
+            }
+            ExprLiteral::Bool(value == U256::one())
+        }
+        (ExprLiteral::Number(ExprLiteralNumber { value, .. }), yul_type) => {
+            if yul_type.bits() < 256 && value >> yul_type.bits() != U256::zero() {
+                panic!("{} is too large to be a {} literal", value, yul_type);
+            }
+            ExprLiteral::Number(ExprLiteralNumber {
+                value,
+                inferred_type: Some(yul_type),
+            })
+        }
+        (literal @ ExprLiteral::Bool(_), YulType::Bool)
+        | (literal @ (ExprLiteral::String(_) | ExprLiteral::Hex(_)), YulType::U256) => literal,
+        (literal, yul_type) => panic!("{:?} can't be used as a {} literal", literal, yul_type),
+    }
+}
+
+//Parses the bytes of a string_literal or hex_literal rule, without any limit on their length.
+//Callers check that literals in expressions fit in a u256.
+fn parse_byte_string(rule: Pair<Rule>) -> Vec<u8> {
+    match rule.as_rule() {
+        Rule::string_literal => decode_string_literal(rule.into_inner().next().unwrap().as_str()),
+        Rule::hex_literal => {
+            // strip the hex prefix and the quotes around the digits
+            let raw = rule.as_str();
+            hex::decode(&raw[4..raw.len() - 1]).unwrap()
+        }
+        r => unreachable!("{:?} is not a byte string", r),
+    }
+}
+
+//Panics if a string or hex literal in an expression is longer than a u256
+fn check_byte_string_length(rule: &Pair<Rule>, bytes: &[u8]) {
+    if bytes.len() > 32 {
+        panic!(
+            "{} is {} bytes long, but string and hex literals can be at most 32 bytes",
+            rule.as_str(),
+            bytes.len()
+        );
+    }
+}
+
+//Decodes the escape sequences in the contents of a string literal, returning its bytes.
+//\u escapes are encoded as UTF-8, and \x escapes are a single byte.
+fn decode_string_literal(content: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next().unwrap() {
+            'b' => bytes.push(0x08),
+            'f' => bytes.push(0x0c),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            't' => bytes.push(b'\t'),
+            'u' => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let c = char::from_u32(u32::from_str_radix(&hex, 16).unwrap())
+                    .unwrap_or_else(|| panic!("\\u{} is not a valid unicode character", hex));
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            'x' => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+            }
+            //\", \\ and \/ are the escaped character itself
+            c => bytes.push(c as u8),
+        }
     }
+    bytes
 }
 
 //Function to parse grammar within an expression rule
@@ -253,20 +365,16 @@ fn parse_expression(expression: Pair<Rule>) -> Expr {
         }
         Rule::number_literal => parse_expression(expression),
         Rule::hex_number => {
-            // TODO: parse hex numbers
-            let initial = expression.as_str();
+            let initial = expression.as_str().trim_start_matches("0x");
             Expr::Literal(ExprLiteral::Number(ExprLiteralNumber {
                 inferred_type: None,
                 value: U256::from_str_radix(initial, 16).unwrap(),
             }))
         }
         Rule::hex_literal => {
-            // TODO: parse hex numbers
-            let initial = expression.as_str();
-            Expr::Literal(ExprLiteral::Number(ExprLiteralNumber {
-                inferred_type: None,
-                value: U256::from_str_radix(initial, 16).unwrap(),
-            }))
+            let bytes = parse_byte_string(expression.clone());
+            check_byte_string_length(&expression, &bytes);
+            Expr::Literal(ExprLiteral::Hex(bytes))
         }
         Rule::decimal_number => {
             let i = expression.as_str();
@@ -276,15 +384,16 @@ fn parse_expression(expression: Pair<Rule>) -> Expr {
             }))
         }
         Rule::string_literal => {
-            let content = expression.into_inner().next().unwrap();
-            Expr::Literal(ExprLiteral::String(content.as_str().to_string()))
+            let bytes = parse_byte_string(expression.clone());
+            check_byte_string_length(&expression, &bytes);
+            Expr::Literal(ExprLiteral::String(bytes))
         }
 
-        // //rule is a false literal
-        // Rule::false_literal => Expr::Bool(false),
+        //rule is a false literal
+        Rule::false_literal => Expr::Literal(ExprLiteral::Bool(false)),
 
-        // //rule is a true literal
-        // Rule::true_literal => Expr::Bool(true),
+        //rule is a true literal
+        Rule::true_literal => Expr::Literal(ExprLiteral::Bool(true)),
 
         //if the matched rule is an identifier
         Rule::identifier => parse_identifier(expression),
@@ -324,17 +433,41 @@ fn parse_identifier(identifier: Pair<Rule>) -> Expr {
 }
 
 //Parses a block into an Expr
-fn parse_block(expression: Pair<Rule>) -> ExprBlock {
+fn parse_block(expression: Pair<Rule>, source_markers: bool) -> ExprBlock {
     let mut exprs: Vec<Expr> = Vec::new();
     for statement in expression.into_inner() {
         if statement.clone().into_inner().next().is_some() {
-            exprs.push(parse_statement(statement));
+            if source_markers {
+                exprs.extend(source_marker(&statement));
+            }
+            exprs.push(parse_statement(statement, source_markers));
         }
     }
 
     ExprBlock { exprs }
 }
 
+//Returns a call to __source_line with the line the statement starts on. Function definitions
+//don't run where they're defined, so they aren't marked.
+fn source_marker(statement: &Pair<Rule>) -> Option<Expr> {
+    let inner = statement.clone().into_inner().next()?;
+    if inner.as_rule() == Rule::function_definition {
+        return None;
+    }
+    let (line, _) = statement.as_span().start_pos().line_col();
+    Some(Expr::FunctionCall(ExprFunctionCall {
+        function_name: "__source_line".to_string(),
+        exprs: Box::new(vec![Expr::Literal(ExprLiteral::Number(
+            ExprLiteralNumber {
+                value: U256::from(line),
+                inferred_type: Some(YulType::U32),
+            },
+        ))]),
+        inferred_return_types: vec![],
+        inferred_param_types: vec![],
+    }))
+}
+
 // TESTS
 #[cfg(test)]
 mod tests {
@@ -366,6 +499,31 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn parse_var_declaration_with_widths() {
+        insta::assert_snapshot!(parse_to_tree(
+            "let a:u8 := 200
+            let b:u64 := 1
+            let c:u128 := add(b, 2)
+            let d:address := 0x10
+            "
+        ));
+    }
+
+    #[test]
+    fn parse_u32_u256_conversions() {
+        insta::assert_snapshot!(parse_to_tree(
+            "function double(x) -> y {
+                y := add(x, x)
+            }
+            let a:u32 := 5
+            let b := add(a, 1)
+            let c:u32 := u256_to_u32_truncating(b)
+            let d := double(c)
+            "
+        ));
+    }
+
     #[test]
     fn parse_function_call() {
         insta::assert_snapshot!(parse_to_tree("add(1,2)"));
@@ -390,6 +548,42 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn parse_string_literal() {
+        insta::assert_snapshot!(parse_to_tree("let x := \"abc\\n\\u00e9\\x01\""));
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 32 bytes")]
+    fn parse_string_literal_too_long() {
+        parse_yul_syntax("let x := \"this string is longer than 32 bytes\"");
+    }
+
+    #[test]
+    fn parse_typed_and_hex_literals() {
+        insta::assert_snapshot!(parse_to_tree(
+            "let x := 0x1f:u32
+            let y := hex\"00ff\"
+            let z:bool := lt(5:u32, 7)"
+        ));
+    }
+
+    #[test]
+    fn data_sections_are_skipped() {
+        let with_data = parse_yul_syntax(
+            "object \"Contract\" {
+                code { let x := 1 }
+                data \"Table\" hex\"4123\"
+            }",
+        );
+        let without_data = parse_yul_syntax(
+            "object \"Contract\" {
+                code { let x := 1 }
+            }",
+        );
+        assert_eq!(with_data, without_data);
+    }
+
     #[test]
     fn parse_fibonnaci() {
         insta::assert_snapshot!(parse_to_tree(
@@ -0,0 +1,1013 @@
+use std::collections::{HashMap, HashSet};
+
+use primitive_types::U256;
+
+use crate::type_inference::{builtin_param_type, infer_types};
+use crate::types::*;
+use crate::utils::convert_bytes_to_u256;
+
+//Range analysis finds u256 variables that can never hold a value larger than a u32, such as loop
+//counters, memory offsets and small constants, and narrows them to u32. u256 operations cost
+//dozens of Miden instructions, while u32 operations are a single instruction.
+//
+//The analysis tracks an interval of the values each variable can hold at each point of the
+//program. Loops are analyzed until the intervals stop growing, using the loop condition to bound
+//the variables it compares. A variable is narrowed when every value assigned to it anywhere in
+//the program fits in a u32.
+//
+//If a narrowed variable is assigned an expression whose intermediate values don't fit in a u32,
+//computing that expression with u32 operations would wrap around differently than the u256
+//operations would. Those expressions are kept as u256 and converted with __u256_to_u32, which
+//checks that the value fits. Memory offsets that can't be proven to fit in a u32 are converted the
+//same way, instead of being truncated.
+
+//Infers the types of an untyped AST like infer_types, after narrowing the u256 variables that
+//range analysis proves always fit in a u32
+pub fn infer_types_with_narrowing(ast: &[Expr]) -> Vec<Expr> {
+    let mut analyzer = RangeAnalyzer::new(ast);
+    let mut env = Env::new();
+    analyzer.analyze_block(ast, &mut env);
+    let narrowed = analyzer.narrowable_variables();
+    let narrower = Narrower {
+        env: analyzer.global_env(&narrowed),
+        analyzer: &analyzer,
+        narrowed: &narrowed,
+    };
+    infer_types(&narrower.narrow_block(ast))
+}
+
+//An inclusive interval of the values an expression can evaluate to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Range {
+    pub lo: U256,
+    pub hi: U256,
+}
+
+impl Range {
+    pub fn constant(value: U256) -> Self {
+        Range {
+            lo: value,
+            hi: value,
+        }
+    }
+
+    pub fn full() -> Self {
+        Range {
+            lo: U256::zero(),
+            hi: U256::max_value(),
+        }
+    }
+
+    //Every value that a variable of the given type can hold
+    pub fn of_type(yul_type: YulType) -> Self {
+        match yul_type.bits() {
+            256 => Range::full(),
+            bits => Range {
+                lo: U256::zero(),
+                hi: (U256::one() << bits) - 1,
+            },
+        }
+    }
+
+    pub fn join(self, other: Range) -> Self {
+        Range {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    pub fn fits_u32(&self) -> bool {
+        self.hi <= U256::from(u32::MAX)
+    }
+}
+
+//The ranges of the variables at a point in the program
+type Env = HashMap<String, Range>;
+
+fn join_envs(a: &Env, b: &Env) -> Env {
+    let mut joined = a.clone();
+    for (identifier, range) in b {
+        joined
+            .entry(identifier.clone())
+            .and_modify(|r| *r = r.join(*range))
+            .or_insert(*range);
+    }
+    joined
+}
+
+//Everything that is known about a variable across the whole program
+#[derive(Clone, Copy, Debug)]
+struct Summary {
+    //The values that are assigned to the variable
+    range: Range,
+    //Whether every intermediate value of the expressions assigned to the variable fits in a u32
+    intermediates_fit: bool,
+}
+
+//Builtins that aren't computed on narrowed values. xor, shl, shr and mod have no u32 lowering,
+//u32 division fails on division by zero instead of returning zero, and and/or can have an operand
+//that doesn't fit in a u32. The variables used in their arguments aren't narrowed, and the values
+//computed with them are converted with __u256_to_u32 when they're assigned to a narrowed variable.
+const WIDE_BUILTINS: [&str; 7] = ["div", "mod", "and", "or", "xor", "shl", "shr"];
+
+//Loops are joined this many times before their variables are widened to the full range
+const LOOP_JOIN_ITERATIONS: u32 = 3;
+
+//After widening, the loop is analyzed this many more times to tighten the ranges again
+const LOOP_NARROWING_ITERATIONS: u32 = 2;
+
+struct RangeAnalyzer {
+    summaries: HashMap<String, Summary>,
+    //Declared types of variables, used for variables with no known range, such as parameters
+    types: HashMap<String, YulType>,
+    //Variables that can't be narrowed whatever their range is
+    excluded: HashSet<String>,
+    user_functions: HashSet<String>,
+    //Environments at break and continue statements of the loops being analyzed
+    breaks: Vec<Option<Env>>,
+    continues: Vec<Option<Env>>,
+    //Summaries are only recorded once the ranges of the loops around a statement are final
+    recording: bool,
+    intermediates_fit: bool,
+}
+
+impl RangeAnalyzer {
+    fn new(ast: &[Expr]) -> Self {
+        let mut user_functions = HashSet::new();
+        collect_function_names(ast, &mut user_functions);
+        RangeAnalyzer {
+            summaries: HashMap::new(),
+            types: HashMap::new(),
+            excluded: HashSet::new(),
+            user_functions,
+            breaks: vec![],
+            continues: vec![],
+            recording: true,
+            intermediates_fit: true,
+        }
+    }
+
+    //Returns the u256 variables whose values always fit in a u32
+    fn narrowable_variables(&self) -> HashSet<String> {
+        self.summaries
+            .iter()
+            .filter(|(identifier, summary)| {
+                self.types.get(*identifier) == Some(&YulType::U256)
+                    && !self.excluded.contains(*identifier)
+                    && summary.range.fits_u32()
+            })
+            .map(|(identifier, _)| identifier.clone())
+            .collect()
+    }
+
+    //Returns the range of every variable over the whole program, after narrowing
+    fn global_env(&self, narrowed: &HashSet<String>) -> Env {
+        let mut env: Env = self
+            .types
+            .iter()
+            .map(|(identifier, yul_type)| (identifier.clone(), Range::of_type(*yul_type)))
+            .collect();
+        for identifier in narrowed {
+            env.insert(identifier.clone(), self.summaries[identifier].range);
+        }
+        env
+    }
+
+    fn record(&mut self, identifier: &str, range: Range, intermediates_fit: bool) {
+        if !self.recording {
+            return;
+        }
+        self.summaries
+            .entry(identifier.to_string())
+            .and_modify(|summary| {
+                summary.range = summary.range.join(range);
+                summary.intermediates_fit &= intermediates_fit;
+            })
+            .or_insert(Summary {
+                range,
+                intermediates_fit,
+            });
+    }
+
+    fn declare(&mut self, typed_identifier: &TypedIdentifier) {
+        let declared_type = self
+            .types
+            .entry(typed_identifier.identifier.clone())
+            .or_insert(typed_identifier.yul_type);
+        //variables are tracked by name, so a name that is declared with different types is left alone
+        if *declared_type != typed_identifier.yul_type {
+            self.excluded.insert(typed_identifier.identifier.clone());
+        }
+    }
+
+    fn analyze_block(&mut self, exprs: &[Expr], env: &mut Env) {
+        for expr in exprs {
+            self.analyze_statement(expr, env);
+        }
+    }
+
+    fn analyze_statement(&mut self, expr: &Expr, env: &mut Env) {
+        match expr {
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs,
+            }) => {
+                for typed_identifier in typed_identifiers {
+                    self.declare(typed_identifier);
+                }
+                let ranges = match rhs {
+                    Some(rhs) => self.evaluate_rhs(rhs, typed_identifiers.len(), env),
+                    None => {
+                        self.intermediates_fit = true;
+                        vec![Range::constant(U256::zero()); typed_identifiers.len()]
+                    }
+                };
+                for (typed_identifier, range) in typed_identifiers.iter().zip(ranges) {
+                    self.assign(&typed_identifier.identifier, range, env);
+                }
+            }
+            Expr::Assignment(ExprAssignment {
+                identifiers, rhs, ..
+            }) => {
+                let ranges = self.evaluate_rhs(rhs, identifiers.len(), env);
+                for (identifier, range) in identifiers.iter().zip(ranges) {
+                    self.assign(identifier, range, env);
+                }
+            }
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => {
+                self.evaluate(first_expr, env);
+                let mut then_env = env.clone();
+                refine(first_expr, &mut then_env, self);
+                self.analyze_block(&second_expr.exprs, &mut then_env);
+                *env = join_envs(env, &then_env);
+            }
+            Expr::Switch(ExprSwitch {
+                default_case,
+                expr,
+                cases,
+                ..
+            }) => {
+                self.evaluate(expr, env);
+                let mut joined = match default_case {
+                    Some(_) => None,
+                    None => Some(env.clone()),
+                };
+                for block in cases
+                    .iter()
+                    .map(|case| &case.block)
+                    .chain(default_case.iter())
+                {
+                    let mut case_env = env.clone();
+                    self.analyze_block(&block.exprs, &mut case_env);
+                    joined = Some(match joined {
+                        Some(joined) => join_envs(&joined, &case_env),
+                        None => case_env,
+                    });
+                }
+                *env = joined.unwrap();
+            }
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => {
+                self.analyze_block(&init_block.exprs, env);
+                self.analyze_loop(
+                    Some(conditional),
+                    &interior_block.exprs,
+                    &after_block.exprs,
+                    env,
+                );
+            }
+            Expr::Repeat(ExprRepeat { interior_block, .. }) => {
+                self.analyze_loop(None, &interior_block.exprs, &[], env);
+            }
+            Expr::Block(ExprBlock { exprs }) => self.analyze_block(exprs, env),
+            Expr::FunctionDefinition(ExprFunctionDefinition {
+                params,
+                returns,
+                block,
+                ..
+            }) => {
+                //parameters and return variables are part of the function's signature, so they
+                //keep their types
+                let mut function_env = Env::new();
+                for typed_identifier in params.iter().chain(returns) {
+                    self.declare(typed_identifier);
+                    self.excluded.insert(typed_identifier.identifier.clone());
+                }
+                for typed_identifier in params {
+                    function_env.insert(
+                        typed_identifier.identifier.clone(),
+                        Range::of_type(typed_identifier.yul_type),
+                    );
+                }
+                for typed_identifier in returns {
+                    function_env.insert(
+                        typed_identifier.identifier.clone(),
+                        Range::constant(U256::zero()),
+                    );
+                }
+                self.analyze_block(&block.exprs, &mut function_env);
+            }
+            Expr::Break => {
+                if let Some(breaks) = self.breaks.last_mut() {
+                    *breaks = Some(breaks.take().map_or(env.clone(), |b| join_envs(&b, env)));
+                }
+            }
+            Expr::Continue => {
+                if let Some(continues) = self.continues.last_mut() {
+                    *continues = Some(continues.take().map_or(env.clone(), |c| join_envs(&c, env)));
+                }
+            }
+            expr => {
+                self.evaluate(expr, env);
+            }
+        }
+    }
+
+    fn assign(&mut self, identifier: &str, range: Range, env: &mut Env) {
+        let intermediates_fit = self.intermediates_fit;
+        self.record(identifier, range, intermediates_fit);
+        env.insert(identifier.to_string(), range);
+    }
+
+    //Evaluates the right hand side of a declaration or assignment, returning the range of each
+    //value it assigns. intermediates_fit is left set for the statement.
+    fn evaluate_rhs(&mut self, rhs: &Expr, values: usize, env: &Env) -> Vec<Range> {
+        self.intermediates_fit = true;
+        let range = self.evaluate(rhs, env);
+        if values == 1 {
+            vec![range]
+        } else {
+            //functions with multiple return values
+            vec![Range::full(); values]
+        }
+    }
+
+    //Analyzes a loop until the ranges of its variables stop changing, then analyzes it one last
+    //time with those ranges to record the values that are assigned in it
+    fn analyze_loop(
+        &mut self,
+        conditional: Option<&Expr>,
+        body: &[Expr],
+        after: &[Expr],
+        env: &mut Env,
+    ) {
+        let recording = self.recording;
+        self.recording = false;
+        let entry = env.clone();
+        let mut head = entry.clone();
+        let mut iteration = 0;
+        loop {
+            let (end, _) = self.analyze_loop_iteration(conditional, body, after, &head);
+            let next = join_envs(&entry, &end);
+            let next = join_envs(&head, &next);
+            if next == head {
+                break;
+            }
+            head = if iteration < LOOP_JOIN_ITERATIONS {
+                next
+            } else {
+                widen(&head, &next)
+            };
+            iteration += 1;
+        }
+        //the widened ranges can be tightened by going through the loop again
+        for _ in 0..LOOP_NARROWING_ITERATIONS {
+            let (end, _) = self.analyze_loop_iteration(conditional, body, after, &head);
+            head = join_envs(&entry, &end);
+        }
+        self.recording = recording;
+        let (end, breaks) = self.analyze_loop_iteration(conditional, body, after, &head);
+        let exit = join_envs(&head, &end);
+        *env = match breaks {
+            Some(breaks) => join_envs(&exit, &breaks),
+            None => exit,
+        };
+    }
+
+    //Analyzes one pass through a loop, starting with the ranges at the loop condition. Returns the
+    //ranges after the after block, and the ranges at any break statements.
+    fn analyze_loop_iteration(
+        &mut self,
+        conditional: Option<&Expr>,
+        body: &[Expr],
+        after: &[Expr],
+        head: &Env,
+    ) -> (Env, Option<Env>) {
+        let mut env = head.clone();
+        if let Some(conditional) = conditional {
+            self.evaluate(conditional, &env);
+            refine(conditional, &mut env, self);
+        }
+        self.breaks.push(None);
+        self.continues.push(None);
+        self.analyze_block(body, &mut env);
+        if let Some(continues) = self.continues.pop().unwrap() {
+            env = join_envs(&env, &continues);
+        }
+        self.analyze_block(after, &mut env);
+        (env, self.breaks.pop().unwrap())
+    }
+
+    //Returns the range of values that an expression can evaluate to. If any intermediate value
+    //doesn't fit in a u32, intermediates_fit is cleared.
+    fn evaluate(&mut self, expr: &Expr, env: &Env) -> Range {
+        let range = match expr {
+            Expr::Literal(literal) => literal_range(literal),
+            Expr::Variable(ExprVariableReference { identifier, .. }) => match env.get(identifier) {
+                Some(range) => *range,
+                None => self
+                    .types
+                    .get(identifier)
+                    .map(|yul_type| Range::of_type(*yul_type))
+                    .unwrap_or_else(Range::full),
+            },
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                ..
+            }) => {
+                let args = exprs
+                    .iter()
+                    .map(|expr| self.evaluate(expr, env))
+                    .collect::<Vec<_>>();
+                if self.user_functions.contains(function_name) {
+                    //values passed to functions have to keep the type of the function's
+                    //parameters
+                    for expr in exprs.iter() {
+                        if let Expr::Variable(ExprVariableReference { identifier, .. }) = expr {
+                            self.excluded.insert(identifier.clone());
+                        }
+                    }
+                    Range::full()
+                } else {
+                    if WIDE_BUILTINS.contains(&function_name.as_str()) {
+                        for expr in exprs.iter() {
+                            collect_variables(expr, &mut self.excluded);
+                        }
+                        self.intermediates_fit = false;
+                    }
+                    builtin_range(function_name, &args)
+                }
+            }
+            _ => Range::full(),
+        };
+        if !range.fits_u32() {
+            self.intermediates_fit = false;
+        }
+        range
+    }
+}
+
+//Widens the ranges that grew since the last iteration of a loop to every value, so that the
+//analysis of the loop finishes
+fn widen(head: &Env, next: &Env) -> Env {
+    next.iter()
+        .map(|(identifier, range)| {
+            let widened = match head.get(identifier) {
+                Some(old) if old == range => *range,
+                Some(old) => Range {
+                    lo: if range.lo < old.lo {
+                        U256::zero()
+                    } else {
+                        old.lo
+                    },
+                    hi: if range.hi > old.hi {
+                        U256::max_value()
+                    } else {
+                        old.hi
+                    },
+                },
+                None => Range::full(),
+            };
+            (identifier.clone(), widened)
+        })
+        .collect()
+}
+
+//Tightens the ranges of the variables in a condition, given that the condition is true.
+//Ex. inside of if lt(i, 10) { }, i is at most 9
+fn refine(condition: &Expr, env: &mut Env, analyzer: &mut RangeAnalyzer) {
+    let (function_name, exprs) = match condition {
+        Expr::FunctionCall(ExprFunctionCall {
+            function_name,
+            exprs,
+            ..
+        }) if exprs.len() == 2 => (function_name.as_str(), exprs),
+        _ => return,
+    };
+    let fit = analyzer.intermediates_fit;
+    let lhs = analyzer.evaluate(&exprs[0], env);
+    let rhs = analyzer.evaluate(&exprs[1], env);
+    analyzer.intermediates_fit = fit;
+    //(variable, bound) pairs where the variable is below the bound, and where it is above it
+    let (below, above) = match function_name {
+        "lt" => ((&exprs[0], rhs), (&exprs[1], lhs)),
+        "gt" => ((&exprs[1], lhs), (&exprs[0], rhs)),
+        _ => return,
+    };
+    if let (Expr::Variable(ExprVariableReference { identifier, .. }), bound) = below {
+        if let Some(range) = env.get_mut(identifier) {
+            if bound.hi > range.lo {
+                range.hi = range.hi.min(bound.hi - 1);
+            }
+        }
+    }
+    if let (Expr::Variable(ExprVariableReference { identifier, .. }), bound) = above {
+        if let Some(range) = env.get_mut(identifier) {
+            if bound.lo < range.hi {
+                range.lo = range.lo.max(bound.lo + 1);
+            }
+        }
+    }
+}
+
+fn literal_range(literal: &ExprLiteral) -> Range {
+    match literal {
+        ExprLiteral::Number(ExprLiteralNumber { value, .. }) => Range::constant(*value),
+        ExprLiteral::Bool(value) => Range::constant(U256::from(*value as u32)),
+        ExprLiteral::String(bytes) | ExprLiteral::Hex(bytes) => {
+            Range::constant(convert_bytes_to_u256(bytes))
+        }
+    }
+}
+
+//Returns the range of a builtin's result, given the ranges of its arguments. Builtins that aren't
+//listed here can return any value.
+fn builtin_range(function_name: &str, args: &[Range]) -> Range {
+    match (function_name, args) {
+        ("add", [a, b]) => match a.hi.checked_add(b.hi) {
+            Some(hi) => Range {
+                lo: a.lo + b.lo,
+                hi,
+            },
+            None => Range::full(),
+        },
+        //sub wraps around if b can be larger than a
+        ("sub", [a, b]) if a.lo >= b.hi => Range {
+            lo: a.lo - b.hi,
+            hi: a.hi - b.lo,
+        },
+        ("mul", [a, b]) => match a.hi.checked_mul(b.hi) {
+            Some(hi) => Range {
+                lo: a.lo * b.lo,
+                hi,
+            },
+            None => Range::full(),
+        },
+        //division by zero is zero in Yul
+        ("div", [a, b]) if b.lo.is_zero() => Range {
+            lo: U256::zero(),
+            hi: a.hi,
+        },
+        ("div", [a, b]) => Range {
+            lo: a.lo / b.hi,
+            hi: a.hi / b.lo,
+        },
+        ("mod", [a, b]) => Range {
+            lo: U256::zero(),
+            hi: a.hi.min(b.hi.saturating_sub(U256::one())),
+        },
+        ("and", [a, b]) => Range {
+            lo: U256::zero(),
+            hi: a.hi.min(b.hi),
+        },
+        ("or" | "xor", [a, b]) => Range {
+            lo: U256::zero(),
+            hi: fill_bits(a.hi.max(b.hi)),
+        },
+        ("shr", [shift, value]) if shift.hi < U256::from(256) => Range {
+            lo: value.lo >> shift.hi.as_u32(),
+            hi: value.hi >> shift.lo.as_u32(),
+        },
+        ("lt" | "gt" | "slt" | "sgt" | "eq" | "iszero", _) => Range {
+            lo: U256::zero(),
+            hi: U256::one(),
+        },
+        ("byte", _) => Range::of_type(YulType::U8),
+        ("calldatasize", _) => Range::of_type(YulType::U32),
+        ("u256_to_u32" | "__u256_to_u32", [a]) => Range {
+            lo: a.lo.min(U256::from(u32::MAX)),
+            hi: a.hi.min(U256::from(u32::MAX)),
+        },
+        ("u256_to_u32_truncating", [a]) if a.fits_u32() => *a,
+        ("u256_to_u32_truncating", _) => Range::of_type(YulType::U32),
+        ("u32_to_u256", [a]) => *a,
+        _ => Range::full(),
+    }
+}
+
+//Returns the smallest value of the form 2^n - 1 that is at least value
+fn fill_bits(value: U256) -> U256 {
+    match value.bits() {
+        256 => U256::max_value(),
+        bits => (U256::one() << bits) - 1,
+    }
+}
+
+//Adds the variables that an expression reads to identifiers
+fn collect_variables(expr: &Expr, identifiers: &mut HashSet<String>) {
+    match expr {
+        Expr::Variable(ExprVariableReference { identifier, .. }) => {
+            identifiers.insert(identifier.clone());
+        }
+        Expr::FunctionCall(ExprFunctionCall { exprs, .. }) => {
+            for expr in exprs.iter() {
+                collect_variables(expr, identifiers);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn collect_function_names(exprs: &[Expr], names: &mut HashSet<String>) {
+    for expr in exprs {
+        match expr {
+            Expr::FunctionDefinition(ExprFunctionDefinition {
+                function_name,
+                block,
+                ..
+            }) => {
+                names.insert(function_name.clone());
+                collect_function_names(&block.exprs, names);
+            }
+            Expr::Block(ExprBlock { exprs }) => collect_function_names(exprs, names),
+            _ => (),
+        }
+    }
+}
+
+//Rewrites the untyped AST with the narrowed variables typed as u32, adding __u256_to_u32
+//conversions where u32 values are needed but can't be proven to fit
+struct Narrower<'a> {
+    env: Env,
+    analyzer: &'a RangeAnalyzer,
+    narrowed: &'a HashSet<String>,
+}
+
+impl Narrower<'_> {
+    fn narrow_block(&self, exprs: &[Expr]) -> Vec<Expr> {
+        exprs.iter().map(|expr| self.narrow_expr(expr)).collect()
+    }
+
+    fn narrow_exprs_in_block(&self, block: &ExprBlock) -> ExprBlock {
+        ExprBlock {
+            exprs: self.narrow_block(&block.exprs),
+        }
+    }
+
+    //Returns the rhs of an assignment to a narrowed variable, converting it with __u256_to_u32 if
+    //computing it with u32 operations could overflow
+    fn narrow_rhs(&self, identifier: &str, rhs: &Expr) -> Expr {
+        let rhs = self.narrow_expr(rhs);
+        if self.narrowed.contains(identifier)
+            && !self.analyzer.summaries[identifier].intermediates_fit
+        {
+            u256_to_u32(rhs)
+        } else {
+            rhs
+        }
+    }
+
+    fn narrow_expr(&self, expr: &Expr) -> Expr {
+        match expr {
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs,
+            }) => Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers: typed_identifiers
+                    .iter()
+                    .map(|typed_identifier| TypedIdentifier {
+                        identifier: typed_identifier.identifier.clone(),
+                        yul_type: if self.narrowed.contains(&typed_identifier.identifier) {
+                            YulType::U32
+                        } else {
+                            typed_identifier.yul_type
+                        },
+                    })
+                    .collect(),
+                rhs: rhs.as_ref().map(|rhs| match typed_identifiers.as_slice() {
+                    [typed_identifier] => {
+                        Box::new(self.narrow_rhs(&typed_identifier.identifier, rhs))
+                    }
+                    _ => Box::new(self.narrow_expr(rhs)),
+                }),
+            }),
+            Expr::Assignment(ExprAssignment {
+                identifiers,
+                inferred_types,
+                rhs,
+            }) => Expr::Assignment(ExprAssignment {
+                identifiers: identifiers.clone(),
+                inferred_types: inferred_types.clone(),
+                rhs: Box::new(match identifiers.as_slice() {
+                    [identifier] => self.narrow_rhs(identifier, rhs),
+                    _ => self.narrow_expr(rhs),
+                }),
+            }),
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                inferred_return_types,
+                inferred_param_types,
+            }) => Expr::FunctionCall(ExprFunctionCall {
+                function_name: function_name.clone(),
+                exprs: Box::new(
+                    exprs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, expr)| {
+                            let narrowed_expr = self.narrow_expr(expr);
+                            if builtin_param_type(function_name, i) == Some(YulType::U32)
+                                && !matches!(expr, Expr::Literal(_))
+                                && !self.fits_u32(expr)
+                            {
+                                u256_to_u32(narrowed_expr)
+                            } else {
+                                narrowed_expr
+                            }
+                        })
+                        .collect(),
+                ),
+                inferred_return_types: inferred_return_types.clone(),
+                inferred_param_types: inferred_param_types.clone(),
+            }),
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => Expr::IfStatement(ExprIfStatement {
+                first_expr: Box::new(self.narrow_expr(first_expr)),
+                second_expr: Box::new(self.narrow_exprs_in_block(second_expr)),
+            }),
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => Expr::ForLoop(ExprForLoop {
+                init_block: Box::new(self.narrow_exprs_in_block(init_block)),
+                conditional: Box::new(self.narrow_expr(conditional)),
+                after_block: Box::new(self.narrow_exprs_in_block(after_block)),
+                interior_block: Box::new(self.narrow_exprs_in_block(interior_block)),
+            }),
+            Expr::Repeat(ExprRepeat {
+                interior_block,
+                iterations,
+            }) => Expr::Repeat(ExprRepeat {
+                interior_block: Box::new(self.narrow_exprs_in_block(interior_block)),
+                iterations: *iterations,
+            }),
+            Expr::Block(block) => Expr::Block(self.narrow_exprs_in_block(block)),
+            Expr::Switch(ExprSwitch {
+                default_case,
+                inferred_type,
+                expr,
+                cases,
+            }) => Expr::Switch(ExprSwitch {
+                default_case: default_case
+                    .as_ref()
+                    .map(|block| self.narrow_exprs_in_block(block)),
+                inferred_type: *inferred_type,
+                expr: Box::new(self.narrow_expr(expr)),
+                cases: cases
+                    .iter()
+                    .map(|case| ExprCase {
+                        literal: case.literal.clone(),
+                        block: self.narrow_exprs_in_block(&case.block),
+                    })
+                    .collect(),
+            }),
+            Expr::FunctionDefinition(ExprFunctionDefinition {
+                function_name,
+                params,
+                returns,
+                block,
+            }) => Expr::FunctionDefinition(ExprFunctionDefinition {
+                function_name: function_name.clone(),
+                params: params.clone(),
+                returns: returns.clone(),
+                block: self.narrow_exprs_in_block(block),
+            }),
+            expr => expr.clone(),
+        }
+    }
+
+    //Whether an expression always fits in a u32, using the ranges of the variables over the whole
+    //program
+    fn fits_u32(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Literal(literal) => literal_range(literal).fits_u32(),
+            Expr::Variable(ExprVariableReference { identifier, .. }) => self
+                .env
+                .get(identifier)
+                .map_or(false, |range| range.fits_u32()),
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                ..
+            }) if !self.analyzer.user_functions.contains(function_name) => {
+                let args = exprs
+                    .iter()
+                    .map(|expr| self.range(expr))
+                    .collect::<Vec<_>>();
+                builtin_range(function_name, &args).fits_u32()
+                    && exprs.iter().all(|expr| self.fits_u32(expr))
+            }
+            _ => false,
+        }
+    }
+
+    fn range(&self, expr: &Expr) -> Range {
+        match expr {
+            Expr::Literal(literal) => literal_range(literal),
+            Expr::Variable(ExprVariableReference { identifier, .. }) => self
+                .env
+                .get(identifier)
+                .copied()
+                .unwrap_or_else(Range::full),
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                ..
+            }) if !self.analyzer.user_functions.contains(function_name) => {
+                let args = exprs
+                    .iter()
+                    .map(|expr| self.range(expr))
+                    .collect::<Vec<_>>();
+                builtin_range(function_name, &args)
+            }
+            _ => Range::full(),
+        }
+    }
+}
+
+//Like u256_to_u32, under a name that only the narrower inserts
+fn u256_to_u32(expr: Expr) -> Expr {
+    Expr::FunctionCall(ExprFunctionCall {
+        function_name: "__u256_to_u32".to_string(),
+        exprs: Box::new(vec![expr]),
+        inferred_return_types: vec![],
+        inferred_param_types: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{decode_stack, execute, ExecutionConfig};
+    use crate::miden_generator::{compile_program, CompileOptions};
+    use crate::parser::parse_yul_syntax;
+
+    //Returns the type of each variable declaration, in the order they're declared
+    fn declared_types(yul: &str) -> Vec<(String, YulType)> {
+        fn collect(exprs: &[Expr], types: &mut Vec<(String, YulType)>) {
+            for expr in exprs {
+                match expr {
+                    Expr::DeclareVariable(op) => types.extend(
+                        op.typed_identifiers
+                            .iter()
+                            .map(|ti| (ti.identifier.clone(), ti.yul_type)),
+                    ),
+                    Expr::ForLoop(op) => {
+                        collect(&op.init_block.exprs, types);
+                        collect(&op.interior_block.exprs, types);
+                    }
+                    Expr::Block(block) => collect(&block.exprs, types),
+                    _ => (),
+                }
+            }
+        }
+        let ast = infer_types_with_narrowing(&parse_yul_syntax(yul));
+        let mut types = vec![];
+        collect(&ast, &mut types);
+        types
+    }
+
+    #[test]
+    fn narrows_loop_counters() {
+        assert_eq!(
+            declared_types(
+                "let total := 0
+                for { let i := 0 } lt(i, 0x100) { i := add(i, 0x20) } {
+                    total := add(total, mload(i))
+                }"
+            ),
+            vec![
+                ("total".to_string(), YulType::U256),
+                ("i".to_string(), YulType::U32),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_values_that_can_wrap_around() {
+        assert_eq!(
+            declared_types(
+                "let x := 5
+                let y := sub(x, 10)
+                let z := mul(x, 0x100000000)"
+            ),
+            vec![
+                ("x".to_string(), YulType::U32),
+                ("y".to_string(), YulType::U256),
+                ("z".to_string(), YulType::U256),
+            ]
+        );
+    }
+
+    #[test]
+    fn narrowed_mstore_values_fill_the_whole_slot() {
+        let yul = "mstore(0, 0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff)
+            let v := 5
+            mstore(0, v)
+            let y := mload(0)";
+        assert_eq!(declared_types(yul)[0], ("v".to_string(), YulType::U32));
+        let program = compile_program(
+            infer_types_with_narrowing(&parse_yul_syntax(yul)),
+            CompileOptions::default(),
+        );
+        let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+        assert_eq!(
+            decode_stack(&program.outputs, &result.stack).unwrap()[0],
+            YulValue::U256(U256::from(5))
+        );
+    }
+
+    //Runs the program at -O0 and at -O2 and returns both stacks as u256 values
+    fn run_unnarrowed_and_narrowed(yul: &str) -> (Vec<U256>, Vec<U256>) {
+        let run = |ast: Vec<Expr>, inline_threshold: usize| {
+            let program = compile_program(
+                ast,
+                CompileOptions {
+                    inline_threshold,
+                    ..CompileOptions::default()
+                },
+            );
+            let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+            decode_stack(&program.outputs, &result.stack)
+                .unwrap()
+                .iter()
+                .map(YulValue::to_u256)
+                .collect::<Vec<_>>()
+        };
+        let ast = parse_yul_syntax(yul);
+        (
+            run(infer_types(&ast), 0),
+            run(
+                infer_types_with_narrowing(&ast),
+                CompileOptions::default().inline_threshold,
+            ),
+        )
+    }
+
+    #[test]
+    fn operands_of_wide_builtins_stay_u256() {
+        assert_eq!(
+            declared_types(
+                "let x := 10
+                let y := div(x, 3)"
+            ),
+            vec![
+                ("x".to_string(), YulType::U256),
+                ("y".to_string(), YulType::U32),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_builtins_compute_the_same_values_when_narrowing() {
+        for (yul, expected) in [
+            ("let x := 0x1234 let y := and(x, 0xff)", 0x34),
+            ("let x := 0x1234 let y := or(x, 0xff)", 0x12ff),
+            ("let x := 0x1234 let y := xor(x, 0xff)", 0x12cb),
+            ("let x := 0x1234 let y := shl(4, x)", 0x12340),
+            ("let x := 0x1234 let y := shr(4, x)", 0x123),
+        ] {
+            let (unnarrowed, narrowed) = run_unnarrowed_and_narrowed(yul);
+            assert_eq!(unnarrowed, narrowed, "{}", yul);
+            assert_eq!(
+                narrowed,
+                vec![U256::from(expected), U256::from(0x1234)],
+                "{}",
+                yul
+            );
+        }
+    }
+
+    #[test]
+    fn u32_division_is_integer_division() {
+        let yul = "let x:u32 := 10
+            let y:u32 := div(x, 3)
+            let z:u32 := and(x, 6)
+            let w:u32 := or(x, 5)";
+        let (unnarrowed, narrowed) = run_unnarrowed_and_narrowed(yul);
+        assert_eq!(unnarrowed, narrowed);
+        assert_eq!(narrowed, [15, 2, 3, 10].map(U256::from).to_vec());
+    }
+}
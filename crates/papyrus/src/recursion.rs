@@ -0,0 +1,905 @@
+use std::collections::{HashMap, HashSet};
+
+use primitive_types::U256;
+
+use crate::ast_optimization::called_functions;
+use crate::types::*;
+
+//Miden procs are inlined where they're executed, so a proc can't call itself, directly or through
+//other procs. Functions that are recursive are lowered to a loop instead, which keeps the
+//variables of each call in a frame in memory, so that the call depth doesn't have to be known at
+//compile time. Functions that aren't recursive are left alone and become regular procs.
+//
+//The bodies of a group of functions that call each other are split into numbered blocks, at every
+//branch and at every call to a function in the group. The loop runs the block numbered pc, and
+//each block ends by setting pc to the block that runs next:
+//
+//function f(param_0) -> return_0 {
+//    __callee_store(2, param_0)         //the argument goes in the frame of the call
+//    __callee_store(0, 4294967295)      //the block to continue at once f returns, none here
+//    __frame_push(4)
+//    let pc := 0                        //the first block of f
+//    for { } lt(pc, 4294967295) { } {
+//        switch pc
+//        case 0 { ... }
+//        case 1 { ... }
+//    }
+//    return_0 := __callee_load(3)       //the return value is left in the popped frame
+//}
+//
+//A call to a function in the group stores the arguments and the block to continue at in a new
+//frame, pushes it and jumps to the first block of the function. Returning jumps to the block saved
+//in the frame and pops it, and the block the caller continues at copies the return values out of
+//the popped frame. Every frame starts with that block number and the frame pointer to restore,
+//followed by the params, the return variables and then the other variables of the function.
+
+//Frames start with the block to continue at and the frame pointer of the caller
+const FRAME_HEADER_SIZE: u32 = 2;
+const CONTINUE_AT_SLOT: u32 = 0;
+
+//The block number that ends the loop, once the outermost call returns
+const EXIT_BLOCK: u32 = u32::MAX;
+
+const PC: &str = "pc";
+
+//Lowers the recursive functions of an AST whose nested functions have been hoisted
+pub fn lower_recursive_functions(ast: Vec<Expr>) -> Vec<Expr> {
+    let functions = ast
+        .iter()
+        .filter_map(|expr| match expr {
+            Expr::FunctionDefinition(op) => Some((op.function_name.clone(), op.clone())),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let mut lowered = HashMap::new();
+    for group in recursive_groups(&functions) {
+        //the size of a frame is only known once the function has been lowered, and calls need
+        //it to push the frame, so the group is lowered once to find the sizes and again to use
+        //them
+        let frame_sizes = group.iter().map(|name| (name.clone(), 0)).collect();
+        let mut state_machine = StateMachine::new(&functions, &group, &frame_sizes);
+        state_machine.lower();
+        let frame_sizes = state_machine.frame_sizes();
+        let mut state_machine = StateMachine::new(&functions, &group, &frame_sizes);
+        state_machine.lower();
+        for name in &group {
+            lowered.insert(name.clone(), state_machine.wrapper(&functions[name]));
+        }
+    }
+    ast.into_iter()
+        .map(|expr| match expr {
+            Expr::FunctionDefinition(op) if lowered.contains_key(&op.function_name) => {
+                Expr::FunctionDefinition(lowered.remove(&op.function_name).unwrap())
+            }
+            expr => expr,
+        })
+        .collect()
+}
+
+//Returns the groups of functions that call each other, which are lowered together
+fn recursive_groups(functions: &HashMap<String, ExprFunctionDefinition>) -> Vec<Vec<String>> {
+    let reachable = functions
+        .keys()
+        .map(|name| (name.clone(), reachable_functions(name, functions)))
+        .collect::<HashMap<_, _>>();
+    let mut names = functions.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    let mut groups: Vec<Vec<String>> = vec![];
+    for name in &names {
+        if !reachable[name].contains(name) || groups.iter().any(|group| group.contains(name)) {
+            continue;
+        }
+        groups.push(
+            names
+                .iter()
+                .filter(|other| {
+                    reachable[name].contains(*other) && reachable[*other].contains(name)
+                })
+                .cloned()
+                .collect(),
+        );
+    }
+    groups
+}
+
+//Returns the user functions that can be called while the function runs
+fn reachable_functions(
+    name: &str,
+    functions: &HashMap<String, ExprFunctionDefinition>,
+) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut to_visit = vec![name.to_string()];
+    while let Some(name) = to_visit.pop() {
+        for callee in called_functions(&functions[&name].block.exprs) {
+            if functions.contains_key(&callee) && reachable.insert(callee.clone()) {
+                to_visit.push(callee);
+            }
+        }
+    }
+    reachable
+}
+
+//Where the variables of a function are in its frame
+#[derive(Clone)]
+struct FrameLayout {
+    slots: HashMap<TypedIdentifier, u32>,
+    size: u32,
+}
+
+impl FrameLayout {
+    //The params and return variables come first, so that callers know where they are
+    fn new(function: &ExprFunctionDefinition) -> Self {
+        let mut layout = FrameLayout {
+            slots: HashMap::new(),
+            size: FRAME_HEADER_SIZE,
+        };
+        for typed_identifier in function.params.iter().chain(&function.returns) {
+            layout.slot(typed_identifier);
+        }
+        layout
+    }
+
+    fn slot(&mut self, typed_identifier: &TypedIdentifier) -> u32 {
+        if let Some(slot) = self.slots.get(typed_identifier) {
+            return *slot;
+        }
+        let slot = self.size;
+        self.size += typed_identifier.yul_type.miden_memory_addresses();
+        self.slots.insert(typed_identifier.clone(), slot);
+        slot
+    }
+}
+
+struct StateMachine<'a> {
+    functions: &'a HashMap<String, ExprFunctionDefinition>,
+    group: &'a [String],
+    frame_sizes: &'a HashMap<String, u32>,
+    blocks: Vec<Vec<Expr>>,
+    current_block: u32,
+    entry_blocks: HashMap<String, u32>,
+    layouts: HashMap<String, FrameLayout>,
+    //the function that is being lowered
+    layout: FrameLayout,
+    return_block: u32,
+    //the blocks that continue and break jump to in each enclosing loop
+    loops: Vec<(u32, u32)>,
+    temporaries: u32,
+}
+
+impl<'a> StateMachine<'a> {
+    fn new(
+        functions: &'a HashMap<String, ExprFunctionDefinition>,
+        group: &'a [String],
+        frame_sizes: &'a HashMap<String, u32>,
+    ) -> Self {
+        StateMachine {
+            functions,
+            group,
+            frame_sizes,
+            blocks: vec![],
+            current_block: 0,
+            entry_blocks: HashMap::new(),
+            layouts: HashMap::new(),
+            layout: FrameLayout {
+                slots: HashMap::new(),
+                size: FRAME_HEADER_SIZE,
+            },
+            return_block: 0,
+            loops: vec![],
+            temporaries: 0,
+        }
+    }
+
+    fn lower(&mut self) {
+        for name in self.group {
+            let entry_block = self.new_block();
+            self.entry_blocks.insert(name.clone(), entry_block);
+        }
+        for name in self.group {
+            let function = self.functions[name].clone();
+            self.layout = FrameLayout::new(&function);
+            self.return_block = self.new_block();
+            self.current_block = self.entry_blocks[name];
+            //return variables start out as zero, but the frame may hold values of an older call
+            for return_ident in &function.returns {
+                let slot = self.layout.slot(return_ident);
+                self.emit(frame_store(
+                    "__frame_store",
+                    slot,
+                    zero(return_ident.yul_type),
+                    return_ident.yul_type,
+                ));
+            }
+            self.lower_block(function.block.exprs);
+            self.jump(self.return_block);
+
+            self.current_block = self.return_block;
+            self.emit(set_pc(frame_load(
+                "__frame_load",
+                CONTINUE_AT_SLOT,
+                YulType::U32,
+            )));
+            self.emit(call("__frame_pop", vec![], vec![], vec![]));
+            self.layouts.insert(name.clone(), self.layout.clone());
+        }
+    }
+
+    fn frame_sizes(&self) -> HashMap<String, u32> {
+        self.layouts
+            .iter()
+            .map(|(name, layout)| (name.clone(), layout.size))
+            .collect()
+    }
+
+    //Returns the function that callers outside the group call, which runs the loop until the
+    //outermost call returns
+    fn wrapper(&self, function: &ExprFunctionDefinition) -> ExprFunctionDefinition {
+        let layout = FrameLayout::new(function);
+        let rename = |typed_identifiers: &[TypedIdentifier], prefix: &str| {
+            typed_identifiers
+                .iter()
+                .enumerate()
+                .map(|(i, typed_identifier)| TypedIdentifier {
+                    identifier: format!("{}_{}", prefix, i),
+                    yul_type: typed_identifier.yul_type,
+                })
+                .collect::<Vec<_>>()
+        };
+        let params = rename(&function.params, "param");
+        let returns = rename(&function.returns, "return");
+
+        let mut exprs = vec![];
+        for (param, wrapper_param) in function.params.iter().zip(&params) {
+            exprs.push(frame_store(
+                "__callee_store",
+                layout.slots[param],
+                variable(wrapper_param),
+                param.yul_type,
+            ));
+        }
+        exprs.push(frame_store(
+            "__callee_store",
+            CONTINUE_AT_SLOT,
+            u32_literal(EXIT_BLOCK),
+            YulType::U32,
+        ));
+        exprs.push(call(
+            "__frame_push",
+            vec![u32_literal(self.frame_sizes[&function.function_name])],
+            vec![Some(YulType::U32)],
+            vec![],
+        ));
+        let pc = pc();
+        exprs.push(Expr::DeclareVariable(ExprDeclareVariable {
+            typed_identifiers: vec![pc.clone()],
+            rhs: Some(Box::new(u32_literal(
+                self.entry_blocks[&function.function_name],
+            ))),
+        }));
+        let cases = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| ExprCase {
+                literal: ExprLiteral::Number(ExprLiteralNumber {
+                    value: U256::from(i),
+                    inferred_type: Some(YulType::U32),
+                }),
+                block: ExprBlock {
+                    exprs: block.clone(),
+                },
+            })
+            .collect();
+        exprs.push(Expr::ForLoop(ExprForLoop {
+            init_block: Box::new(ExprBlock { exprs: vec![] }),
+            conditional: Box::new(call(
+                "lt",
+                vec![variable(&pc), u32_literal(EXIT_BLOCK)],
+                vec![Some(YulType::U32), Some(YulType::U32)],
+                vec![Some(YulType::Bool)],
+            )),
+            after_block: Box::new(ExprBlock { exprs: vec![] }),
+            interior_block: Box::new(ExprBlock {
+                exprs: vec![Expr::Switch(ExprSwitch {
+                    default_case: None,
+                    inferred_type: Some(YulType::U32),
+                    expr: Box::new(variable(&pc)),
+                    cases,
+                })],
+            }),
+        }));
+        for (return_ident, wrapper_return) in function.returns.iter().zip(&returns) {
+            exprs.push(Expr::Assignment(ExprAssignment {
+                identifiers: vec![wrapper_return.identifier.clone()],
+                inferred_types: vec![Some(wrapper_return.yul_type)],
+                rhs: Box::new(frame_load(
+                    "__callee_load",
+                    layout.slots[return_ident],
+                    return_ident.yul_type,
+                )),
+            }));
+        }
+        ExprFunctionDefinition {
+            function_name: function.function_name.clone(),
+            params,
+            returns,
+            block: ExprBlock { exprs },
+        }
+    }
+
+    fn new_block(&mut self) -> u32 {
+        self.blocks.push(vec![]);
+        self.blocks.len() as u32 - 1
+    }
+
+    fn emit(&mut self, expr: Expr) {
+        self.blocks[self.current_block as usize].push(expr);
+    }
+
+    fn jump(&mut self, block: u32) {
+        self.emit(set_pc(u32_literal(block)));
+    }
+
+    //Ends the current block by jumping to then_block if the condition is true, and to
+    //else_block otherwise
+    fn branch(&mut self, condition: Expr, then_block: u32, else_block: u32) {
+        self.jump(else_block);
+        self.emit(Expr::IfStatement(ExprIfStatement {
+            first_expr: Box::new(condition),
+            second_expr: Box::new(ExprBlock {
+                exprs: vec![set_pc(u32_literal(then_block))],
+            }),
+        }));
+    }
+
+    fn temporary(&mut self, yul_type: YulType) -> TypedIdentifier {
+        self.temporaries += 1;
+        TypedIdentifier {
+            identifier: format!("temporary_{}", self.temporaries),
+            yul_type,
+        }
+    }
+
+    fn lower_block(&mut self, exprs: Vec<Expr>) {
+        for expr in exprs {
+            self.lower_statement(expr);
+        }
+    }
+
+    fn lower_statement(&mut self, expr: Expr) {
+        match expr {
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs: None,
+            }) => {
+                for typed_identifier in typed_identifiers {
+                    let slot = self.layout.slot(&typed_identifier);
+                    self.emit(frame_store(
+                        "__frame_store",
+                        slot,
+                        zero(typed_identifier.yul_type),
+                        typed_identifier.yul_type,
+                    ));
+                }
+            }
+            Expr::DeclareVariable(ExprDeclareVariable {
+                typed_identifiers,
+                rhs: Some(rhs),
+            }) => self.lower_assignment(typed_identifiers, *rhs),
+            Expr::Assignment(ExprAssignment {
+                identifiers,
+                inferred_types,
+                rhs,
+            }) => {
+                let typed_identifiers = identifiers
+                    .into_iter()
+                    .zip(inferred_types)
+                    .map(|(identifier, yul_type)| TypedIdentifier {
+                        identifier,
+                        yul_type: yul_type.expect("assignments are typed"),
+                    })
+                    .collect();
+                self.lower_assignment(typed_identifiers, *rhs)
+            }
+            Expr::FunctionCall(op) if self.group.contains(&op.function_name) => {
+                self.lower_call(op);
+            }
+            Expr::FunctionCall(op) => {
+                let returns = self
+                    .functions
+                    .get(&op.function_name)
+                    .map_or(vec![], |function| function.returns.clone());
+                let op = self.lower_args(op);
+                if returns.is_empty() {
+                    self.emit(Expr::FunctionCall(op));
+                } else {
+                    //the return values aren't used, but they have to be tracked on the stack
+                    let typed_identifiers = returns
+                        .iter()
+                        .map(|return_ident| self.temporary(return_ident.yul_type))
+                        .collect();
+                    self.emit(Expr::DeclareVariable(ExprDeclareVariable {
+                        typed_identifiers,
+                        rhs: Some(Box::new(Expr::FunctionCall(op))),
+                    }));
+                }
+            }
+            Expr::IfStatement(ExprIfStatement {
+                first_expr,
+                second_expr,
+            }) => {
+                let condition = self.lower_expr(*first_expr);
+                let then_block = self.new_block();
+                let end_block = self.new_block();
+                self.branch(condition, then_block, end_block);
+                self.current_block = then_block;
+                self.lower_block(second_expr.exprs);
+                self.jump(end_block);
+                self.current_block = end_block;
+            }
+            Expr::ForLoop(ExprForLoop {
+                init_block,
+                conditional,
+                after_block,
+                interior_block,
+            }) => {
+                self.lower_block(init_block.exprs);
+                let condition_block = self.new_block();
+                let body_block = self.new_block();
+                let after_block_number = self.new_block();
+                let end_block = self.new_block();
+                self.jump(condition_block);
+
+                self.current_block = condition_block;
+                let condition = self.lower_expr(*conditional);
+                self.branch(condition, body_block, end_block);
+
+                self.loops.push((after_block_number, end_block));
+                self.current_block = body_block;
+                self.lower_block(interior_block.exprs);
+                self.jump(after_block_number);
+                self.current_block = after_block_number;
+                self.lower_block(after_block.exprs);
+                self.jump(condition_block);
+                self.loops.pop();
+                self.current_block = end_block;
+            }
+            //cases jump to their block if the value matches, and the default case (or the end of
+            //the switch) is where the jump goes otherwise
+            Expr::Switch(ExprSwitch {
+                default_case,
+                inferred_type,
+                expr,
+                cases,
+            }) => {
+                let yul_type = inferred_type.unwrap_or(YulType::U256);
+                let value = self.lower_expr(*expr);
+                let value = self.spill(value, yul_type);
+                let end_block = self.new_block();
+                let default_block = default_case.as_ref().map(|_| self.new_block());
+                self.jump(default_block.unwrap_or(end_block));
+                let mut case_blocks = vec![];
+                for case in &cases {
+                    let case_block = self.new_block();
+                    self.emit(Expr::IfStatement(ExprIfStatement {
+                        first_expr: Box::new(call(
+                            "eq",
+                            vec![value.clone(), Expr::Literal(case.literal.clone())],
+                            vec![Some(yul_type), Some(yul_type)],
+                            vec![Some(YulType::Bool)],
+                        )),
+                        second_expr: Box::new(ExprBlock {
+                            exprs: vec![set_pc(u32_literal(case_block))],
+                        }),
+                    }));
+                    case_blocks.push(case_block);
+                }
+                let blocks = cases
+                    .into_iter()
+                    .map(|case| case.block)
+                    .zip(case_blocks)
+                    .chain(default_case.zip(default_block));
+                for (block, block_number) in blocks {
+                    self.current_block = block_number;
+                    self.lower_block(block.exprs);
+                    self.jump(end_block);
+                }
+                self.current_block = end_block;
+            }
+            Expr::Block(ExprBlock { exprs }) => self.lower_block(exprs),
+            Expr::Break => {
+                let (_, end_block) = *self.loops.last().expect("break outside of a loop");
+                self.jump(end_block);
+                //anything after it in the block can't run
+                self.current_block = self.new_block();
+            }
+            Expr::Continue => {
+                let (after_block, _) = *self.loops.last().expect("continue outside of a loop");
+                self.jump(after_block);
+                self.current_block = self.new_block();
+            }
+            Expr::Leave => {
+                self.jump(self.return_block);
+                self.current_block = self.new_block();
+            }
+            //the value of an expression statement isn't used
+            Expr::Literal(_) | Expr::Variable(_) => (),
+            Expr::Repeat(_) => panic!("repeat loops can't be used in recursive functions"),
+            Expr::FunctionDefinition(_) | Expr::Case(_) => {
+                unreachable!("functions are hoisted before recursion is lowered")
+            }
+        }
+    }
+
+    fn lower_assignment(&mut self, typed_identifiers: Vec<TypedIdentifier>, rhs: Expr) {
+        match rhs {
+            Expr::FunctionCall(op) if self.group.contains(&op.function_name) => {
+                let callee = self.functions[&op.function_name].clone();
+                self.lower_call(op);
+                let callee_layout = FrameLayout::new(&callee);
+                for (typed_identifier, return_ident) in
+                    typed_identifiers.iter().zip(&callee.returns)
+                {
+                    let value = frame_load(
+                        "__callee_load",
+                        callee_layout.slots[return_ident],
+                        return_ident.yul_type,
+                    );
+                    let slot = self.layout.slot(typed_identifier);
+                    self.emit(frame_store(
+                        "__frame_store",
+                        slot,
+                        value,
+                        typed_identifier.yul_type,
+                    ));
+                }
+            }
+            //the values a proc returns are on the stack until they're stored
+            Expr::FunctionCall(op) if typed_identifiers.len() > 1 => {
+                let op = self.lower_args(op);
+                let temporaries = typed_identifiers
+                    .iter()
+                    .map(|typed_identifier| self.temporary(typed_identifier.yul_type))
+                    .collect::<Vec<_>>();
+                self.emit(Expr::DeclareVariable(ExprDeclareVariable {
+                    typed_identifiers: temporaries.clone(),
+                    rhs: Some(Box::new(Expr::FunctionCall(op))),
+                }));
+                for (typed_identifier, temporary) in typed_identifiers.iter().zip(&temporaries) {
+                    let slot = self.layout.slot(typed_identifier);
+                    self.emit(frame_store(
+                        "__frame_store",
+                        slot,
+                        variable(temporary),
+                        typed_identifier.yul_type,
+                    ));
+                }
+            }
+            rhs => {
+                let value = self.lower_expr(rhs);
+                let typed_identifier = &typed_identifiers[0];
+                let slot = self.layout.slot(typed_identifier);
+                self.emit(frame_store(
+                    "__frame_store",
+                    slot,
+                    value,
+                    typed_identifier.yul_type,
+                ));
+            }
+        }
+    }
+
+    //Stores the arguments in a new frame, pushes it and jumps to the function. The current block
+    //ends, and the code after the call goes in the block the function returns to.
+    fn lower_call(&mut self, op: ExprFunctionCall) {
+        let callee = self.functions[&op.function_name].clone();
+        let callee_layout = FrameLayout::new(&callee);
+        let args = self.lower_args(op).exprs;
+        //arguments are evaluated from right to left
+        for (param, arg) in callee.params.iter().zip(*args).rev() {
+            self.emit(frame_store(
+                "__callee_store",
+                callee_layout.slots[param],
+                arg,
+                param.yul_type,
+            ));
+        }
+        let continue_block = self.new_block();
+        self.emit(frame_store(
+            "__callee_store",
+            CONTINUE_AT_SLOT,
+            u32_literal(continue_block),
+            YulType::U32,
+        ));
+        self.emit(call(
+            "__frame_push",
+            vec![u32_literal(self.frame_sizes[&callee.function_name])],
+            vec![Some(YulType::U32)],
+            vec![],
+        ));
+        self.jump(self.entry_blocks[&callee.function_name]);
+        self.current_block = continue_block;
+    }
+
+    //Lowers the arguments of a call. They're evaluated from right to left, and the arguments that
+    //were evaluated before a call to a function in the group are stored in the frame, since the
+    //call continues in another block.
+    fn lower_args(&mut self, op: ExprFunctionCall) -> ExprFunctionCall {
+        let mut args = vec![];
+        for (expr, yul_type) in (*op.exprs)
+            .into_iter()
+            .zip(
+                op.inferred_param_types
+                    .iter()
+                    .chain(std::iter::repeat(&None)),
+            )
+            .rev()
+        {
+            if self.calls_group(&expr) {
+                for (arg, arg_type) in args.iter_mut() {
+                    if !is_frame_value(arg) {
+                        *arg = self.spill(arg.clone(), *arg_type);
+                    }
+                }
+            }
+            let arg = self.lower_expr(expr);
+            let arg_type = arg
+                .get_inferred_type()
+                .or(*yul_type)
+                .unwrap_or(YulType::U256);
+            args.push((arg, arg_type));
+        }
+        ExprFunctionCall {
+            function_name: op.function_name,
+            exprs: Box::new(args.into_iter().rev().map(|(arg, _)| arg).collect()),
+            inferred_return_types: op.inferred_return_types,
+            inferred_param_types: op.inferred_param_types,
+        }
+    }
+
+    fn lower_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Variable(ExprVariableReference {
+                identifier,
+                inferred_type,
+            }) => {
+                let typed_identifier = TypedIdentifier {
+                    identifier,
+                    yul_type: inferred_type.expect("variables are typed"),
+                };
+                let slot = self.layout.slot(&typed_identifier);
+                frame_load("__frame_load", slot, typed_identifier.yul_type)
+            }
+            Expr::FunctionCall(op) if self.group.contains(&op.function_name) => {
+                let callee = self.functions[&op.function_name].clone();
+                let return_ident = callee
+                    .returns
+                    .first()
+                    .unwrap_or_else(|| panic!("{} doesn't return a value", callee.function_name));
+                self.lower_call(op);
+                //the return value is copied out of the popped frame before another call can
+                //reuse it
+                let value = frame_load(
+                    "__callee_load",
+                    FrameLayout::new(&callee).slots[return_ident],
+                    return_ident.yul_type,
+                );
+                self.spill(value, return_ident.yul_type)
+            }
+            Expr::FunctionCall(op) => Expr::FunctionCall(self.lower_args(op)),
+            expr => expr,
+        }
+    }
+
+    //Stores a value in a new slot of the frame, returning the expression that loads it
+    fn spill(&mut self, value: Expr, yul_type: YulType) -> Expr {
+        let temporary = self.temporary(yul_type);
+        let slot = self.layout.slot(&temporary);
+        self.emit(frame_store("__frame_store", slot, value, yul_type));
+        frame_load("__frame_load", slot, yul_type)
+    }
+
+    fn calls_group(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::FunctionCall(op) => {
+                self.group.contains(&op.function_name)
+                    || op.exprs.iter().any(|expr| self.calls_group(expr))
+            }
+            _ => false,
+        }
+    }
+}
+
+//Values that can be evaluated after a call without changing the result
+fn is_frame_value(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::FunctionCall(op) => op.function_name == "__frame_load",
+        _ => false,
+    }
+}
+
+fn call(
+    function_name: &str,
+    exprs: Vec<Expr>,
+    inferred_param_types: Vec<Option<YulType>>,
+    inferred_return_types: Vec<Option<YulType>>,
+) -> Expr {
+    Expr::FunctionCall(ExprFunctionCall {
+        function_name: function_name.to_string(),
+        exprs: Box::new(exprs),
+        inferred_return_types,
+        inferred_param_types,
+    })
+}
+
+fn frame_load(builtin: &str, slot: u32, yul_type: YulType) -> Expr {
+    call(
+        builtin,
+        vec![u32_literal(slot)],
+        vec![Some(YulType::U32)],
+        vec![Some(yul_type)],
+    )
+}
+
+fn frame_store(builtin: &str, slot: u32, value: Expr, yul_type: YulType) -> Expr {
+    call(
+        builtin,
+        vec![u32_literal(slot), value],
+        vec![Some(YulType::U32), Some(yul_type)],
+        vec![],
+    )
+}
+
+fn pc() -> TypedIdentifier {
+    TypedIdentifier {
+        identifier: PC.to_string(),
+        yul_type: YulType::U32,
+    }
+}
+
+fn set_pc(value: Expr) -> Expr {
+    Expr::Assignment(ExprAssignment {
+        identifiers: vec![PC.to_string()],
+        inferred_types: vec![Some(YulType::U32)],
+        rhs: Box::new(value),
+    })
+}
+
+fn variable(typed_identifier: &TypedIdentifier) -> Expr {
+    Expr::Variable(ExprVariableReference {
+        identifier: typed_identifier.identifier.clone(),
+        inferred_type: Some(typed_identifier.yul_type),
+    })
+}
+
+fn u32_literal(value: u32) -> Expr {
+    Expr::Literal(ExprLiteral::Number(ExprLiteralNumber {
+        value: U256::from(value),
+        inferred_type: Some(YulType::U32),
+    }))
+}
+
+fn zero(yul_type: YulType) -> Expr {
+    Expr::Literal(ExprLiteral::Number(ExprLiteralNumber {
+        value: U256::zero(),
+        inferred_type: Some(yul_type),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{decode_stack, execute, ExecutionConfig};
+    use crate::miden_generator::{compile_program, CompileOptions};
+    use crate::parser::parse_yul_syntax;
+    use crate::type_inference::infer_types;
+
+    fn lower(yul: &str) -> String {
+        let ast = infer_types(&parse_yul_syntax(yul));
+        expressions_to_tree(&lower_recursive_functions(ast))
+    }
+
+    //Compiles and executes the program, returning the values of its variables
+    fn run(yul: &str) -> Vec<U256> {
+        let program = compile_program(
+            infer_types(&parse_yul_syntax(yul)),
+            CompileOptions::default(),
+        );
+        let result = execute(program.masm, &ExecutionConfig::default()).unwrap();
+        decode_stack(&program.outputs, &result.stack)
+            .unwrap()
+            .iter()
+            .map(YulValue::to_u256)
+            .collect()
+    }
+
+    #[test]
+    fn lowers_recursive_functions_to_frames() {
+        let tree = lower(
+            "function count(n) -> r {
+                if gt(n, 0) {
+                    r := add(count(sub(n, 1)), 1)
+                }
+            }
+            function double(x) -> y {
+                y := mul(x, 2)
+            }
+            let r := double(count(3))",
+        );
+        assert!(tree.contains("function definition - count"));
+        assert!(tree.contains("__frame_push(u32): "));
+        assert!(tree.contains("__frame_pop(): "));
+        //the recursive call is replaced by a jump to the start of count
+        assert_eq!(tree.matches("count(u256): u256").count(), 1);
+        //functions that aren't recursive are left alone
+        assert!(tree.contains("mul(u256, u256): u256"));
+    }
+
+    #[test]
+    fn runs_recursive_functions() {
+        let values = run("function count(n) -> r {
+                if gt(n, 0) {
+                    r := add(count(sub(n, 1)), 1)
+                }
+            }
+            let r := count(3)");
+        assert_eq!(values[0], U256::from(3));
+    }
+
+    #[test]
+    fn runs_mutually_recursive_functions() {
+        let values = run("function is_even(n) -> r {
+                r := 1
+                if gt(n, 0) {
+                    r := is_odd(sub(n, 1))
+                }
+            }
+            function is_odd(n) -> r {
+                if gt(n, 0) {
+                    r := is_even(sub(n, 1))
+                }
+            }
+            let a := is_even(4)
+            let b := is_odd(4)
+            let c := is_odd(7)");
+        assert_eq!(values[..3], [1, 0, 1].map(U256::from));
+    }
+
+    #[test]
+    fn runs_recursive_calls_inside_arguments() {
+        //the value of n and the result of the first call have to stay in the frame while the
+        //other call runs
+        let values = run("function fib(n) -> r {
+                r := n
+                if gt(n, 1) {
+                    r := add(fib(sub(n, 1)), fib(sub(n, 2)))
+                }
+            }
+            function sum(n) -> r {
+                if gt(n, 0) {
+                    r := add(mul(n, 2), sum(sub(n, 1)))
+                }
+            }
+            let a := fib(6)
+            let b := sum(3)");
+        //b is on top
+        assert_eq!(values[..2], [12, 8].map(U256::from));
+    }
+
+    #[test]
+    fn runs_loops_and_switches_in_recursive_functions() {
+        let values = run("function total(n) -> r {
+                for { let i := 0 } lt(i, n) { i := add(i, 1) } {
+                    r := add(r, i)
+                }
+                switch n
+                case 0 { }
+                default {
+                    r := add(r, total(sub(n, 1)))
+                }
+            }
+            let t := total(3)");
+        assert_eq!(values[0], U256::from(4));
+    }
+}
@@ -8,9 +8,39 @@ pub fn infer_types(ast: &Vec<Expr>) -> Vec<Expr> {
     inferrer.walk_ast(ast)
 }
 
+//The types of a user function's params and return values
+#[derive(Clone)]
+struct FunctionSignature {
+    params: Vec<YulType>,
+    returns: Vec<YulType>,
+}
+
+//Functions can be called anywhere in the block they're defined in, even before their definition,
+//so their signatures are collected before the block is walked
+fn collect_function_signatures(ast: &[Expr], functions: &mut HashMap<String, FunctionSignature>) {
+    for expr in ast {
+        if let Expr::FunctionDefinition(ExprFunctionDefinition {
+            function_name,
+            params,
+            returns,
+            ..
+        }) = expr
+        {
+            functions.insert(
+                function_name.clone(),
+                FunctionSignature {
+                    params: params.iter().map(|param| param.yul_type).collect(),
+                    returns: returns.iter().map(|ret| ret.yul_type).collect(),
+                },
+            );
+        }
+    }
+}
+
 #[derive(Default)]
 struct TypeInferrer {
     scoped_variables: HashMap<String, YulType>,
+    functions: HashMap<String, FunctionSignature>,
     expected_types: Vec<Option<YulType>>,
     evaluated_types: Vec<Option<YulType>>,
 }
@@ -18,10 +48,13 @@ struct TypeInferrer {
 //FIXME: needs comments still
 impl TypeInferrer {
     fn walk_ast(&mut self, ast: &Vec<Expr>) -> Vec<Expr> {
+        let functions_old = self.functions.clone();
+        collect_function_signatures(ast, &mut self.functions);
         let mut new_ast = vec![];
         for expr in ast {
             new_ast.push(self.walk_expr(expr.clone()));
         }
+        self.functions = functions_old;
         new_ast
     }
 
@@ -37,27 +70,46 @@ impl TypeInferrer {
                 inferred_param_types: _,
                 exprs,
             }) => {
-                // TODO: this is dumb, but inferring that the params to the function should be the
-                // same type as the first return value. Will work for now, for our math and boolean
-                // ops
+                if let Some(signature) = self.functions.get(&function_name).cloned() {
+                    return self.walk_user_function_call(function_name, *exprs, &signature);
+                }
                 let expected_types = self.expected_types.clone();
+                let is_comparison = COMPARISON_BUILTINS.contains(&function_name.as_str());
+                let operand_type =
+                    self.operand_type(&function_name, &exprs, &expected_types, is_comparison);
+                let operand_types = match operand_type {
+                    Some(operand_type) => vec![Some(operand_type)],
+                    None => expected_types.clone(),
+                };
                 let mut param_types = Vec::new();
                 let expressions = exprs
                     .iter()
                     .enumerate()
                     .map(|(i, expr)| {
-                        if i == 0 && function_name == "mstore" || function_name == "mload" {
-                            self.expected_types = vec![Some(YulType::U32)];
+                        self.expected_types = operand_types.clone();
+                        let param_type = builtin_param_type(&function_name, i);
+                        if let Some(param_type) = param_type {
+                            self.expected_types = vec![Some(param_type)];
                         }
-                        // self.expected_types = vec![expected_param_type.clone()];
                         let new_expr = self.walk_expr(expr.clone());
+                        //u32 arguments are widened when the builtin operates on u256 values
+                        let new_expr = match param_type.or(operand_type) {
+                            Some(yul_type) => self.widen(new_expr, yul_type),
+                            None => new_expr,
+                        };
                         param_types.append(&mut self.evaluated_types.clone());
                         self.expected_types = expected_types.clone();
                         new_expr
                     })
                     .collect();
 
-                let inferred_return_types = self.expected_types.clone();
+                let inferred_return_types = match builtin_return_type(&function_name) {
+                    Some(return_type) => vec![Some(return_type)],
+                    None => match operand_type {
+                        Some(operand_type) if !is_comparison => vec![Some(operand_type)],
+                        _ => self.expected_types.clone(),
+                    },
+                };
                 self.evaluated_types = inferred_return_types.clone();
                 Expr::FunctionCall(ExprFunctionCall {
                     function_name,
@@ -238,20 +290,189 @@ impl TypeInferrer {
         };
     }
 
+    //Returns the type shared by the operands of a builtin, ignoring the params that have a type of
+    //their own (see builtin_param_type). Comparison operands take the type of the operands whose
+    //type is already known (ie. a variable), so that lt(i, 10) compares a u32 i against a u32
+    //literal. Other builtins also take the expected type into account, so add(i, 1) is a u256
+    //addition when a u256 is expected. If the types have different widths, the widest one is used.
+    fn operand_type(
+        &self,
+        function_name: &str,
+        exprs: &[Expr],
+        expected_types: &[Option<YulType>],
+        is_comparison: bool,
+    ) -> Option<YulType> {
+        let known_type = exprs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| builtin_param_type(function_name, *i).is_none())
+            .filter_map(|(_, expr)| self.peek_type(expr))
+            .reduce(YulType::common_type);
+        let expected_type = expected_types
+            .first()
+            .copied()
+            .flatten()
+            .filter(|yul_type| *yul_type != YulType::Bool);
+        match (known_type, expected_type) {
+            (Some(known_type), Some(expected_type)) if !is_comparison => {
+                Some(known_type.common_type(expected_type))
+            }
+            (known_type, expected_type) => known_type.or(expected_type),
+        }
+    }
+
+    //Arguments to user functions take the types of the function's params. u32 arguments are
+    //widened to u256 params, but u256 arguments are never narrowed implicitly.
+    fn walk_user_function_call(
+        &mut self,
+        function_name: String,
+        exprs: Vec<Expr>,
+        signature: &FunctionSignature,
+    ) -> Expr {
+        if exprs.len() != signature.params.len() {
+            panic!(
+                "{} takes {} arguments, but {} were given",
+                function_name,
+                signature.params.len(),
+                exprs.len()
+            );
+        }
+        let expected_types = self.expected_types.clone();
+        let expressions = exprs
+            .into_iter()
+            .zip(&signature.params)
+            .map(|(expr, param_type)| {
+                self.expected_types = vec![Some(*param_type)];
+                let expr = self.walk_expr(expr);
+                let expr = self.widen(expr, *param_type);
+                if let Some(arg_type) = self.evaluated_types.first().copied().flatten() {
+                    if arg_type.miden_stack_width() > param_type.miden_stack_width() {
+                        panic!(
+                            "can't pass a {} to a {} param of {}, convert it with u256_to_u32",
+                            arg_type, param_type, function_name
+                        );
+                    }
+                }
+                expr
+            })
+            .collect();
+        self.expected_types = expected_types;
+        let inferred_return_types = signature
+            .returns
+            .iter()
+            .map(|yul_type| Some(*yul_type))
+            .collect::<Vec<_>>();
+        self.evaluated_types = inferred_return_types.clone();
+        Expr::FunctionCall(ExprFunctionCall {
+            function_name,
+            exprs: Box::new(expressions),
+            inferred_return_types,
+            inferred_param_types: signature
+                .params
+                .iter()
+                .map(|yul_type| Some(*yul_type))
+                .collect(),
+        })
+    }
+
+    //Wraps an expression that evaluated to a u32 in a u32_to_u256 call, if a u256 is needed
+    fn widen(&mut self, expr: Expr, yul_type: YulType) -> Expr {
+        if yul_type != YulType::U256 || self.evaluated_types != [Some(YulType::U32)] {
+            return expr;
+        }
+        self.evaluated_types = vec![Some(YulType::U256)];
+        Expr::FunctionCall(ExprFunctionCall {
+            function_name: "u32_to_u256".to_string(),
+            exprs: Box::new(vec![expr]),
+            inferred_param_types: vec![Some(YulType::U32)],
+            inferred_return_types: vec![Some(YulType::U256)],
+        })
+    }
+
+    //Finds the type of an expression without walking it, if it can be known up front
+    fn peek_type(&self, expr: &Expr) -> Option<YulType> {
+        match expr {
+            Expr::Variable(ExprVariableReference { identifier, .. }) => {
+                self.scoped_variables.get(identifier).cloned()
+            }
+            Expr::Literal(ExprLiteral::Bool(_)) => Some(YulType::Bool),
+            Expr::Literal(ExprLiteral::String(_) | ExprLiteral::Hex(_)) => Some(YulType::U256),
+            Expr::Literal(ExprLiteral::Number(ExprLiteralNumber { inferred_type, .. })) => {
+                *inferred_type
+            }
+            Expr::FunctionCall(ExprFunctionCall {
+                function_name,
+                exprs,
+                ..
+            }) => match self.functions.get(function_name) {
+                Some(signature) => signature.returns.first().copied(),
+                None => builtin_return_type(function_name)
+                    .or_else(|| exprs.iter().find_map(|expr| self.peek_type(expr))),
+            },
+            _ => None,
+        }
+    }
+
     fn infer_literal(&mut self, literal: ExprLiteral) -> ExprLiteral {
         match literal {
             ExprLiteral::Number(ExprLiteralNumber {
                 value,
-                inferred_type: _,
+                inferred_type,
             }) => {
-                let inferred_type = *self.expected_types.first().unwrap_or(&Some(YulType::U256));
+                //literals that were annotated with a type, like 1:u32, keep it
+                let inferred_type =
+                    inferred_type.or(*self.expected_types.first().unwrap_or(&Some(YulType::U256)));
                 self.evaluated_types = vec![inferred_type];
                 ExprLiteral::Number(ExprLiteralNumber {
                     value,
                     inferred_type,
                 })
             }
-            x => x,
+            ExprLiteral::Bool(value) => {
+                self.evaluated_types = vec![Some(YulType::Bool)];
+                ExprLiteral::Bool(value)
+            }
+            //string literals are left aligned, so they only fit in a u256
+            ExprLiteral::String(bytes) => {
+                self.evaluated_types = vec![Some(YulType::U256)];
+                ExprLiteral::String(bytes)
+            }
+            ExprLiteral::Hex(bytes) => {
+                self.evaluated_types = vec![Some(YulType::U256)];
+                ExprLiteral::Hex(bytes)
+            }
         }
     }
 }
+
+//Builtins that return a bool
+const COMPARISON_BUILTINS: [&str; 4] = ["lt", "gt", "eq", "iszero"];
+
+//Returns the type of a builtin's return value, if it doesn't follow the type that is expected
+//where the builtin is called
+fn builtin_return_type(function_name: &str) -> Option<YulType> {
+    match function_name {
+        "lt" | "gt" | "eq" | "iszero" => Some(YulType::Bool),
+        "u256_to_u32" | "__u256_to_u32" | "u256_to_u32_truncating" => Some(YulType::U32),
+        "u32_to_u256" => Some(YulType::U256),
+        "calldatasize" => Some(YulType::U32),
+        _ => None,
+    }
+}
+
+//Returns the type of a builtin parameter that doesn't follow the type of the function's return
+//value. Memory offsets and lengths are always u32, and log topics and storage keys and values
+//are always u256.
+pub(crate) fn builtin_param_type(function_name: &str, index: usize) -> Option<YulType> {
+    match (function_name, index) {
+        ("u256_to_u32" | "__u256_to_u32" | "u256_to_u32_truncating", 0) => Some(YulType::U256),
+        ("u32_to_u256", 0) => Some(YulType::U32),
+        ("mstore", 0) | ("mload", _) => Some(YulType::U32),
+        ("return" | "revert", 0 | 1) => Some(YulType::U32),
+        ("log0" | "log1" | "log2" | "log3" | "log4", 0 | 1) => Some(YulType::U32),
+        ("log1" | "log2" | "log3" | "log4", _) => Some(YulType::U256),
+        ("calldataload" | "__source_line", 0) => Some(YulType::U32),
+        ("sload", 0) | ("sstore", _) => Some(YulType::U256),
+        _ => None,
+    }
+}
@@ -33,7 +33,8 @@ impl Expr {
     pub fn get_inferred_type(&self) -> Option<YulType> {
         match self {
             Expr::Literal(ExprLiteral::Number(x)) => x.inferred_type,
-            Expr::Literal(_) => todo!(),
+            Expr::Literal(ExprLiteral::Bool(_)) => Some(YulType::Bool),
+            Expr::Literal(ExprLiteral::String(_) | ExprLiteral::Hex(_)) => Some(YulType::U256),
             Expr::FunctionCall(x) => *x.inferred_return_types.first().unwrap(),
             Expr::Variable(x) => x.inferred_type,
             _ => unreachable!(),
@@ -41,54 +42,103 @@ impl Expr {
     }
 }
 
-//Type to represent u32 and u256 integers
+//Type to represent unsigned integers of different widths, addresses and booleans
 #[derive(Hash, Clone, PartialEq, Eq, Debug, Copy)]
 pub enum YulType {
+    U8,
     U32,
+    U64,
+    U128,
     U256,
+    Address,
+    Bool,
 }
 
 impl YulType {
-    //Converts a string representation of u32 or u256 to a YulType
+    //Converts a type annotation, such as u32 or address, to a YulType
     pub fn from_annotation(annotation: &str) -> Self {
         match annotation {
+            "u8" => Self::U8,
             "u32" => Self::U32,
+            "u64" => Self::U64,
+            "u128" => Self::U128,
             "u256" => Self::U256,
-            _ => panic!(),
+            "address" => Self::Address,
+            "bool" => Self::Bool,
+            _ => panic!("unknown type {}", annotation),
         }
     }
 
     //Returns the stack width that the uint occupies in the Miden VM
     //Miden stack elements can occupy 32bits, a u32 number will occupy one element, where a u256 number will occupy 8 elements
-    //u256 numbers are stored in little endian
+    //Numbers wider than 32 bits are split into u32 limbs, with the most significant limb on top of the stack. This is the
+    //same layout that Miden's u64 standard library uses.
+    //A u8 is a single element that is always below 256, and a bool is a single element that is either 0 or 1
+    //Addresses are 160 bits, but are laid out like a u256 so that they can be used with u256 operations
     pub fn miden_stack_width(&self) -> u32 {
         match self {
-            Self::U32 => 1,
-            Self::U256 => 8,
+            Self::U8 | Self::U32 | Self::Bool => 1,
+            Self::U64 => 2,
+            Self::U128 => 4,
+            Self::U256 | Self::Address => 8,
         }
     }
 
     //Returns the amount of addresses in memory the number occupies.
     //Memory addresses in Miden are four words (one word is 32bits).
     //A u32 number will take up one word, meaning that it will only need one address.
+    //A u64 number is saved as two u32 values, so it uses two addresses. A u128 number fills one address completely.
     //A u256 number will take up 8 words, meaning that it will need two addresses.
     pub fn miden_memory_addresses(&self) -> u32 {
         match self {
-            Self::U32 => 1,
-            Self::U256 => 2,
+            Self::U8 | Self::U32 | Self::Bool | Self::U128 => 1,
+            Self::U64 | Self::U256 | Self::Address => 2,
+        }
+    }
+
+    //Returns the number of bits in a value of this type
+    pub fn bits(&self) -> u32 {
+        match self {
+            Self::Bool => 1,
+            Self::U8 => 8,
+            Self::U32 => 32,
+            Self::U64 => 64,
+            Self::U128 => 128,
+            Self::Address => 160,
+            Self::U256 => 256,
+        }
+    }
+
+    //Returns the type that two values are converted to when they are used together, such as in add(x, y).
+    //This is the wider of the two types, so that no value is truncated.
+    pub fn common_type(self, other: YulType) -> YulType {
+        let rank = |yul_type: &YulType| match yul_type {
+            Self::Bool => 0,
+            Self::U8 => 1,
+            Self::U32 => 2,
+            Self::U64 => 3,
+            Self::U128 => 4,
+            Self::Address => 5,
+            Self::U256 => 6,
+        };
+        if rank(&other) > rank(&self) {
+            other
+        } else {
+            self
         }
     }
 }
 
 //Enum to represent Yul literals
 //Number Literal Ex: 123456789 (this gets converted into an ExprLiteralNumber)
-//String Literal Ex: "hello world"
+//String Literal Ex: "hello world" (stored as bytes, after escape sequences are decoded)
 //True/False Literal Ex: True
-//TODO: Hex literals
+//Hex Literal Ex: hex"00ff" (the bytes it contains, like a string literal)
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ExprLiteral {
     Number(ExprLiteralNumber),
-    String(String),
+    String(Vec<u8>),
+    Hex(Vec<u8>),
     Bool(bool),
 }
 
@@ -256,7 +306,8 @@ impl Expr {
                         .map(|yt| yt.to_string())
                         .unwrap_or_else(|| "unknown".to_string())
                 )),
-                ExprLiteral::String(x) => tree.add_leaf(x),
+                ExprLiteral::String(x) => tree.add_leaf(&format!("\"{}\"", x.escape_ascii())),
+                ExprLiteral::Hex(x) => tree.add_leaf(&format!("hex\"{}\"", hex::encode(x))),
                 ExprLiteral::Bool(x) => tree.add_leaf(&x.to_string()),
             },
             //--------------------------------------------------------
@@ -478,8 +529,61 @@ impl fmt::Display for YulType {
     //Print YulType
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            YulType::U8 => write!(f, "u8"),
             YulType::U32 => write!(f, "u32"),
+            YulType::U64 => write!(f, "u64"),
+            YulType::U128 => write!(f, "u128"),
             YulType::U256 => write!(f, "u256"),
+            YulType::Address => write!(f, "address"),
+            YulType::Bool => write!(f, "bool"),
+        }
+    }
+}
+
+//A value of one of the Yul types, such as one decoded from the Miden stack after execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YulValue {
+    U8(u8),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    U256(U256),
+    Address(U256),
+    Bool(bool),
+}
+
+impl YulValue {
+    pub fn yul_type(&self) -> YulType {
+        match self {
+            YulValue::U8(_) => YulType::U8,
+            YulValue::U32(_) => YulType::U32,
+            YulValue::U64(_) => YulType::U64,
+            YulValue::U128(_) => YulType::U128,
+            YulValue::U256(_) => YulType::U256,
+            YulValue::Address(_) => YulType::Address,
+            YulValue::Bool(_) => YulType::Bool,
+        }
+    }
+
+    //Widens the value to a u256, the way Yul sees every value
+    pub fn to_u256(&self) -> U256 {
+        match self {
+            YulValue::U8(value) => U256::from(*value),
+            YulValue::U32(value) => U256::from(*value),
+            YulValue::U64(value) => U256::from(*value),
+            YulValue::U128(value) => U256::from(*value),
+            YulValue::U256(value) | YulValue::Address(value) => *value,
+            YulValue::Bool(value) => U256::from(*value as u8),
+        }
+    }
+}
+
+impl fmt::Display for YulValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YulValue::Address(value) => write!(f, "{:#x}", value),
+            YulValue::Bool(value) => write!(f, "{}", value),
+            _ => write!(f, "{}", self.to_u256()),
         }
     }
 }
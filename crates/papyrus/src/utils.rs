@@ -53,6 +53,14 @@ pub fn join_u32s_to_u256(x: Vec<u32>) -> U256 {
     U256::from_big_endian(&u256_bytes)
 }
 
+//Converts the bytes of a string literal into a u256 the way Yul does, with the string left aligned
+//so that its first byte is the most significant byte of the value
+pub fn convert_bytes_to_u256(bytes: &[u8]) -> U256 {
+    let mut word = [0u8; 32];
+    word[..bytes.len()].copy_from_slice(bytes);
+    U256::from_big_endian(&word)
+}
+
 pub fn load_all_procs() -> String {
     static MASM_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/miden_asm");
     MASM_DIR